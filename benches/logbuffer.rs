@@ -0,0 +1,74 @@
+//! Measures how long it takes to draw a LogBuffer holding a
+//! large scrollback. Run with `cargo bench --bench logbuffer`.
+
+use std::time::{Duration, Instant};
+
+use chrono::Utc;
+use eesh::{
+    tui::{widget::Timestamps, RenderContext},
+    ContextualWidget, LogBuffer,
+};
+use ratatui::{buffer::Buffer, layout::Rect, text::Line};
+
+const LINES: usize = 100_000;
+const FRAMES: u32 = 200;
+
+fn main() {
+    let mut lb = LogBuffer::new(LINES, chrono_tz::UTC);
+
+    let started = Instant::now();
+    for i in 0..LINES {
+        // Mix short lines with ones long enough to wrap.
+        let text = if i % 7 == 0 {
+            format!("line {i}: {}", "lorem ipsum dolor sit amet ".repeat(8))
+        } else {
+            format!("line {i}: hello there")
+        };
+        lb.push_line(
+            Utc::now(),
+            Line::from(format!("nick{}", i % 50)),
+            Line::from(text),
+        );
+    }
+    report("push", started.elapsed(), LINES as u32);
+
+    let ctx = RenderContext {
+        lcol_width: 12,
+        wrap_indent: 2,
+        timestamps: Some(Timestamps::new("[%H:%M:%S]", chrono_tz::UTC).unwrap()),
+        ..RenderContext::default()
+    };
+    let area = Rect::new(0, 0, 160, 50);
+    let mut buf = Buffer::empty(area);
+
+    let mut frame = |lb: &LogBuffer| {
+        buf.reset();
+        lb.render_ref(&ctx, area, &mut buf);
+    };
+
+    let started = Instant::now();
+    frame(&lb);
+    report("first frame", started.elapsed(), 1);
+
+    let started = Instant::now();
+    for _ in 0..FRAMES {
+        frame(&lb);
+    }
+    report("frame at the bottom", started.elapsed(), FRAMES);
+
+    lb.set_scroll(500);
+    frame(&lb);
+    let started = Instant::now();
+    for _ in 0..FRAMES {
+        frame(&lb);
+    }
+    report("frame scrolled back", started.elapsed(), FRAMES);
+}
+
+fn report(name: &str, elapsed: Duration, iterations: u32) {
+    println!(
+        "{name:>20}: {:>10.3?} total, {:>10.3?} each",
+        elapsed,
+        elapsed / iterations
+    );
+}
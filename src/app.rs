@@ -0,0 +1,1353 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use color_eyre::Result;
+use hashbrown::HashMap;
+use irc::proto::Prefix;
+use ratatui::crossterm::event::{self, Event, KeyEvent};
+use ratatui::style::Style;
+use ratatui::text::{Line, Span};
+use ratatui::widgets::ScrollDirection;
+use std::{
+    cell::Cell,
+    io,
+    path::{Path, PathBuf},
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+    time::{Duration, Instant},
+};
+use regex::Regex;
+use tokio::sync::{mpsc, oneshot, Notify, OnceCell, RwLock};
+use tracing::{debug, error, info, warn};
+use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt, Layer};
+
+use crate::{
+    built,
+    chatlog::{ChatLogger, LogLine},
+    client::{self, ChannelState, ConnectedClient, DisconnectedClient, NetworkStatus, Target},
+    input::{self, InputHandler},
+    ignore::{self, IgnoreList, IgnoreRule, MessageKind, SmartFilter},
+    logging,
+    notify::{Notification, Notifier},
+    plugin,
+    tui::{
+        self,
+        mirc::Formatter,
+        nickcolor::{fold_case, normalize, NickColors},
+        widget::{LineFilter, LogBuffer, Timestamps, TopicView},
+        RenderContext, StatelessView, Theme,
+    },
+    Config,
+};
+
+/// Most lines a global search lists.
+const MAX_RESULTS: usize = 500;
+
+/// Most bytes of chat log a global search reads,
+/// across every buffer.
+const SEARCH_BYTES: u64 = 32 * 1024 * 1024;
+
+/// How often each network is pinged to measure lag.
+const LAG_INTERVAL: Duration = Duration::from_secs(30);
+
+/// A line found by a global search, as its timestamp,
+/// buffer, line id, sender and message.
+type Found = (DateTime<Utc>, usize, Option<usize>, String, Line<'static>);
+
+/// A global search whose chat log matches are still
+/// being read on a blocking task.
+struct PendingSearch {
+    text: String,
+    re: Regex,
+    /// Matches in the buffers themselves.
+    found: Vec<Found>,
+    /// Name of every buffer there was at the time.
+    labels: Vec<String>,
+    logged: oneshot::Receiver<Vec<Found>>,
+}
+
+/// Where a line listed by a global search came from.
+struct SearchLink {
+    /// Index into `App::logbuffers`.
+    buffer: usize,
+    /// Id of the line in that buffer, or `None`
+    /// if it was found in the chat log.
+    line: Option<usize>,
+    timestamp: DateTime<Utc>,
+}
+
+pub struct App {
+    /// Configuration loaded from the user's "eeshrc" file.
+    cfg: Config,
+
+    /// Setting this flag to `true` will cause the application
+    /// to gracefully exit at the end of the current frame.
+    exit: AtomicBool,
+
+    #[allow(unused)]
+    clients: Vec<ConnectedClient>,
+
+    #[allow(unused)]
+    disconnected: Vec<DisconnectedClient>,
+
+    /// This context represents the application state
+    /// shared with the UI. Updates to this member
+    /// should be the only thing that will mutate
+    /// UI state.
+    shared_context: Arc<RwLock<RenderContext>>,
+
+    /// Set when something the UI shows has changed
+    /// and a new frame should be drawn.
+    dirty: bool,
+
+    /// The focused buffer and its revision as of the
+    /// last frame, and when that frame was drawn.
+    last_drawn: (u16, u64, Instant),
+
+    /// This represents every text-buffer
+    /// for every channel currently open.
+    /// Channels may not necessarily be IRC
+    /// channels but may be produced by
+    /// scripts or logging commands.
+    logbuffers: Vec<Arc<Mutex<LogBuffer>>>,
+    logbuffer_cursor: u16,
+
+    /// Format and width of the timestamp column,
+    /// or `None` if timestamps are hidden.
+    timestamps: Option<Timestamps>,
+
+    /// Renders mIRC formatting according to
+    /// the user's preferences.
+    formatter: Formatter,
+
+    /// Colours nicks consistently across widgets.
+    nick_colors: NickColors,
+
+    /// Styles for every widget. Shared with the
+    /// tracing layer that writes the debug log.
+    theme: Arc<Mutex<Theme>>,
+
+    /// Our own state on each network, keyed like
+    /// the `clients` section of the config.
+    networks: HashMap<String, NetworkStatus>,
+
+    /// Writes chat to disk for networks that opted in.
+    chatlog: ChatLogger,
+
+    /// File eesh's own diagnostics are written to.
+    log_path: PathBuf,
+
+    /// Whether diagnostics are drawn over the focused buffer.
+    debug_overlay: bool,
+
+    /// Whether the topic bar is expanded or scrolled.
+    topic_view: TopicView,
+
+    /// The buffer global search results are listed
+    /// in, once there has been a search.
+    search_buffer: Option<usize>,
+    search_results: Vec<SearchLink>,
+    pending_search: Option<PendingSearch>,
+    /// Whether a `/` search is being typed, and
+    /// highlighted as it is.
+    search_preview: bool,
+
+    /// Rules hiding messages from some senders.
+    ignores: IgnoreList,
+    smart_filter: SmartFilter,
+
+    /// Sends notifications of mentions and private messages.
+    notifier: Notifier,
+    /// Whether the terminal has focus, as far as it reports.
+    terminal_focused: bool,
+
+    /// The buffer every message mentioning us is copied
+    /// to, once there has been one.
+    mentions_buffer: Option<usize>,
+
+    /// This struct manages user input.
+    /// See struct-level docs for more.
+    input_handler: InputHandler,
+
+    /// Every registered plugin, including the Lua
+    /// runtime, and the requests they have queued
+    /// for the main loop.
+    plugins: plugin::PluginHost,
+}
+
+impl App {
+    pub fn new(cfg: Config) -> Result<Self> {
+        Ok(App {
+            cfg: cfg.clone(),
+
+            exit: AtomicBool::new(false),
+
+            clients: Vec::new(),
+            disconnected: Vec::new(),
+
+            shared_context: Arc::new(RwLock::new(RenderContext::default())),
+            dirty: true,
+            last_drawn: (0, 0, Instant::now()),
+            logbuffers: vec![Arc::new(Mutex::new(LogBuffer::new(
+                cfg.ui.scrollbuffer,
+                cfg.ui.tz,
+            )))],
+            logbuffer_cursor: 0,
+
+            timestamps: Timestamps::from_config(&cfg.ui)?,
+            formatter: Formatter::from_config(&cfg.ui),
+            nick_colors: NickColors::from_config(&cfg.ui, &cfg.theme),
+            theme: Arc::new(Mutex::new(cfg.theme.clone())),
+
+            networks: cfg
+                .clients
+                .iter()
+                .map(|(name, client)| {
+                    let status = NetworkStatus {
+                        nick: client.irc.nickname.clone().unwrap_or_default(),
+                        ..NetworkStatus::default()
+                    };
+                    (name.clone(), status)
+                })
+                .collect(),
+            chatlog: ChatLogger::from_config(&cfg),
+            log_path: crate::paths::state_dir().join("eesh.log"),
+            debug_overlay: false,
+            topic_view: TopicView::default(),
+            search_buffer: None,
+            search_results: Vec::new(),
+            pending_search: None,
+            search_preview: false,
+            mentions_buffer: None,
+            ignores: IgnoreList::from_config(&cfg),
+            smart_filter: SmartFilter::from_config(&cfg),
+            notifier: Notifier::from_config(&cfg),
+            terminal_focused: true,
+
+            input_handler: InputHandler::new(cfg.alias.clone()),
+
+            plugins: plugin::PluginHost::new(),
+        })
+    }
+
+    /// Register a plugin alongside the ones built into eesh,
+    /// e.g. from a downstream crate. Plugins are loaded when
+    /// `run` starts.
+    pub fn with_plugin(mut self, plugin: impl plugin::Plugin + 'static) -> Self {
+        self.plugins.register(Box::new(plugin));
+        self
+    }
+
+    /// Write diagnostics to `path` instead of the
+    /// default log file in the state directory.
+    pub fn with_log_path(mut self, path: impl Into<PathBuf>) -> Self {
+        self.log_path = path.into();
+        self
+    }
+
+    /// Run the application's main loop until the user quits
+    pub async fn run(&mut self, terminal: tui::Tui) -> Result<()> {
+        // Without a log file, the debug log still works.
+        let (file_layer, file_error) =
+            match logging::file_layer(&self.log_path, self.cfg.logging.file_filter()?) {
+                Ok(layer) => (Some(layer), None),
+                Err(e) => (None, Some(e)),
+            };
+        tracing_subscriber::registry()
+            .with(
+                logging::LogBufferLayer::new(
+                    Arc::clone(&self.logbuffers[0]),
+                    Arc::clone(&self.theme),
+                )
+                .with_filter(self.cfg.logging.buffer_filter()?),
+            )
+            .with(file_layer)
+            .init();
+        debug!("Strike the Earth!");
+        info!("Welcome to eesh, the Extra Extensible IRC Shell.");
+        info!(version = built::PKG_VERSION);
+        if let Some(e) = file_error {
+            warn!(
+                error = e.to_string(),
+                path = %self.log_path.display(),
+                "Log file can't be opened"
+            );
+        }
+        info!("");
+        info!(
+            "Don't know where to start? Type {0}h<enter> for help or {0}q<enter> to quit.",
+            self.cfg.alias.get("leader").expect("No leader key was configured!"),
+        );
+
+        if self.cfg.ui.spill_scrollback {
+            self.spill_scrollback();
+        }
+        if let Err(e) = self.ignores.load() {
+            warn!(error = e.to_string(), "Saved ignore rules can't be read");
+        }
+        self.load_plugins();
+
+        let frame_time = Duration::from_secs(1) / u32::from(self.cfg.ui.max_fps.max(1));
+        let redraw = Arc::new(Notify::new());
+        let (alerts, mut pending_alerts) = mpsc::unbounded_channel();
+        self.notifier.attach(alerts);
+
+        // Launch the UI thread.
+        let ui_exit = {
+            let shared_context = Arc::clone(&self.shared_context);
+            let redraw = Arc::clone(&redraw);
+            let stop_signal: Arc<OnceCell<()>> = Arc::new(OnceCell::new());
+            let thread_local_stop = Arc::clone(&stop_signal);
+            tokio::spawn(async move {
+                let mut t = terminal;
+                let sc = shared_context;
+                loop {
+                    // Sleep until there is something new to draw.
+                    redraw.notified().await;
+                    if thread_local_stop.initialized() {
+                        break;
+                    }
+
+                    let started = Instant::now();
+                    while let Ok(escapes) = pending_alerts.try_recv() {
+                        if let Err(e) = t.write_raw(&escapes) {
+                            error!(error = e.to_string(), "Notification can't be written");
+                        }
+                    }
+                    if let Err(e) = Self::render_frame(&sc, &mut t).await {
+                        error!(error = e.to_string(), "UI Thread Error");
+                    }
+                    // Changes made in the meantime are drawn together
+                    // in the next frame.
+                    tokio::time::sleep(frame_time.saturating_sub(started.elapsed())).await;
+                }
+                t.release().unwrap();
+            });
+            stop_signal
+        };
+
+        // Main thread event loop
+        let mut events = Self::read_terminal_events();
+        while !self.is_exiting() {
+            if self.needs_redraw() {
+                *self.shared_context.write().await = self.create_render_context();
+                self.mark_drawn();
+                redraw.notify_one();
+            }
+            self.handle_events(&mut events, frame_time).await?;
+            self.update()?;
+        }
+
+        // Setting this OnceCell terminates the UI thread.
+        ui_exit.set(())?;
+        redraw.notify_one();
+
+        Ok(())
+    }
+
+    /// Give every buffer a file to spill its scrollback
+    /// into, named after the buffer.
+    fn spill_scrollback(&mut self) {
+        for lb in &self.logbuffers {
+            Self::spill(lb, &self.cfg.ui);
+        }
+    }
+
+    fn spill(lb: &Mutex<LogBuffer>, cfg: &tui::UIConfig) {
+        let dir = crate::paths::state_dir().join("scrollback");
+        let retention = TimeDelta::days(cfg.spill_retention_days.into());
+        let result = {
+            let mut lb = lb.lock().expect("Logbuffer mutex was poisoned!");
+            let name = match lb.target() {
+                Some(t) => format!("{}-{}", t.network, t.name),
+                None => String::from("status"),
+            }
+            .replace(['/', '\\'], "_");
+            lb.spill_to(dir.join(format!("{name}.spill")), retention)
+        };
+
+        // Logged only once the buffer is unlocked, since
+        // it might be the one the log is written to.
+        if let Err(e) = result {
+            warn!(error = e.to_string(), "Scrollback can't be spilled to disk");
+        }
+    }
+
+    /// PING every connected network now and then, timing the
+    /// PONG to show lag in the status line.
+    fn ping_networks(&mut self) {
+        for client in &self.clients {
+            let Some(status) = self.networks.get_mut(client.network()) else {
+                continue;
+            };
+            if status.ping_sent.is_some_and(|sent| sent.elapsed() < LAG_INTERVAL) {
+                continue;
+            }
+            status.ping_sent = Some(Instant::now());
+            let ping = irc::proto::Command::PING(client::LAG_TOKEN.to_owned(), None);
+            if let Err(e) = client.send(ping) {
+                warn!(error = e.to_string(), network = client.network(), "Lag can't be measured");
+            }
+        }
+    }
+
+    /// Log any spill file which has failed since the last
+    /// update, now that no buffer is locked.
+    fn report_spill_errors(&self) {
+        for lb in &self.logbuffers {
+            let error = lb
+                .lock()
+                .expect("Logbuffer mutex was poisoned!")
+                .take_spill_error();
+            if let Some(e) = error {
+                warn!(error = e.to_string(), "Scrollback can no longer be spilled to disk");
+            }
+        }
+    }
+
+    /// Load every registered plugin. Called by `run`; only
+    /// needed directly when driving the app without a terminal.
+    pub fn load_plugins(&mut self) {
+        self.plugins.load_all(&self.cfg);
+    }
+
+    /// Advance the application state by one frame: evaluate
+    /// pending input and let plugins act. `run` calls this in
+    /// a loop, but it can also drive the app headlessly
+    /// together with `push_key` and `is_exiting`.
+    pub fn update(&mut self) -> Result<()> {
+        self.process_user_input()?;
+        self.preview_search();
+        self.finish_search();
+        self.plugins.tick();
+        self.ping_networks();
+        self.report_spill_errors();
+        self.process_plugin_requests()
+    }
+
+    /// Highlight matches in the focused buffer while a `/`
+    /// search is being typed, and stop if it's abandoned.
+    fn preview_search(&mut self) {
+        let leader = self.cfg.alias.get("leader").unwrap_or(",");
+        let line = self.input_handler.to_string();
+        let search = match line.strip_prefix(leader).and_then(|c| c.strip_prefix('/')) {
+            Some(text) => {
+                self.search_preview = true;
+                tui::widget::search::pattern(text.trim())
+            }
+            None if self.search_preview => {
+                self.search_preview = false;
+                None
+            }
+            None => return,
+        };
+        self.logbuffers[self.logbuffer_cursor as usize]
+            .lock()
+            .expect("Logbuffer mutex was poisoned!")
+            .set_search(search);
+    }
+
+    /// Feed a key press to the input handler as if
+    /// it came from the terminal.
+    pub fn push_key(&mut self, key: KeyEvent) {
+        self.input_handler.append(key);
+    }
+
+    /// Apply a message received from `network` to
+    /// the state of the buffers it concerns.
+    pub fn handle_message(&mut self, network: &str, msg: &irc::proto::Message) {
+        use irc::proto::Command;
+
+        if let Command::PRIVMSG(target, text) | Command::NOTICE(target, text) = &msg.command {
+            let source = msg.source_nickname().unwrap_or(network);
+            // Messages sent to us directly go in a query with the sender.
+            let buffer = if client::is_channel(target) {
+                target
+            } else {
+                source
+            };
+            let timestamp = client::server_time(msg).unwrap_or_else(Utc::now);
+            if client::is_channel(target) {
+                self.smart_filter.spoke(network, target, source, timestamp);
+            }
+
+            if !self.is_ignored(network, buffer, msg) {
+                self.show_message(network, msg, buffer, source, text, timestamp);
+            }
+        }
+        if let Some(event) = plugin::Event::of(network, msg) {
+            self.plugins.emit(event);
+        }
+        self.show_membership(network, msg);
+        if let Some(status) = self.networks.get_mut(network) {
+            status.apply(msg);
+        }
+
+        let own_nick = self.networks.get(network).map_or("", |n| n.nick.as_str());
+
+        // Quits and nick changes concern every channel we share.
+        if let Command::QUIT(_) | Command::NICK(_) = &msg.command {
+            for lb in &self.logbuffers {
+                let mut lb = lb.lock().expect("Logbuffer mutex was poisoned!");
+                if lb.target().is_some_and(|t| t.network == network) {
+                    lb.channel_mut().apply(msg, own_nick);
+                }
+            }
+        }
+
+        let Some(channel) = ChannelState::channel_of(msg) else {
+            return;
+        };
+
+        if let Some(lb) = self.buffer_for(network, channel) {
+            lb.lock()
+                .expect("Logbuffer mutex was poisoned!")
+                .channel_mut()
+                .apply(msg, own_nick);
+        }
+    }
+
+    /// Whether an ignore rule hides `msg`, sent to `buffer`.
+    fn is_ignored(&self, network: &str, buffer: &str, msg: &irc::proto::Message) -> bool {
+        let Some(kind) = MessageKind::of(&msg.command) else {
+            return false;
+        };
+        let source = msg.prefix.as_ref().map(ignore::mask_of).unwrap_or_default();
+        self.ignores.ignores(network, buffer, kind, &source)
+    }
+
+    /// Show a message sent to `buffer`, highlighting and
+    /// notifying of it if it mentions us.
+    fn show_message(
+        &mut self,
+        network: &str,
+        msg: &irc::proto::Message,
+        buffer: &str,
+        source: &str,
+        text: &str,
+        timestamp: DateTime<Utc>,
+    ) {
+        let own_nick = self.networks.get(network).map_or("", |n| n.nick.as_str());
+        let mentioned = self.cfg.highlight.matches(own_nick, buffer, source, text);
+        let style = if mentioned {
+            Style::from(self.theme.lock().expect("Theme mutex was poisoned!").highlight)
+        } else {
+            Style::default()
+        };
+
+        let lb = self.push_routed(
+            &LogLine {
+                timestamp,
+                network,
+                target: Some(buffer),
+                sender: source,
+                text,
+                message: Some(msg),
+            },
+            style,
+        );
+        if mentioned {
+            lb.lock()
+                .expect("Logbuffer mutex was poisoned!")
+                .add_mention();
+            self.copy_mention(timestamp, buffer, source, text);
+        }
+
+        // Only worth a notification if it can't be seen already.
+        let notify = self.notifier.config();
+        let query = matches!(msg.command, irc::proto::Command::PRIVMSG(..))
+            && !client::is_channel(buffer)
+            && msg.source_nickname().is_some();
+        let wanted = (mentioned && notify.mentions) || (query && notify.queries);
+        let visible = self.terminal_focused
+            && Arc::ptr_eq(&lb, &self.logbuffers[self.logbuffer_cursor as usize]);
+        if wanted && !visible {
+            let notification = Notification {
+                buffer,
+                sender: source,
+                text,
+            };
+            if let Err(e) = self.notifier.notify(&notification, Utc::now()) {
+                warn!(error = e.to_string(), "Notification command can't be run");
+            }
+        }
+    }
+
+    /// Show joins, parts, kicks and quits in the channels they
+    /// concern, unless ignored or hidden by the smart filter.
+    /// Called before the channel state is updated, so quitters
+    /// are still members.
+    fn show_membership(&mut self, network: &str, msg: &irc::proto::Message) {
+        use irc::proto::Command;
+
+        let Some(nick) = msg.source_nickname() else {
+            return;
+        };
+        let who = match &msg.prefix {
+            Some(Prefix::Nickname(_, user, host)) => format!("{nick} ({user}@{host})"),
+            _ => nick.to_owned(),
+        };
+        let reason = |reason: &Option<String>| match reason.as_deref() {
+            Some(reason) if !reason.is_empty() => format!(" ({reason})"),
+            _ => String::new(),
+        };
+        let (text, buffers) = match &msg.command {
+            Command::JOIN(channel, ..) => (
+                format!("--> {who} has joined {channel}"),
+                vec![self.open_buffer(network, channel)],
+            ),
+            Command::PART(channel, message) => (
+                format!("<-- {who} has left {channel}{}", reason(message)),
+                self.buffer_for(network, channel).into_iter().cloned().collect(),
+            ),
+            Command::KICK(channel, nicks, message) => (
+                format!(
+                    "<-- {} was kicked from {channel} by {nick}{}",
+                    nicks.replace(',', ", "),
+                    reason(message)
+                ),
+                self.buffer_for(network, channel).into_iter().cloned().collect(),
+            ),
+            Command::QUIT(message) => (
+                format!("<-- {who} has quit{}", reason(message)),
+                self.logbuffers
+                    .iter()
+                    .filter(|lb| {
+                        let lb = lb.lock().expect("Logbuffer mutex was poisoned!");
+                        lb.target().is_some_and(|t| t.network == network)
+                            && lb.channel().has_member(nick)
+                    })
+                    .cloned()
+                    .collect(),
+            ),
+            _ => return,
+        };
+
+        let timestamp = client::server_time(msg).unwrap_or_else(Utc::now);
+        let own_nick = self.networks.get(network).map_or("", |n| n.nick.as_str());
+        let style = Style::from(self.theme.lock().expect("Theme mutex was poisoned!").join_part);
+        for lb in buffers {
+            let mut lb = lb.lock().expect("Logbuffer mutex was poisoned!");
+            let Some(channel) = lb.target().map(|t| t.name.clone()) else {
+                continue;
+            };
+            // Our own comings and goings are always shown, and
+            // so are kicks, whoever did the kicking.
+            let hidden = self.is_ignored(network, &channel, msg)
+                || (normalize(nick) != normalize(own_nick)
+                    && !matches!(msg.command, Command::KICK(..))
+                    && self.smart_filter.hides(network, &channel, nick, timestamp));
+            if !hidden {
+                lb.push_line(timestamp, Line::default(), Line::styled(text.clone(), style));
+                self.dirty = true;
+            }
+        }
+    }
+
+    /// Show a line in the buffer for its target, opening one
+    /// if needed, and write it to the chat log. Lines without a
+    /// target go to the status buffer.
+    pub fn route_line(&mut self, line: &LogLine) {
+        self.push_routed(line, Style::default());
+    }
+
+    /// `route_line` with the message restyled by `style`,
+    /// returning the buffer it went to.
+    fn push_routed(&mut self, line: &LogLine, style: Style) -> Arc<Mutex<LogBuffer>> {
+        let lb = match line.target {
+            Some(name) => self.open_buffer(line.network, name),
+            None => Arc::clone(&self.logbuffers[0]),
+        };
+        lb.lock().expect("Logbuffer mutex was poisoned!").push_line(
+            line.timestamp,
+            Line::from(line.sender.to_owned()),
+            self.formatter.parse(line.text).patch_style(style),
+        );
+        self.dirty = true;
+
+        if let Err(e) = self.chatlog.log(line) {
+            warn!(
+                error = e.to_string(),
+                network = line.network,
+                "Chat can't be logged to disk"
+            );
+        }
+        lb
+    }
+
+    /// Copy a message mentioning us to the mentions buffer,
+    /// opened at the end of the list if needed, along with
+    /// the name of the buffer it was sent to.
+    fn copy_mention(&mut self, timestamp: DateTime<Utc>, buffer: &str, sender: &str, text: &str) {
+        let index = *self.mentions_buffer.get_or_insert_with(|| {
+            self.logbuffers.push(Arc::new(Mutex::new(LogBuffer::new(
+                self.cfg.ui.scrollbuffer,
+                self.cfg.ui.tz,
+            ))));
+            self.logbuffers.len() - 1
+        });
+
+        let mut message = Line::from(format!("<{sender}> "));
+        message.spans.extend(self.formatter.parse(text).spans);
+        self.logbuffers[index]
+            .lock()
+            .expect("Logbuffer mutex was poisoned!")
+            .push_line(timestamp, Line::from(buffer.to_owned()), message);
+    }
+
+    /// The buffer showing `name` on `network`,
+    /// opened at the end of the list if needed.
+    fn open_buffer(&mut self, network: &str, name: &str) -> Arc<Mutex<LogBuffer>> {
+        if let Some(lb) = self.buffer_for(network, name) {
+            return Arc::clone(lb);
+        }
+
+        let target = Target {
+            network: network.to_owned(),
+            name: name.to_owned(),
+        };
+        let lb = Arc::new(Mutex::new(
+            LogBuffer::new(self.cfg.ui.scrollbuffer, self.cfg.ui.tz).with_target(target),
+        ));
+        if self.cfg.ui.spill_scrollback {
+            Self::spill(&lb, &self.cfg.ui);
+        }
+        self.replay_backlog(&lb, network, Some(name));
+        self.logbuffers.push(Arc::clone(&lb));
+        lb
+    }
+
+    /// Fill a newly opened buffer with the end of its chat log,
+    /// dimmed and set apart from live traffic. The lines are
+    /// pushed straight to the buffer so they aren't logged again.
+    /// Skipped if earlier sessions' lines already came back with
+    /// the buffer's spill file.
+    fn replay_backlog(&self, lb: &Mutex<LogBuffer>, network: &str, target: Option<&str>) {
+        if lb.lock().expect("Logbuffer mutex was poisoned!").older() > 0 {
+            return;
+        }
+        let backlog = self.chatlog.backlog(network, target);
+        if backlog.is_empty() {
+            return;
+        }
+        let style = Style::from(self.theme.lock().expect("Theme mutex was poisoned!").backlog);
+
+        let mut lb = lb.lock().expect("Logbuffer mutex was poisoned!");
+        for line in backlog {
+            lb.push_line(
+                line.timestamp,
+                Line::from(line.sender),
+                self.formatter.parse(&line.text).patch_style(style),
+            );
+        }
+        lb.push_line(
+            Utc::now(),
+            Line::default(),
+            Line::styled("— end of backlog —", style),
+        );
+    }
+
+    /// The buffer showing `name` on `network`, if one is open.
+    fn buffer_for(&self, network: &str, name: &str) -> Option<&Arc<Mutex<LogBuffer>>> {
+        self.logbuffers.iter().find(|lb| {
+            lb.lock()
+                .expect("Logbuffer mutex was poisoned!")
+                .target()
+                .is_some_and(|t| t.network == network && t.name.eq_ignore_ascii_case(name))
+        })
+    }
+
+    /// Whether the application has been asked to exit.
+    pub fn is_exiting(&self) -> bool {
+        self.exit.load(Ordering::Relaxed)
+    }
+
+    /// Produce a snapshot of the current application state
+    /// as it is relevant to the UI subsystem.
+    fn create_render_context(&self) -> RenderContext {
+        let focused = &self.logbuffers[self.logbuffer_cursor as usize];
+        let network = focused
+            .lock()
+            .expect("Logbuffer mutex was poisoned!")
+            .target()
+            .and_then(|target| self.networks.get(&target.network))
+            .cloned();
+        let mentions = self
+            .logbuffers
+            .iter()
+            .enumerate()
+            .filter(|(index, _)| *index != self.logbuffer_cursor as usize)
+            .filter_map(|(_, lb)| {
+                let lb = lb.lock().expect("Logbuffer mutex was poisoned!");
+                let target = lb.target()?;
+                (lb.mentions() > 0).then(|| (target.name.clone(), lb.mentions()))
+            })
+            .collect();
+
+        RenderContext {
+            user_line: self.input_handler.to_string(),
+            lcol_width: self.cfg.ui.lcol_width,
+            timestamps: self.timestamps.clone(),
+            wrap_indent: self.cfg.ui.wrap_indent,
+            formatter: self.formatter,
+            nick_colors: self.nick_colors.clone(),
+            theme: self.theme.lock().expect("Theme mutex was poisoned!").clone(),
+            status_line: self.cfg.ui.status_line.clone(),
+            network,
+            debug_overlay: self.debug_overlay,
+            topic_view: self.topic_view,
+            mentions,
+            text_buffer: Some(Arc::clone(focused)),
+        }
+    }
+
+    async fn render_frame(ctx: &RwLock<RenderContext>, terminal: &mut tui::Tui) -> io::Result<()> {
+        let context = ctx.read().await;
+        let view = StatelessView::new(&context);
+
+        terminal.as_mut().draw(|frame| view.render_frame(frame))?;
+
+        Ok(())
+    }
+
+    /// Whether anything shown has changed since the last frame,
+    /// or the last frame is old enough to be drawn again anyway.
+    fn needs_redraw(&self) -> bool {
+        let (cursor, revision, at) = self.last_drawn;
+        self.dirty
+            || cursor != self.logbuffer_cursor
+            || revision != self.focused_revision()
+            || at.elapsed() >= Duration::from_millis(self.cfg.ui.idle_redraw_ms)
+    }
+
+    fn mark_drawn(&mut self) {
+        // Mentions in the focused buffer have now been seen.
+        self.logbuffers[self.logbuffer_cursor as usize]
+            .lock()
+            .expect("Logbuffer mutex was poisoned!")
+            .clear_mentions();
+
+        self.dirty = false;
+        self.last_drawn = (self.logbuffer_cursor, self.focused_revision(), Instant::now());
+    }
+
+    fn focused_revision(&self) -> u64 {
+        self.logbuffers[self.logbuffer_cursor as usize]
+            .lock()
+            .expect("Logbuffer mutex was poisoned!")
+            .revision()
+    }
+
+    /// Read terminal events on a thread of their own, since
+    /// reading blocks. The thread stops after the first error,
+    /// or once the receiver is dropped and another event comes.
+    fn read_terminal_events() -> mpsc::UnboundedReceiver<io::Result<Event>> {
+        let (sender, events) = mpsc::unbounded_channel();
+        // Not a blocking task, which would hold up the runtime's
+        // shutdown for as long as no key is pressed.
+        std::thread::spawn(move || loop {
+            let event = event::read();
+            let failed = event.is_err();
+            if sender.send(event).is_err() || failed {
+                break;
+            }
+        });
+        events
+    }
+
+    /// Wait up to `timeout` for terminal events or plugin
+    /// requests, then handle every event that is pending.
+    async fn handle_events(
+        &mut self,
+        events: &mut mpsc::UnboundedReceiver<io::Result<Event>>,
+        timeout: Duration,
+    ) -> io::Result<()> {
+        let mut next = tokio::select! {
+            event = events.recv() => event,
+            // Requests are applied by `update`.
+            () = self.plugins.requested() => None,
+            () = tokio::time::sleep(timeout) => None,
+        };
+        if next.is_some() {
+            // Any event, including a resize, changes what is drawn.
+            self.dirty = true;
+        }
+
+        while let Some(event) = next {
+            match event? {
+                Event::Key(key_event) => self.push_key(key_event),
+                Event::FocusGained => self.terminal_focused = true,
+                Event::FocusLost => self.terminal_focused = false,
+                e => debug!(event = format!("{e:?}")),
+            };
+            next = events.try_recv().ok();
+        }
+        Ok(())
+    }
+
+    fn process_user_input(&mut self) -> Result<()> {
+        // The handler is moved out while it runs so that it
+        // can drive the rest of the app through `input::Api`.
+        let mut input = std::mem::take(&mut self.input_handler);
+        let result = input.evaluate(self);
+        self.input_handler = input;
+        result
+    }
+
+    /// Apply every request plugins have made since
+    /// the last frame.
+    fn process_plugin_requests(&mut self) -> Result<()> {
+        while let Some(request) = self.plugins.next_request() {
+            self.dirty = true;
+            // A plugin's failed request, such as a message sent while
+            // disconnected, shouldn't bring down the whole app.
+            if let Err(e) = request.apply(self) {
+                warn!("{e}");
+            }
+        }
+        Ok(())
+    }
+
+    /// List the results of a global search in the
+    /// search buffer once its logs have been read.
+    fn finish_search(&mut self) {
+        let Some(pending) = &mut self.pending_search else {
+            return;
+        };
+        let logged = match pending.logged.try_recv() {
+            Ok(logged) => logged,
+            Err(oneshot::error::TryRecvError::Empty) => return,
+            // The blocking task panicked.
+            Err(oneshot::error::TryRecvError::Closed) => Vec::new(),
+        };
+        let Some(PendingSearch {
+            text,
+            re,
+            mut found,
+            labels,
+            ..
+        }) = self.pending_search.take()
+        else {
+            return;
+        };
+
+        found.extend(logged);
+        found.sort_by_key(|(timestamp, ..)| *timestamp);
+        found.drain(..found.len().saturating_sub(MAX_RESULTS));
+
+        let index = *self.search_buffer.get_or_insert_with(|| {
+            self.logbuffers.push(Arc::new(Mutex::new(LogBuffer::new(
+                MAX_RESULTS,
+                self.cfg.ui.tz,
+            ))));
+            self.logbuffers.len() - 1
+        });
+        let theme = self.theme.lock().expect("Theme mutex was poisoned!").clone();
+
+        let mut results = LogBuffer::new(MAX_RESULTS, self.cfg.ui.tz);
+        results.set_search(Some(re));
+        self.search_results.clear();
+        for (timestamp, buffer, line, sender, content) in found {
+            self.search_results.push(SearchLink {
+                buffer,
+                line,
+                timestamp,
+            });
+            let mut message = Line::from(vec![
+                Span::styled(format!("[{}] ", self.search_results.len()), theme.timestamp),
+                Span::raw(format!("<{sender}> ")),
+            ]);
+            message.spans.extend(content.spans);
+            results.push_line(timestamp, Line::from(labels[buffer].clone()), message);
+        }
+        *self.logbuffers[index]
+            .lock()
+            .expect("Logbuffer mutex was poisoned!") = results;
+        self.logbuffer_cursor = index as u16;
+        self.dirty = true;
+
+        info!(text, results = self.search_results.len(), "Searched");
+    }
+}
+
+impl input::Api for App {
+    fn exit(&mut self) {
+        self.exit.store(true, Ordering::Relaxed)
+    }
+
+    fn scroll(&mut self, direction: ScrollDirection) {
+        match direction {
+            ScrollDirection::Forward => {
+                self.logbuffers[self.logbuffer_cursor as usize]
+                    .lock()
+                    .expect("Logbuffer mutex was poisoned!")
+                    .inc_scroll();
+            }
+            ScrollDirection::Backward => {
+                self.logbuffers[self.logbuffer_cursor as usize]
+                    .lock()
+                    .expect("Logbuffer mutex was poisoned!")
+                    .dec_scroll();
+            }
+        }
+    }
+
+    fn clear_input_buffer(&mut self) {
+        self.input_handler.clear();
+    }
+
+    fn send_message<M: Into<irc::proto::Message>>(
+        &mut self,
+        server: &str,
+        channel: &str,
+        message: M,
+    ) -> Result<()> {
+        let Some(client) = self.clients.iter().find(|c| c.network() == server) else {
+            color_eyre::eyre::bail!("Not connected to {server}");
+        };
+        let message = message.into();
+        client.send(message.clone())?;
+
+        // Servers don't echo our own messages back, so they
+        // are shown as soon as they are sent.
+        if let irc::proto::Command::PRIVMSG(_, text) = &message.command {
+            let own_nick = self.networks.get(server).map_or("", |n| n.nick.as_str()).to_owned();
+            let style = Style::from(self.theme.lock().expect("Theme mutex was poisoned!").own_message);
+            self.push_routed(
+                &LogLine {
+                    timestamp: Utc::now(),
+                    network: server,
+                    target: Some(channel),
+                    sender: &own_nick,
+                    text,
+                    message: Some(&message),
+                },
+                style,
+            );
+        }
+        Ok(())
+    }
+
+    fn send_text(&mut self, text: &str) -> Result<()> {
+        let target = self.logbuffers[self.logbuffer_cursor as usize]
+            .lock()
+            .expect("Logbuffer mutex was poisoned!")
+            .target()
+            .cloned();
+        let Some(target) = target else {
+            color_eyre::eyre::bail!("This buffer isn't connected to a channel.");
+        };
+
+        let msg = irc::proto::Command::PRIVMSG(target.name.clone(), text.to_owned());
+        self.send_message(&target.network, &target.name, msg)
+    }
+
+    fn set_theme(&mut self, name: &str) -> Result<()> {
+        let theme = match name {
+            "config" => self.cfg.theme.clone(),
+            name => Theme::load(name, crate::paths::data_dir().join("themes"))?,
+        };
+
+        self.nick_colors = NickColors::from_config(&self.cfg.ui, &theme);
+        *self.theme.lock().expect("Theme mutex was poisoned!") = theme;
+        info!(theme = name, "Switched theme");
+        Ok(())
+    }
+
+    fn toggle_debug_overlay(&mut self) {
+        self.debug_overlay = !self.debug_overlay;
+    }
+
+    fn toggle_topic(&mut self) {
+        self.topic_view.expanded = !self.topic_view.expanded;
+    }
+
+    fn scroll_topic(&mut self, direction: ScrollDirection) {
+        // Scroll by a few columns at a time, or it takes forever.
+        const STEP: u16 = 8;
+
+        self.topic_view.scroll = match direction {
+            ScrollDirection::Forward => self.topic_view.scroll.saturating_add(STEP),
+            ScrollDirection::Backward => self.topic_view.scroll.saturating_sub(STEP),
+        };
+    }
+
+    fn set_filter(&mut self, spec: &str) -> Result<()> {
+        let filter = if spec.is_empty() {
+            None
+        } else {
+            Some(LineFilter::parse(spec)?)
+        };
+        self.logbuffers[self.logbuffer_cursor as usize]
+            .lock()
+            .expect("Logbuffer mutex was poisoned!")
+            .set_filter(filter);
+        Ok(())
+    }
+
+    fn toggle_follow(&mut self) {
+        let mut lb = self.logbuffers[self.logbuffer_cursor as usize]
+            .lock()
+            .expect("Logbuffer mutex was poisoned!");
+        let follow = !lb.following();
+        lb.set_follow(follow);
+    }
+
+    fn export_buffer(&mut self, path: &Path) -> Result<()> {
+        let file = io::BufWriter::new(std::fs::File::create(path)?);
+        let count = self.logbuffers[self.logbuffer_cursor as usize]
+            .lock()
+            .expect("Logbuffer mutex was poisoned!")
+            .export(file)?;
+
+        // Logged only once the buffer is unlocked, since
+        // it might be the one the log is written to.
+        info!(lines = count, path = %path.display(), "Exported buffer");
+        Ok(())
+    }
+
+    fn find(&mut self, text: &str) {
+        // The search is no longer being typed, so it stays.
+        self.search_preview = false;
+
+        let found = {
+            let mut lb = self.logbuffers[self.logbuffer_cursor as usize]
+                .lock()
+                .expect("Logbuffer mutex was poisoned!");
+            lb.set_search(tui::widget::search::pattern(text));
+            text.is_empty() || lb.step_search(true)
+        };
+        if !found {
+            info!(text, "Not found");
+        }
+    }
+
+    fn find_next(&mut self, direction: ScrollDirection) {
+        let found = self.logbuffers[self.logbuffer_cursor as usize]
+            .lock()
+            .expect("Logbuffer mutex was poisoned!")
+            .step_search(direction == ScrollDirection::Backward);
+        if !found {
+            info!("No more matches");
+        }
+    }
+
+    fn search(&mut self, text: &str) {
+        let Some(re) = tui::widget::search::pattern(text) else {
+            return;
+        };
+
+        let mut found = Vec::new();
+        let mut labels = Vec::new();
+        // Buffers whose chat log is searched, with the
+        // oldest line each still has in memory.
+        let mut logs = Vec::new();
+        for (index, lb) in self.logbuffers.iter().enumerate() {
+            let lb = lb.lock().expect("Logbuffer mutex was poisoned!");
+            labels.push(lb.target().map_or(String::from("status"), |t| t.name.clone()));
+            if Some(index) == self.search_buffer || Some(index) == self.mentions_buffer {
+                continue;
+            }
+            for (id, timestamp, tag, content) in lb.find(&re) {
+                let sender = tui::widget::plain(tag);
+                found.push((*timestamp, index, Some(id), sender, content.clone()));
+            }
+            if let Some(target) = lb.target() {
+                logs.push((index, target.clone(), lb.oldest()));
+            }
+        }
+
+        // Reading the logs can take a while, so it happens
+        // off the main loop, with no buffer locked.
+        let (sender, logged) = oneshot::channel();
+        let (chatlog, formatter, log_re) = (self.chatlog.reader(), self.formatter, re.clone());
+        let read = move || {
+            let budget = Cell::new(SEARCH_BYTES);
+            let mut found = Vec::new();
+            for (index, target, oldest) in logs {
+                let logged =
+                    chatlog.search(&target.network, Some(&target.name), &log_re, MAX_RESULTS, &budget);
+                // Only lines from before the oldest one still in memory.
+                for line in logged {
+                    if oldest.is_none_or(|oldest| line.timestamp < oldest) {
+                        let content = formatter.parse(&line.text);
+                        found.push((line.timestamp, index, None, line.sender, content));
+                    }
+                }
+            }
+            // Nobody is waiting if another search has started.
+            let _ = sender.send(found);
+        };
+        match tokio::runtime::Handle::try_current() {
+            Ok(runtime) => drop(runtime.spawn_blocking(read)),
+            // Driven headlessly, outside of a runtime.
+            Err(_) => read(),
+        }
+
+        self.pending_search = Some(PendingSearch {
+            text: text.to_owned(),
+            re,
+            found,
+            labels,
+            logged,
+        });
+        // Already done when the logs were read inline.
+        self.finish_search();
+    }
+
+    fn jump(&mut self, n: usize) -> Result<()> {
+        let Some(link) = n.checked_sub(1).and_then(|i| self.search_results.get(i)) else {
+            color_eyre::eyre::bail!("There is no search result {n}");
+        };
+        self.logbuffer_cursor = link.buffer as u16;
+
+        let mut lb = self.logbuffers[link.buffer]
+            .lock()
+            .expect("Logbuffer mutex was poisoned!");
+        let jumped = match link.line {
+            Some(id) => lb.jump_to(id),
+            None => false,
+        };
+        if !jumped {
+            // Show the nearest line to it that's still in memory.
+            lb.jump_near(link.timestamp);
+            drop(lb);
+            let local = link.timestamp.with_timezone(&self.cfg.ui.tz);
+            info!(
+                logged = %local.format("%Y-%m-%d %H:%M:%S"),
+                "That line is only in the chat log now"
+            );
+        }
+        Ok(())
+    }
+
+    fn switch_buffer(&mut self, which: &str) -> Result<()> {
+        let index = match which.to_lowercase().as_str() {
+            "status" => Some(0),
+            "mentions" => self.mentions_buffer,
+            "search" => self.search_buffer,
+            _ => match which.parse::<usize>() {
+                Ok(n) => n.checked_sub(1).filter(|&i| i < self.logbuffers.len()),
+                Err(_) => {
+                    // A name alone matches on any network.
+                    let (network, name) = match which.split_once('/') {
+                        Some((network, name)) => (Some(network), name),
+                        None => (None, which),
+                    };
+                    self.logbuffers.iter().position(|lb| {
+                        let lb = lb.lock().expect("Logbuffer mutex was poisoned!");
+                        lb.target().is_some_and(|t| {
+                            network.is_none_or(|n| n == t.network)
+                                && fold_case(name) == fold_case(&t.name)
+                        })
+                    })
+                }
+            },
+        };
+        let Some(index) = index else {
+            color_eyre::eyre::bail!("There is no buffer {which}");
+        };
+        self.logbuffer_cursor = index as u16;
+        Ok(())
+    }
+
+    fn ignore(&mut self, rule: IgnoreRule) -> Result<()> {
+        let shown = rule.to_string();
+        self.ignores.add(rule)?;
+        info!(rule = shown, "Ignoring");
+        Ok(())
+    }
+
+    fn list_ignores(&mut self) {
+        if self.ignores.rules().next().is_none() {
+            info!("Nobody is ignored");
+        }
+        for (n, rule) in self.ignores.rules() {
+            info!("{n}: {rule}");
+        }
+    }
+
+    fn unignore(&mut self, which: &str) -> Result<()> {
+        for rule in self.ignores.remove(which)? {
+            info!(rule = rule.to_string(), "No longer ignoring");
+        }
+        Ok(())
+    }
+
+    fn plugin_command(&mut self, name: &str, args: &[String]) -> Result<()> {
+        self.plugins.command(name, args)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::App;
+    use crate::{tui::widget::plain, Config};
+    use std::path::{Path, PathBuf};
+
+    fn log_dir(name: &str) -> PathBuf {
+        std::env::temp_dir().join(format!("eesh-app-{name}-{}", std::process::id()))
+    }
+
+    /// An app logging to JSONL files in `dir`,
+    /// replaying the last two lines of each buffer.
+    fn app(dir: &Path) -> App {
+        let cfg = format!(
+            "[alias]\n[ui]\n[clients]\n[chatlog]\nenabled = true\ndir = {:?}\n\
+             formats = [\"jsonl\"]\nreplay_lines = 2\n",
+            dir.display().to_string()
+        );
+        App::new(Config::parse_str(&cfg).unwrap()).unwrap()
+    }
+
+    fn say(app: &mut App, text: &str) {
+        let msg = format!(":bob!b@host PRIVMSG #rust :{text}\r\n");
+        app.handle_message("libera", &msg.parse().unwrap());
+    }
+
+    fn shown(app: &App) -> Vec<String> {
+        let lb = app.buffer_for("libera", "#rust").unwrap().lock().unwrap();
+        lb.lines().map(|(_, _, content)| plain(content)).collect()
+    }
+
+    /// Every line logged for `#rust`, whatever day it fell on.
+    fn logged(dir: &Path) -> usize {
+        std::fs::read_dir(dir.join("libera/#rust"))
+            .unwrap()
+            .map(|file| std::fs::read_to_string(file.unwrap().path()).unwrap())
+            .map(|log| log.lines().count())
+            .sum()
+    }
+
+    #[test]
+    fn reopened_buffers_replay_the_end_of_their_log() {
+        let dir = log_dir("replay");
+        let mut first = app(&dir);
+        for text in ["one", "two", "three"] {
+            say(&mut first, text);
+        }
+        assert_eq!(shown(&first), ["one", "two", "three"]);
+        drop(first);
+
+        let mut second = app(&dir);
+        say(&mut second, "four");
+        assert_eq!(
+            shown(&second),
+            ["two", "three", "— end of backlog —", "four"]
+        );
+        // Only the new line was logged, not the replayed ones.
+        assert_eq!(logged(&dir), 4);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn nothing_is_replayed_without_a_log() {
+        let dir = log_dir("empty");
+        let mut app = app(&dir);
+        say(&mut app, "hello");
+        assert_eq!(shown(&app), ["hello"]);
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
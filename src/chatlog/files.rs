@@ -0,0 +1,89 @@
+use hashbrown::HashMap;
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, Write},
+    path::{Path, PathBuf},
+};
+
+/// The log files currently open, one per route, rolled
+/// over to a new part once they outgrow `max_size`.
+pub struct LogFiles {
+    max_size: Option<u64>,
+    open: HashMap<String, LogFile>,
+}
+
+struct LogFile {
+    /// Path of the first part, before any size rotation.
+    base: PathBuf,
+    part: usize,
+    file: File,
+    size: u64,
+}
+
+impl LogFiles {
+    pub fn new(max_size: Option<u64>) -> Self {
+        LogFiles {
+            max_size,
+            open: HashMap::new(),
+        }
+    }
+
+    /// Append `record` to the file for `route`, now named
+    /// `base`. Once the date in `base` changes, the old file
+    /// is closed and the next record starts a new one.
+    pub fn append(&mut self, route: &str, base: &Path, record: &str) -> io::Result<()> {
+        let stale = self.open.get(route).is_none_or(|f| f.base != base);
+        if stale {
+            self.open
+                .insert(route.to_owned(), Self::find_part(self.max_size, base, 0)?);
+        }
+        let log = self.open.get_mut(route).expect("Log file was just opened");
+
+        let len = record.len() as u64;
+        if let Some(max) = self.max_size {
+            if log.size > 0 && log.size + len > max {
+                *log = Self::find_part(self.max_size, base, log.part + 1)?;
+            }
+        }
+
+        log.file.write_all(record.as_bytes())?;
+        log.size += len;
+        Ok(())
+    }
+
+    /// Open the first part from `first` on that still has room,
+    /// so that a restart appends to where the last session left off.
+    fn find_part(max_size: Option<u64>, base: &Path, first: usize) -> io::Result<LogFile> {
+        if let Some(parent) = base.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut part = first;
+        loop {
+            let path = part_path(base, part);
+            let size = std::fs::metadata(&path).map_or(0, |m| m.len());
+            if max_size.is_none_or(|max| size < max) {
+                let file = OpenOptions::new().create(true).append(true).open(&path)?;
+                return Ok(LogFile {
+                    base: base.to_owned(),
+                    part,
+                    file,
+                    size,
+                });
+            }
+            part += 1;
+        }
+    }
+}
+
+/// `2024-01-01.log`, `2024-01-01.1.log`, `2024-01-01.2.log`, ...
+pub fn part_path(base: &Path, part: usize) -> PathBuf {
+    if part == 0 {
+        return base.to_owned();
+    }
+    let stem = base.file_stem().unwrap_or_default().to_string_lossy();
+    match base.extension() {
+        Some(ext) => base.with_file_name(format!("{stem}.{part}.{}", ext.to_string_lossy())),
+        None => base.with_file_name(format!("{stem}.{part}")),
+    }
+}
@@ -0,0 +1,137 @@
+use chrono::{DateTime, FixedOffset};
+use chrono_tz::Tz;
+use irc::proto::message::Tag;
+use serde::Serialize;
+use std::{collections::BTreeMap, io};
+
+use super::{LogLine, LoggedLine};
+use crate::tui::mirc::Formatter;
+
+/// A line of a JSONL log. Fields only known for lines
+/// received from a server are `null` otherwise.
+#[derive(Debug, Serialize)]
+pub struct Record<'a> {
+    /// RFC 3339, in the configured time zone.
+    pub timestamp: DateTime<FixedOffset>,
+    pub network: &'a str,
+    pub target: Option<&'a str>,
+    pub sender: &'a str,
+
+    /// Full `nick!user@host` or server name.
+    pub prefix: Option<String>,
+
+    /// IRCv3 message tags. Tags without a value map to `null`.
+    pub tags: BTreeMap<&'a str, Option<&'a str>>,
+
+    /// Command or numeric, such as `PRIVMSG`.
+    pub command: Option<String>,
+
+    /// The message as it came off the wire.
+    pub raw: Option<String>,
+
+    /// Message text, including any mIRC formatting codes.
+    pub text: &'a str,
+
+    /// Message text with formatting codes removed.
+    pub plain: String,
+}
+
+impl<'a> Record<'a> {
+    pub fn new(line: &LogLine<'a>, tz: Tz) -> Self {
+        let msg = line.message;
+        Record {
+            timestamp: line.timestamp.with_timezone(&tz).fixed_offset(),
+            network: line.network,
+            target: line.target,
+            sender: line.sender,
+            prefix: msg.and_then(|m| m.prefix.as_ref()).map(ToString::to_string),
+            tags: msg
+                .and_then(|m| m.tags.as_ref())
+                .into_iter()
+                .flatten()
+                .map(|Tag(key, value)| (key.as_str(), value.as_deref()))
+                .collect(),
+            command: msg.and_then(|m| {
+                String::from(&m.command)
+                    .split(' ')
+                    .next()
+                    .map(str::to_owned)
+            }),
+            raw: msg.map(|m| m.to_string().trim_end().to_owned()),
+            text: line.text,
+            plain: Formatter::strip(line.text),
+        }
+    }
+}
+
+/// `line` as a single line of JSON.
+pub fn record(line: &LogLine, tz: Tz) -> io::Result<String> {
+    let mut record = serde_json::to_string(&Record::new(line, tz))?;
+    record.push('\n');
+    Ok(record)
+}
+
+/// Read back a line written by `record`.
+pub fn parse(raw: &str) -> Option<LoggedLine> {
+    serde_json::from_str(raw).ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, record};
+    use crate::chatlog::LogLine;
+    use chrono::{TimeZone, Utc};
+    use irc::proto::Message;
+
+    #[test]
+    fn records_read_back_as_they_were_written() {
+        let msg: Message = "@time=2024-03-01T12:00:00.000Z;+draft/reply :bob!b@host PRIVMSG #rust :\x02hi\x02 all\r\n"
+            .parse()
+            .unwrap();
+        let line = LogLine {
+            timestamp: Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap(),
+            network: "libera",
+            target: Some("#rust"),
+            sender: "bob",
+            text: "\x02hi\x02 all",
+            message: Some(&msg),
+        };
+
+        let raw = record(&line, chrono_tz::Asia::Tokyo).unwrap();
+        assert!(raw.ends_with('\n') && !raw.trim_end().contains('\n'));
+
+        let json: serde_json::Value = serde_json::from_str(&raw).unwrap();
+        assert_eq!(json["timestamp"], "2024-03-01T21:00:00+09:00");
+        assert_eq!(json["prefix"], "bob!b@host");
+        assert_eq!(json["command"], "PRIVMSG");
+        assert_eq!(json["tags"]["time"], "2024-03-01T12:00:00.000Z");
+        assert_eq!(json["tags"]["+draft/reply"], serde_json::Value::Null);
+        assert_eq!(json["plain"], "hi all");
+
+        let logged = parse(&raw).unwrap();
+        assert_eq!(logged.timestamp, line.timestamp);
+        assert_eq!(logged.sender, "bob");
+        assert_eq!(logged.text, line.text);
+    }
+
+    #[test]
+    fn lines_without_a_message_have_null_fields() {
+        let line = LogLine {
+            timestamp: Utc.with_ymd_and_hms(2024, 3, 1, 12, 0, 0).unwrap(),
+            network: "libera",
+            target: None,
+            sender: "*",
+            text: "Connected",
+            message: None,
+        };
+        let raw = record(&line, chrono_tz::UTC).unwrap();
+        let json: serde_json::Value = serde_json::from_str(&raw).unwrap();
+        for field in ["target", "prefix", "command", "raw"] {
+            assert_eq!(json[field], serde_json::Value::Null, "{field}");
+        }
+        assert_eq!(parse(&raw).unwrap().text, "Connected");
+
+        assert!(parse("not json").is_none());
+        assert!(parse("{\"sender\": \"bob\"}").is_none());
+    }
+}
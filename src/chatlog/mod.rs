@@ -0,0 +1,364 @@
+use chrono::{DateTime, Days, Utc};
+use chrono_tz::Tz;
+use hashbrown::HashMap;
+use irc::proto::Message;
+use regex::Regex;
+use serde::Deserialize;
+use std::{
+    cell::Cell,
+    io,
+    path::{Path, PathBuf},
+};
+
+use crate::{tui::mirc::Formatter, Config};
+
+mod files;
+mod jsonl;
+mod text;
+
+use files::{part_path, LogFiles};
+pub use jsonl::Record;
+
+/// Name a network's status buffer is logged under in path
+/// templates. Nicks can't start with a dash, so it never
+/// collides with a query.
+pub const STATUS_TARGET: &str = "-status";
+
+/// How many days of logs are read for backlog, for
+/// channels too quiet to fill the backlog in less.
+const HISTORY_DAYS: u64 = 90;
+
+/// How many days of logs a search looks through. Searches
+/// read on the main loop, so they are kept short.
+const SEARCH_DAYS: usize = 30;
+
+/// How lines are written to disk.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum LogFormat {
+    /// Human-readable lines, as in the path template.
+    Text,
+    /// A JSON object per line, next to the text
+    /// log but with `.jsonl` added to its name.
+    Jsonl,
+}
+
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct LogConfig {
+    /// Log every network unless its `clients` entry
+    /// says otherwise with `chatlog = false`.
+    pub enabled: bool,
+
+    /// Root directory for chat logs. Defaults
+    /// to `logs` in the data directory.
+    pub dir: Option<PathBuf>,
+
+    /// Path of each log file below `dir`. `{network}`,
+    /// `{target}` and `{date}` are filled in per line, so
+    /// a template with `{date}` starts a new file every day.
+    pub path: String,
+
+    /// `chrono` format string for `{date}`.
+    pub date_format: String,
+
+    /// Start a new numbered part once a file would
+    /// grow past this many bytes.
+    pub max_size: Option<u64>,
+
+    /// `chrono` format string for the timestamp of each line.
+    pub timestamp_format: String,
+
+    /// Write times in UTC instead of the `ui.tz` time zone.
+    /// Also decides which day `{date}` falls on.
+    pub utc: bool,
+
+    /// Which formats every line is written in.
+    pub formats: Vec<LogFormat>,
+
+    /// Lines of backlog read from the log into a buffer when
+    /// it's opened. Read from the JSONL log if there is one.
+    pub replay_lines: usize,
+}
+
+impl Default for LogConfig {
+    fn default() -> Self {
+        LogConfig {
+            enabled: false,
+            dir: None,
+            path: String::from("{network}/{target}/{date}.log"),
+            date_format: String::from("%Y-%m-%d"),
+            max_size: None,
+            timestamp_format: String::from("[%H:%M:%S]"),
+            utc: false,
+            formats: vec![LogFormat::Text],
+            replay_lines: 50,
+        }
+    }
+}
+
+/// A line shown in a chat buffer, as it is written to disk.
+#[derive(Clone, Copy, Debug)]
+pub struct LogLine<'a> {
+    pub timestamp: DateTime<Utc>,
+    pub network: &'a str,
+    /// Channel or query the line was shown in,
+    /// or `None` for the network's status buffer.
+    pub target: Option<&'a str>,
+    pub sender: &'a str,
+    /// Message text, including any mIRC formatting codes.
+    pub text: &'a str,
+    /// The message the line was made from,
+    /// if it was received from a server.
+    pub message: Option<&'a Message>,
+}
+
+/// A line read back from a log file.
+#[derive(Clone, Debug, Deserialize)]
+pub struct LoggedLine {
+    pub timestamp: DateTime<Utc>,
+    pub sender: String,
+    pub text: String,
+}
+
+/// Appends chat lines to files, one per network, target
+/// and format, as configured in the `[chatlog]` section.
+pub struct ChatLogger {
+    cfg: LogConfig,
+    dir: PathBuf,
+    tz: Tz,
+    /// Networks which opted in or out regardless of `enabled`.
+    networks: HashMap<String, bool>,
+    sinks: Vec<(LogFormat, LogFiles)>,
+}
+
+impl ChatLogger {
+    pub fn from_config(cfg: &Config) -> Self {
+        ChatLogger {
+            dir: cfg
+                .chatlog
+                .dir
+                .clone()
+                .unwrap_or_else(|| crate::paths::data_dir().join("logs")),
+            tz: if cfg.chatlog.utc { Tz::UTC } else { cfg.ui.tz },
+            networks: cfg
+                .clients
+                .iter()
+                .filter_map(|(name, client)| Some((name.clone(), client.chatlog?)))
+                .collect(),
+            sinks: cfg
+                .chatlog
+                .formats
+                .iter()
+                .map(|&format| (format, LogFiles::new(cfg.chatlog.max_size)))
+                .collect(),
+            cfg: cfg.chatlog.clone(),
+        }
+    }
+
+    /// A logger for searching the logs on another thread.
+    /// It writes nothing, having no files of its own.
+    pub fn reader(&self) -> ChatLogger {
+        ChatLogger {
+            cfg: self.cfg.clone(),
+            dir: self.dir.clone(),
+            tz: self.tz,
+            networks: self.networks.clone(),
+            sinks: Vec::new(),
+        }
+    }
+
+    /// Whether lines from `network` are written to disk.
+    pub fn is_enabled(&self, network: &str) -> bool {
+        self.networks
+            .get(network)
+            .copied()
+            .unwrap_or(self.cfg.enabled)
+    }
+
+    /// Append `line` to its log file, if its network is logged.
+    pub fn log(&mut self, line: &LogLine) -> io::Result<()> {
+        if !self.is_enabled(line.network) {
+            return Ok(());
+        }
+
+        let route = format!("{}\0{}", line.network, line.target.unwrap_or_default());
+        let path = self.path_for(line.network, line.target, line.timestamp);
+        for (format, files) in &mut self.sinks {
+            match format {
+                LogFormat::Text => {
+                    let record = text::record(line, self.tz, &self.cfg.timestamp_format);
+                    files.append(&route, &path, &record)?;
+                }
+                LogFormat::Jsonl => {
+                    let record = jsonl::record(line, self.tz)?;
+                    files.append(&route, &jsonl_path(&path), &record)?;
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// The last `replay_lines` lines logged for `target` on
+    /// `network`, oldest first. Unreadable lines are skipped.
+    pub fn backlog(&self, network: &str, target: Option<&str>) -> Vec<LoggedLine> {
+        let count = self.cfg.replay_lines;
+        if count == 0 || !self.is_enabled(network) {
+            return Vec::new();
+        }
+
+        let mut lines = Vec::new();
+        for day in self.history(network, target, &Cell::new(u64::MAX)) {
+            lines.splice(0..0, day);
+            if lines.len() >= count {
+                break;
+            }
+        }
+
+        lines.drain(..lines.len().saturating_sub(count));
+        lines
+    }
+
+    /// The last `limit` lines logged for `target` on `network`
+    /// whose text matches `re`, oldest first. Only the last
+    /// `SEARCH_DAYS` are read, and at most `budget` bytes of
+    /// them, taking what was read off `budget`.
+    pub fn search(
+        &self,
+        network: &str,
+        target: Option<&str>,
+        re: &Regex,
+        limit: usize,
+        budget: &Cell<u64>,
+    ) -> Vec<LoggedLine> {
+        if !self.is_enabled(network) {
+            return Vec::new();
+        }
+
+        let mut found: Vec<_> = self
+            .history(network, target, budget)
+            .take(SEARCH_DAYS)
+            .flat_map(|day| day.into_iter().rev())
+            .filter(|line| re.is_match(&Formatter::strip(&line.text)))
+            .take(limit)
+            .collect();
+        found.reverse();
+        found
+    }
+
+    /// What was logged for `target` on `network` a day at a time,
+    /// newest day first, read from the JSONL log if there is one.
+    /// Every day has a file of its own unless the template has
+    /// no `{date}`, in which case there is only the one. Files
+    /// are only read while they fit in what's left of `budget`
+    /// bytes, and what is read is taken off it.
+    fn history<'a>(
+        &'a self,
+        network: &'a str,
+        target: Option<&'a str>,
+        budget: &'a Cell<u64>,
+    ) -> impl Iterator<Item = Vec<LoggedLine>> + 'a {
+        let format = if self.cfg.formats.contains(&LogFormat::Jsonl) {
+            LogFormat::Jsonl
+        } else {
+            LogFormat::Text
+        };
+        let days = if self.cfg.path.contains("{date}") {
+            HISTORY_DAYS
+        } else {
+            0
+        };
+
+        let now = Utc::now();
+        (0..=days)
+            .map_while(move |days| now.checked_sub_days(Days::new(days)))
+            .map_while(move |at| {
+                if budget.get() == 0 {
+                    return None;
+                }
+                let base = match format {
+                    LogFormat::Text => self.path_for(network, target, at),
+                    LogFormat::Jsonl => jsonl_path(&self.path_for(network, target, at)),
+                };
+                let day = at.with_timezone(&self.tz).date_naive();
+
+                let mut logged = Vec::new();
+                for part in 0.. {
+                    let path = part_path(&base, part);
+                    let Ok(size) = std::fs::metadata(&path).map(|m| m.len()) else {
+                        break;
+                    };
+                    if size > budget.get() {
+                        // Nothing more is read once over budget.
+                        budget.set(0);
+                        break;
+                    }
+                    let Ok(raw) = std::fs::read_to_string(path) else {
+                        break;
+                    };
+                    budget.set(budget.get().saturating_sub(raw.len() as u64));
+                    logged.extend(raw.lines().filter_map(|l| match format {
+                        LogFormat::Text => text::parse(l, day, self.tz, &self.cfg.timestamp_format),
+                        LogFormat::Jsonl => jsonl::parse(l),
+                    }));
+                }
+                Some(logged)
+            })
+    }
+
+    /// Fill in the path template for a line sent at `timestamp`.
+    fn path_for(&self, network: &str, target: Option<&str>, timestamp: DateTime<Utc>) -> PathBuf {
+        let date = timestamp
+            .with_timezone(&self.tz)
+            .format(&self.cfg.date_format)
+            .to_string();
+        let target = target
+            .map(str::to_lowercase)
+            .unwrap_or_else(|| String::from(STATUS_TARGET));
+
+        self.dir.join(
+            self.cfg
+                .path
+                .replace("{network}", &sanitize(network))
+                .replace("{target}", &sanitize(&target))
+                .replace("{date}", &sanitize(&date)),
+        )
+    }
+}
+
+/// The JSONL log next to the text log at `path`. Names may
+/// have dots of their own, as in `#foo.bar`, so nothing of
+/// the name is replaced.
+fn jsonl_path(path: &Path) -> PathBuf {
+    let mut name = path.file_name().unwrap_or_default().to_owned();
+    name.push(".jsonl");
+    path.with_file_name(name)
+}
+
+/// Keep names from servers from escaping their directory.
+fn sanitize(name: &str) -> String {
+    let name = name.replace(['/', '\\', '\0'], "_");
+    if name.starts_with('.') {
+        name.replacen('.', "_", 1)
+    } else {
+        name
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::jsonl_path;
+    use std::path::Path;
+
+    #[test]
+    fn jsonl_logs_keep_the_whole_name() {
+        assert_eq!(
+            jsonl_path(Path::new("libera/#foo.bar")),
+            Path::new("libera/#foo.bar.jsonl")
+        );
+        assert_eq!(
+            jsonl_path(Path::new("libera/#rust/2024-01-01.log")),
+            Path::new("libera/#rust/2024-01-01.log.jsonl")
+        );
+    }
+}
@@ -0,0 +1,89 @@
+use chrono::{NaiveDate, NaiveDateTime, NaiveTime, TimeZone, Utc};
+use chrono_tz::Tz;
+
+use super::{LogLine, LoggedLine};
+use crate::tui::mirc::Formatter;
+
+/// `[12:34:56] <nick> text`, with formatting codes removed.
+pub fn record(line: &LogLine, tz: Tz, timestamp_format: &str) -> String {
+    format!(
+        "{} <{}> {}\n",
+        line.timestamp.with_timezone(&tz).format(timestamp_format),
+        line.sender,
+        Formatter::strip(line.text).replace(['\r', '\n'], " "),
+    )
+}
+
+/// Read back a line written by `record` to the log for `day`. Unless
+/// `timestamp_format` includes the date, lines are dated by their file.
+pub fn parse(raw: &str, day: NaiveDate, tz: Tz, timestamp_format: &str) -> Option<LoggedLine> {
+    // The timestamp may have a " <" of its own, but always as many.
+    let sample = tz
+        .from_utc_datetime(&day.and_time(NaiveTime::MIN))
+        .format(timestamp_format)
+        .to_string();
+    let (split, _) = raw.match_indices(" <").nth(sample.matches(" <").count())?;
+    let (timestamp, rest) = (&raw[..split], &raw[split + 2..]);
+    // Nicks can't contain '>' or spaces.
+    let (sender, text) = rest.split_once("> ")?;
+
+    let local = NaiveDateTime::parse_from_str(timestamp, timestamp_format)
+        .ok()
+        .or_else(|| {
+            NaiveTime::parse_from_str(timestamp, timestamp_format)
+                .ok()
+                .map(|t| day.and_time(t))
+        })
+        .unwrap_or_else(|| day.and_time(NaiveTime::MIN));
+
+    Some(LoggedLine {
+        timestamp: tz
+            .from_local_datetime(&local)
+            .earliest()?
+            .with_timezone(&Utc),
+        sender: sender.to_owned(),
+        text: text.to_owned(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{parse, record};
+    use crate::chatlog::LogLine;
+    use chrono::{NaiveDate, TimeZone, Utc};
+    use chrono_tz::Tz;
+
+    fn round_trip(timestamp_format: &str) {
+        let timestamp = Utc.with_ymd_and_hms(2024, 1, 2, 12, 34, 56).unwrap();
+        let raw = record(
+            &LogLine {
+                timestamp,
+                network: "libera",
+                target: Some("#eesh"),
+                sender: "nick",
+                text: "a <b> c",
+                message: None,
+            },
+            Tz::UTC,
+            timestamp_format,
+        );
+
+        let day = NaiveDate::from_ymd_opt(2024, 1, 2).unwrap();
+        let line = parse(raw.trim_end(), day, Tz::UTC, timestamp_format).unwrap();
+        assert_eq!(line.timestamp, timestamp);
+        assert_eq!(line.sender, "nick");
+        assert_eq!(line.text, "a <b> c");
+    }
+
+    #[test]
+    fn reads_back_what_was_written() {
+        round_trip("[%H:%M:%S]");
+        round_trip("%Y-%m-%d %H:%M:%S");
+    }
+
+    #[test]
+    fn timestamps_may_look_like_nicks() {
+        round_trip("%H:%M <%S>");
+        round_trip("<%Y-%m-%d> <%H:%M:%S>");
+    }
+}
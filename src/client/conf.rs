@@ -9,6 +9,10 @@ use serde::Deserialize;
 pub struct ClientConfig {
     pub default_quit: Option<String>,
 
+    /// Log this network's chat to disk, overriding
+    /// `enabled` in the `[chatlog]` section.
+    pub chatlog: Option<bool>,
+
     #[serde(flatten)]
     pub irc: Config,
 }
@@ -1,13 +1,288 @@
 use color_eyre::eyre::Result;
 use conf::ClientConfig;
+use chrono::{DateTime, Utc};
 use irc::client::{prelude::*, ClientStream};
+use irc::proto::{message::Tag, mode::ModeType};
+use hashbrown::HashSet;
+use std::{
+    fmt::Display,
+    time::{Duration, Instant},
+};
+
+use crate::tui::nickcolor::normalize;
 
 pub mod conf;
 
+/// Identifies a channel or query on a particular network.
+#[derive(Clone, Debug, PartialEq, Eq, Hash)]
+pub struct Target {
+    /// Name of the network, as keyed in the `clients` config.
+    pub network: String,
+
+    /// Channel name or nickname.
+    pub name: String,
+}
+
+/// Whether `name` is a channel rather than a nick,
+/// going by the prefixes RFC 2811 allows.
+pub fn is_channel(name: &str) -> bool {
+    name.starts_with(['#', '&', '+', '!'])
+}
+
+/// When a message was sent, according to the IRCv3 `server-time`
+/// tag, or `None` if the server didn't say.
+pub fn server_time(msg: &Message) -> Option<DateTime<Utc>> {
+    let Tag(_, value) = msg.tags.as_ref()?.iter().find(|Tag(key, _)| key == "time")?;
+    DateTime::parse_from_rfc3339(value.as_deref()?)
+        .ok()
+        .map(|t| t.with_timezone(&Utc))
+}
+
+/// What we know about a channel from the server.
+#[derive(Clone, Debug, Default)]
+pub struct ChannelState {
+    pub topic: Option<String>,
+
+    /// Who last changed the topic.
+    pub topic_set_by: Option<String>,
+    pub topic_set_at: Option<DateTime<Utc>>,
+
+    /// Channel modes, such as `+nt`.
+    pub modes: String,
+
+    /// Number of users in the channel, once NAMES has been received.
+    pub users: Option<usize>,
+
+    /// Normalized nicks of everyone in the channel.
+    pub members: HashSet<String>,
+
+    /// Whether the last NAMES reply has ended, so the
+    /// next one starts the member list afresh.
+    names_ended: bool,
+}
+
+impl ChannelState {
+    /// Name of the channel a message updates the state of, if any.
+    pub fn channel_of(msg: &Message) -> Option<&str> {
+        match &msg.command {
+            Command::TOPIC(channel, _)
+            | Command::JOIN(channel, ..)
+            | Command::PART(channel, _)
+            | Command::ChannelMODE(channel, _)
+            | Command::KICK(channel, ..) => Some(channel),
+            // 353: `<nick> <type> <channel> :<names>`.
+            Command::Response(Response::RPL_NAMREPLY, args) => args.get(2).map(String::as_str),
+            Command::Response(
+                Response::RPL_TOPIC
+                | Response::RPL_TOPICWHOTIME
+                | Response::RPL_NOTOPIC
+                | Response::RPL_CHANNELMODEIS
+                | Response::RPL_ENDOFNAMES,
+                args,
+            ) => args.get(1).map(String::as_str),
+            _ => None,
+        }
+    }
+
+    /// Update the state from a message about this channel,
+    /// received while we go by `own_nick`.
+    pub fn apply(&mut self, msg: &Message, own_nick: &str) {
+        let ours = |nick: &str| normalize(nick) == normalize(own_nick);
+        match &msg.command {
+            // Someone changed the topic while we were here.
+            Command::TOPIC(_, topic) => {
+                self.topic = topic.clone().filter(|t| !t.is_empty());
+                self.topic_set_by = msg.source_nickname().map(str::to_owned);
+                self.topic_set_at = Some(Utc::now());
+            }
+            // 332: the topic, sent when we join.
+            Command::Response(Response::RPL_TOPIC, args) => {
+                self.topic = args.get(2).cloned();
+            }
+            // 333: who set it and when, as a unix timestamp.
+            Command::Response(Response::RPL_TOPICWHOTIME, args) => {
+                self.topic_set_by = args.get(2).cloned();
+                self.topic_set_at = args
+                    .get(3)
+                    .and_then(|t| t.parse().ok())
+                    .and_then(|t| DateTime::from_timestamp(t, 0));
+            }
+            Command::Response(Response::RPL_NOTOPIC, _) => {
+                self.topic = None;
+                self.topic_set_by = None;
+                self.topic_set_at = None;
+            }
+            Command::ChannelMODE(_, changes) => {
+                apply_modes(&mut self.modes, changes, is_channel_setting);
+            }
+            // 324: `<nick> <channel> <modes> [<params>]`, all of them.
+            Command::Response(Response::RPL_CHANNELMODEIS, args) => {
+                let pieces: Vec<&str> = args.iter().skip(2).map(String::as_str).collect();
+                self.modes.clear();
+                if let Ok(modes) = Mode::as_channel_modes(&pieces) {
+                    apply_modes(&mut self.modes, &modes, is_channel_setting);
+                }
+            }
+            // 353: a page of members, repeated until 366.
+            Command::Response(Response::RPL_NAMREPLY, args) => {
+                if self.names_ended {
+                    self.members.clear();
+                    self.names_ended = false;
+                }
+                let names = args.get(3).map_or("", String::as_str);
+                self.members.extend(names.split_whitespace().map(normalize));
+                self.users = Some(self.members.len());
+            }
+            Command::Response(Response::RPL_ENDOFNAMES, _) => self.names_ended = true,
+            // Once we're gone, who is here is no longer known.
+            Command::PART(..) if msg.source_nickname().is_some_and(ours) => self.leave(),
+            Command::KICK(_, nicks, _) if nicks.split(',').any(ours) => self.leave(),
+            Command::KICK(_, nicks, _) => {
+                for nick in nicks.split(',') {
+                    self.members.remove(&normalize(nick));
+                }
+                self.users = Some(self.members.len());
+            }
+            Command::JOIN(..) => {
+                if let Some(nick) = msg.source_nickname() {
+                    self.members.insert(normalize(nick));
+                    self.users = Some(self.members.len());
+                }
+            }
+            // Quits and nick changes don't name a channel, so
+            // they are applied to every channel on the network.
+            Command::PART(..) | Command::QUIT(_) => {
+                if let Some(nick) = msg.source_nickname() {
+                    if self.members.remove(&normalize(nick)) {
+                        self.users = Some(self.members.len());
+                    }
+                }
+            }
+            Command::NICK(new) => {
+                if let Some(old) = msg.source_nickname() {
+                    if self.members.remove(&normalize(old)) {
+                        self.members.insert(normalize(new));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn leave(&mut self) {
+        self.members.clear();
+        self.users = None;
+        self.names_ended = false;
+    }
+
+    pub fn has_member(&self, nick: &str) -> bool {
+        self.members.contains(&normalize(nick))
+    }
+}
+
+/// Token of the PINGs sent to measure lag, which
+/// the server echoes back in its PONG.
+pub const LAG_TOKEN: &str = "eesh-lag";
+
+/// What we know about ourselves on a network.
+#[derive(Clone, Debug, Default)]
+pub struct NetworkStatus {
+    pub nick: String,
+
+    /// Our user modes, such as `+iw`.
+    pub modes: String,
+
+    /// Round trip time of the last PING, if one has been answered.
+    pub lag: Option<Duration>,
+
+    /// When the last PING with `LAG_TOKEN` was sent.
+    pub ping_sent: Option<Instant>,
+}
+
+impl NetworkStatus {
+    /// Update what we know about ourselves from a message.
+    pub fn apply(&mut self, msg: &Message) {
+        match &msg.command {
+            // 001: the server may not call us what we asked for.
+            Command::Response(Response::RPL_WELCOME, args) => {
+                if let Some(nick) = args.first() {
+                    self.nick = nick.clone();
+                }
+            }
+            Command::NICK(nick) if self.is_source(msg) => self.nick = nick.clone(),
+            Command::UserMODE(target, changes) if normalize(target) == normalize(&self.nick) => {
+                apply_modes(&mut self.modes, changes, |_| true);
+            }
+            // 221: `<nick> <modes>`, all of them.
+            Command::Response(Response::RPL_UMODEIS, args) => {
+                let pieces: Vec<&str> = args.iter().skip(1).map(String::as_str).collect();
+                self.modes.clear();
+                if let Ok(modes) = Mode::as_user_modes(&pieces) {
+                    apply_modes(&mut self.modes, &modes, |_| true);
+                }
+            }
+            // Servers differ on which parameter the token comes back in.
+            Command::PONG(first, second) => {
+                let token = second.as_deref().unwrap_or(first);
+                if token == LAG_TOKEN {
+                    self.lag = self.ping_sent.map(|sent| sent.elapsed());
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn is_source(&self, msg: &Message) -> bool {
+        msg.source_nickname()
+            .is_some_and(|source| normalize(source) == normalize(&self.nick))
+    }
+}
+
+/// Channel modes which are settings of the channel, rather
+/// than lists of masks or the status of its members.
+fn is_channel_setting(mode: &ChannelMode) -> bool {
+    use ChannelMode::*;
+    !matches!(
+        mode,
+        Ban | Exception | InviteException | Founder | Admin | Oper | Halfop | Voice
+    )
+}
+
+/// Apply MODE changes of the kinds `shown` lets through to a
+/// mode string such as `+nt`, keeping letters in the order
+/// they were set.
+fn apply_modes<T>(modes: &mut String, changes: &[Mode<T>], shown: impl Fn(&T) -> bool)
+where
+    T: ModeType + Display,
+{
+    let mut letters: Vec<char> = modes.trim_start_matches('+').chars().collect();
+    for change in changes {
+        let (Mode::Plus(mode, _) | Mode::Minus(mode, _) | Mode::NoPrefix(mode)) = change;
+        if !shown(mode) {
+            continue;
+        }
+        let Some(letter) = mode.to_string().chars().next() else {
+            continue;
+        };
+        letters.retain(|&c| c != letter);
+        if !matches!(change, Mode::Minus(..)) {
+            letters.push(letter);
+        }
+    }
+
+    modes.clear();
+    if !letters.is_empty() {
+        modes.push('+');
+        modes.extend(letters);
+    }
+}
+
 #[derive(Default)]
 pub struct ClientBuffer {}
 
 pub struct ConnectedClient {
+        /// Name of the network, as keyed in the `clients` config.
+        network: String,
         config: ClientConfig,
         client: Client,
         sender: Sender,
@@ -16,6 +291,15 @@ pub struct ConnectedClient {
 }
 
 impl ConnectedClient {
+    pub fn network(&self) -> &str {
+        &self.network
+    }
+
+    /// Queue a message to be sent to the server.
+    pub fn send(&self, message: impl Into<Message>) -> Result<()> {
+        Ok(self.sender.send(message)?)
+    }
+
     pub async fn disconnect(self) -> Result<DisconnectedClient> {
         self.client.send_quit(self.config.default_quit.unwrap_or("eesh.rsrvc.org".to_owned()))?;
 
@@ -24,13 +308,14 @@ impl ConnectedClient {
 }
 
 pub struct DisconnectedClient {
+        network: String,
         config: ClientConfig,
         buf: ClientBuffer,
 }
 
 impl DisconnectedClient {
-    pub fn new(config: ClientConfig) -> DisconnectedClient {
-        DisconnectedClient { config, buf: ClientBuffer::default() }
+    pub fn new(network: impl Into<String>, config: ClientConfig) -> DisconnectedClient {
+        DisconnectedClient { network: network.into(), config, buf: ClientBuffer::default() }
     }
 
     pub async fn connect(self) -> Result<ConnectedClient> {
@@ -38,7 +323,14 @@ impl DisconnectedClient {
         let sender = client.sender();
         let stream = client.stream()?;
 
-        Ok(ConnectedClient { config: self.config, client, sender, stream, buf: self.buf })
+        Ok(ConnectedClient {
+            network: self.network,
+            config: self.config,
+            client,
+            sender,
+            stream,
+            buf: self.buf,
+        })
     }
 }
 
@@ -0,0 +1,67 @@
+use color_eyre::Result;
+use hashbrown::HashMap;
+use serde::Deserialize;
+use std::path::Path;
+
+use crate::{
+    chatlog::LogConfig,
+    client::conf::ClientConfig,
+    highlight::HighlightConfig,
+    ignore::IgnoreConfig,
+    input::CommandAliases,
+    logging::LoggingConfig,
+    notify::NotifyConfig,
+    tui::{Theme, UIConfig},
+};
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct Config {
+    /// Command aliases such as SEND, ME, QUERY, and LEADER
+    pub alias: CommandAliases,
+
+    /// User preferences to dictate how the TUI renders.
+    pub ui: UIConfig,
+
+    /// Styles for every widget. Can be swapped for a
+    /// built-in theme or a theme file at runtime.
+    #[serde(default)]
+    pub theme: Theme,
+
+    /// Where and how chat is logged to disk.
+    #[serde(default)]
+    pub chatlog: LogConfig,
+
+    /// Which messages count as mentioning us.
+    #[serde(default)]
+    pub highlight: HighlightConfig,
+
+    /// Who to hide messages from.
+    #[serde(default)]
+    pub ignore: IgnoreConfig,
+
+    /// How mentions and private messages are notified of.
+    #[serde(default)]
+    pub notify: NotifyConfig,
+
+    /// How much of eesh's own diagnostics are logged.
+    #[serde(default)]
+    pub logging: LoggingConfig,
+
+    /// Configurations for connecting to IRC.
+    pub clients: HashMap<String, ClientConfig>,
+
+    /// Lua scripts to load at startup, keyed by script name.
+    #[cfg(feature = "lua")]
+    #[serde(default)]
+    pub scripts: HashMap<String, crate::script::ScriptConfig>,
+}
+
+impl Config {
+    pub fn parse_str(raw: &str) -> Result<Config> {
+        Ok(toml::from_str(raw)?)
+    }
+
+    pub fn parse(path: impl AsRef<Path>) -> Result<Config> {
+        Self::parse_str(&std::fs::read_to_string(path.as_ref())?)
+    }
+}
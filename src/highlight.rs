@@ -0,0 +1,161 @@
+use hashbrown::HashMap;
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+
+use crate::tui::nickcolor::{fold_case, normalize};
+
+/// Which incoming messages count as mentioning us, as
+/// configured in the `[highlight]` section.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct HighlightConfig {
+    /// Highlight messages containing our current nick.
+    pub nick: bool,
+
+    /// Further words that highlight a message, such
+    /// as a team name. Matched whole, ignoring case.
+    pub words: Vec<String>,
+
+    /// Regexes that highlight a message when they match.
+    #[serde(deserialize_with = "regexes")]
+    pub patterns: Vec<Regex>,
+
+    /// Nicks whose messages never highlight, keyed by channel
+    /// or query name. The key `*` applies everywhere, and a
+    /// `*` in a list ignores everyone there.
+    pub ignore: HashMap<String, Vec<String>>,
+}
+
+impl Default for HighlightConfig {
+    fn default() -> Self {
+        HighlightConfig {
+            nick: true,
+            words: Vec::new(),
+            patterns: Vec::new(),
+            ignore: HashMap::new(),
+        }
+    }
+}
+
+impl HighlightConfig {
+    /// Whether `text`, sent by `sender` to `channel` while we
+    /// go by `own_nick`, mentions us.
+    pub fn matches(&self, own_nick: &str, channel: &str, sender: &str, text: &str) -> bool {
+        let sender = normalize(sender);
+        if sender == normalize(own_nick) || self.ignores(channel, &sender) {
+            return false;
+        }
+
+        (self.nick && !own_nick.is_empty() && contains_word(text, own_nick))
+            || self.words.iter().any(|word| contains_word(text, word))
+            || self.patterns.iter().any(|re| re.is_match(text))
+    }
+
+    fn ignores(&self, channel: &str, sender: &str) -> bool {
+        let channel = normalize(channel);
+        self.ignore
+            .iter()
+            .filter(|(key, _)| *key == "*" || normalize(key) == channel)
+            .flat_map(|(_, nicks)| nicks)
+            .any(|nick| nick == "*" || normalize(nick) == sender)
+    }
+}
+
+/// Whether `word` appears in `text` on its own, rather than
+/// as part of a longer nick or word, ignoring case.
+fn contains_word(text: &str, word: &str) -> bool {
+    // Folding keeps byte offsets the same, unlike `normalize`.
+    let (text, word) = (fold_case(text), fold_case(word));
+    if word.is_empty() {
+        return false;
+    }
+
+    text.match_indices(&word).any(|(start, _)| {
+        let before = text[..start].chars().next_back();
+        let after = text[start + word.len()..].chars().next();
+        !before.is_some_and(is_word_char) && !after.is_some_and(is_word_char)
+    })
+}
+
+/// Characters that can continue a nick, so `eesh` isn't
+/// found in `eesh_` or `eesh[m]`.
+fn is_word_char(c: char) -> bool {
+    c.is_alphanumeric() || "-_{}|^`".contains(c)
+}
+
+fn regexes<'de, D>(deserializer: D) -> Result<Vec<Regex>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|pattern| Regex::new(pattern).map_err(serde::de::Error::custom))
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HighlightConfig;
+
+    fn config(toml: &str) -> HighlightConfig {
+        toml::from_str(toml).unwrap()
+    }
+
+    #[test]
+    fn own_nick_is_matched_as_a_whole_word() {
+        let cfg = HighlightConfig::default();
+        let mentions = |text| cfg.matches("eesh", "#rust", "bob", text);
+        assert!(mentions("eesh: ping"));
+        assert!(mentions("hey EESH!"));
+        assert!(mentions("ask eesh"));
+        assert!(!mentions("eesh_ is away"));
+        assert!(!mentions("eesh[m] said"));
+        assert!(!mentions("the eeshbot"));
+        // Not before we know our nick, nor from ourselves.
+        assert!(!cfg.matches("", "#rust", "bob", "anything"));
+        assert!(!cfg.matches("eesh", "#rust", "@EESH", "eesh"));
+    }
+
+    #[test]
+    fn words_and_patterns_highlight_too() {
+        let cfg = config(
+            r#"
+            nick = false
+            words = ["release", "team[a]"]
+            patterns = ['\bv\d+\.\d+']
+            "#,
+        );
+        let mentions = |text| cfg.matches("eesh", "#rust", "bob", text);
+        assert!(!mentions("eesh"));
+        assert!(mentions("Release day"));
+        assert!(mentions("ping team{A}"));
+        assert!(!mentions("prerelease"));
+        assert!(mentions("v1.2 is out"));
+        assert!(!mentions("v1 is out"));
+
+        assert!(toml::from_str::<HighlightConfig>("patterns = ['(']").is_err());
+    }
+
+    #[test]
+    fn ignored_senders_never_highlight() {
+        let cfg = config(
+            r##"
+            [ignore]
+            "*" = ["ChanServ"]
+            "#Spam" = ["*"]
+            "#rust" = ["@bot"]
+            "##,
+        );
+        let mentions = |channel, sender| cfg.matches("eesh", channel, sender, "hi eesh");
+        assert!(mentions("#rust", "bob"));
+        // `*` as a key applies in every channel.
+        assert!(!mentions("#rust", "chanserv"));
+        assert!(!mentions("#other", "ChanServ"));
+        // `*` in a list ignores everyone there, however
+        // the channel is written.
+        assert!(!mentions("#spam", "bob"));
+        // Nicks are compared normalized.
+        assert!(!mentions("#RUST", "Bot"));
+        assert!(mentions("#other", "bot"));
+    }
+}
@@ -0,0 +1,498 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use color_eyre::eyre::{bail, Result};
+use hashbrown::HashMap;
+use irc::proto::{Command, Prefix};
+use serde::{Deserialize, Serialize};
+use std::{fmt, path::PathBuf};
+
+use crate::{
+    tui::nickcolor::{fold_case, normalize},
+    Config,
+};
+
+/// Kinds of message an ignore rule can be limited to.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum MessageKind {
+    Message,
+    Notice,
+    /// `/me` messages, sent as CTCP ACTION.
+    Action,
+    Join,
+    Part,
+    Quit,
+}
+
+impl MessageKind {
+    /// The kind of `command`, if ignore rules apply to it.
+    pub fn of(command: &Command) -> Option<MessageKind> {
+        let kind = match command {
+            Command::PRIVMSG(_, text) if text.starts_with("\x01ACTION") => MessageKind::Action,
+            Command::PRIVMSG(..) => MessageKind::Message,
+            Command::NOTICE(..) => MessageKind::Notice,
+            Command::JOIN(..) => MessageKind::Join,
+            Command::PART(..) => MessageKind::Part,
+            Command::QUIT(_) => MessageKind::Quit,
+            _ => return None,
+        };
+        Some(kind)
+    }
+
+    fn parse(name: &str) -> Result<MessageKind> {
+        let kind = match name {
+            "message" | "privmsg" => MessageKind::Message,
+            "notice" => MessageKind::Notice,
+            "action" => MessageKind::Action,
+            "join" => MessageKind::Join,
+            "part" => MessageKind::Part,
+            "quit" => MessageKind::Quit,
+            _ => bail!(
+                "{name:?} is not a message kind, such as message, notice, action, join, part or quit"
+            ),
+        };
+        Ok(kind)
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            MessageKind::Message => "message",
+            MessageKind::Notice => "notice",
+            MessageKind::Action => "action",
+            MessageKind::Join => "join",
+            MessageKind::Part => "part",
+            MessageKind::Quit => "quit",
+        }
+    }
+}
+
+/// Hides messages from whoever matches `mask`, such as
+/// `spambot!*@*` or `*!*@*.example.com`. The lists narrow
+/// where the rule applies; left empty, it applies everywhere.
+#[derive(Clone, Debug, PartialEq, Eq, Serialize, Deserialize)]
+pub struct IgnoreRule {
+    /// A `nick!user@host` mask, where `*` matches any text and
+    /// `?` any one character. A bare nick means `nick!*@*`.
+    pub mask: String,
+
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub kinds: Vec<MessageKind>,
+
+    /// Channels, or query names, the rule applies in.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub channels: Vec<String>,
+
+    /// Networks, as keyed in the `clients` config.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub networks: Vec<String>,
+}
+
+impl IgnoreRule {
+    /// Parse a rule such as `spambot kinds=join,part channels=#rust`.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut words = spec.split_whitespace();
+        let Some(mask) = words.next() else {
+            bail!("An ignore rule needs a mask, such as nick or *!*@host");
+        };
+        let mut rule = IgnoreRule {
+            mask: mask.to_owned(),
+            kinds: Vec::new(),
+            channels: Vec::new(),
+            networks: Vec::new(),
+        };
+
+        let list = |value: &str| value.split(',').map(str::to_owned).collect();
+        for word in words {
+            if let Some(kinds) = word.strip_prefix("kinds=") {
+                rule.kinds = kinds
+                    .split(',')
+                    .map(MessageKind::parse)
+                    .collect::<Result<_>>()?;
+            } else if let Some(channels) = word.strip_prefix("channels=") {
+                rule.channels = list(channels);
+            } else if let Some(networks) = word.strip_prefix("networks=") {
+                rule.networks = list(networks);
+            } else {
+                bail!("{word:?} should be kinds=, channels= or networks=");
+            }
+        }
+        Ok(rule)
+    }
+
+    /// Whether the rule hides a message of `kind` from `source`, a
+    /// full `nick!user@host`, sent to `channel` on `network`.
+    pub fn matches(&self, network: &str, channel: &str, kind: MessageKind, source: &str) -> bool {
+        (self.networks.is_empty() || self.networks.iter().any(|n| n == network))
+            && (self.kinds.is_empty() || self.kinds.contains(&kind))
+            && (self.channels.is_empty()
+                || self
+                    .channels
+                    .iter()
+                    .any(|c| fold_case(c) == fold_case(channel)))
+            && wildcard(&fold_case(&full_mask(&self.mask)), &fold_case(source))
+    }
+}
+
+impl fmt::Display for IgnoreRule {
+    /// The rule as it would be typed.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.mask)?;
+        if !self.kinds.is_empty() {
+            let kinds: Vec<_> = self.kinds.iter().map(|k| k.name()).collect();
+            write!(f, " kinds={}", kinds.join(","))?;
+        }
+        if !self.channels.is_empty() {
+            write!(f, " channels={}", self.channels.join(","))?;
+        }
+        if !self.networks.is_empty() {
+            write!(f, " networks={}", self.networks.join(","))?;
+        }
+        Ok(())
+    }
+}
+
+/// Fill in the parts of a mask left out, so
+/// `nick` becomes `nick!*@*`.
+fn full_mask(mask: &str) -> String {
+    match (mask.contains('!'), mask.contains('@')) {
+        (true, true) => mask.to_owned(),
+        (true, false) => format!("{mask}@*"),
+        (false, true) => format!("*!{mask}"),
+        (false, false) => format!("{mask}!*@*"),
+    }
+}
+
+/// Whether `text` matches `pattern`, where `*` matches
+/// any text and `?` any one character.
+fn wildcard(pattern: &str, text: &str) -> bool {
+    let (pattern, text): (Vec<char>, Vec<char>) =
+        (pattern.chars().collect(), text.chars().collect());
+    let (mut p, mut t) = (0, 0);
+    // Where the last `*` was, and how much of the text it took.
+    let mut star = None;
+
+    while t < text.len() {
+        match pattern.get(p) {
+            Some('*') => {
+                star = Some((p, t));
+                p += 1;
+            }
+            Some(&c) if c == '?' || c == text[t] => {
+                p += 1;
+                t += 1;
+            }
+            _ => match star {
+                // Let the `*` take one more character.
+                Some((star_p, star_t)) => {
+                    star = Some((star_p, star_t + 1));
+                    p = star_p + 1;
+                    t = star_t + 1;
+                }
+                None => return false,
+            },
+        }
+    }
+    pattern[p..].iter().all(|&c| c == '*')
+}
+
+/// The `nick!user@host` of a message's sender,
+/// or the name of the server that sent it.
+pub fn mask_of(prefix: &Prefix) -> String {
+    match prefix {
+        Prefix::ServerName(name) => name.clone(),
+        Prefix::Nickname(nick, user, host) => format!("{nick}!{user}@{host}"),
+    }
+}
+
+/// Who to hide, and whether to hide comings and goings of
+/// the quiet, as configured in the `[ignore]` section.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct IgnoreConfig {
+    pub rules: Vec<IgnoreRule>,
+
+    /// Hide joins, parts and quits of users who
+    /// haven't spoken in the channel recently.
+    pub smart_filter: bool,
+
+    /// How many minutes count as recently.
+    pub smart_filter_mins: u32,
+}
+
+impl Default for IgnoreConfig {
+    fn default() -> Self {
+        IgnoreConfig {
+            rules: Vec::new(),
+            smart_filter: false,
+            smart_filter_mins: 30,
+        }
+    }
+}
+
+/// The rules added and removed with commands, as saved.
+#[derive(Default, Serialize, Deserialize)]
+struct SavedRules {
+    #[serde(default)]
+    rules: Vec<IgnoreRule>,
+}
+
+/// Every ignore rule in effect: those in the config file,
+/// then those added with commands, which are saved to
+/// `ignore.toml` in the data directory.
+pub struct IgnoreList {
+    configured: Vec<IgnoreRule>,
+    added: Vec<IgnoreRule>,
+    path: PathBuf,
+}
+
+impl IgnoreList {
+    pub fn from_config(cfg: &Config) -> Self {
+        IgnoreList {
+            configured: cfg.ignore.rules.clone(),
+            added: Vec::new(),
+            path: crate::paths::data_dir().join("ignore.toml"),
+        }
+    }
+
+    /// Read the rules saved by earlier sessions, if any.
+    pub fn load(&mut self) -> Result<()> {
+        if !self.path.exists() {
+            return Ok(());
+        }
+        let saved: SavedRules = toml::from_str(&std::fs::read_to_string(&self.path)?)?;
+        self.added = saved.rules;
+        Ok(())
+    }
+
+    fn save(&self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+        let saved = SavedRules {
+            rules: self.added.clone(),
+        };
+        std::fs::write(&self.path, toml::to_string(&saved)?)?;
+        Ok(())
+    }
+
+    /// Every rule, numbered from 1 as `remove` takes them.
+    pub fn rules(&self) -> impl Iterator<Item = (usize, &IgnoreRule)> {
+        self.configured
+            .iter()
+            .chain(&self.added)
+            .enumerate()
+            .map(|(i, rule)| (i + 1, rule))
+    }
+
+    pub fn add(&mut self, rule: IgnoreRule) -> Result<()> {
+        if self.rules().any(|(_, r)| *r == rule) {
+            bail!("{rule} is already ignored");
+        }
+        self.added.push(rule);
+        self.save()
+    }
+
+    /// Remove the rule numbered `which`, or every added rule
+    /// with `which` as its mask, returning what was removed.
+    pub fn remove(&mut self, which: &str) -> Result<Vec<IgnoreRule>> {
+        let removed = match which.parse::<usize>() {
+            Ok(n) if n >= 1 && n <= self.configured.len() => {
+                bail!("Rule {n} is in the config file, and has to be removed from there")
+            }
+            Ok(n) => match n.checked_sub(self.configured.len() + 1) {
+                Some(i) if i < self.added.len() => vec![self.added.remove(i)],
+                _ => bail!("There is no ignore rule {n}"),
+            },
+            Err(_) => {
+                let (removed, kept) = self.added.drain(..).partition(|r| r.mask == which);
+                self.added = kept;
+                removed
+            }
+        };
+        if removed.is_empty() {
+            bail!("No ignore rule added with a command has the mask {which}");
+        }
+        self.save()?;
+        Ok(removed)
+    }
+
+    /// Whether any rule hides a message of `kind` from
+    /// `source` sent to `channel` on `network`.
+    pub fn ignores(&self, network: &str, channel: &str, kind: MessageKind, source: &str) -> bool {
+        self.rules()
+            .any(|(_, rule)| rule.matches(network, channel, kind, source))
+    }
+}
+
+/// Remembers when people last spoke in each channel, to
+/// hide the comings and goings of those who haven't lately.
+pub struct SmartFilter {
+    enabled: bool,
+    window: TimeDelta,
+    /// Keyed by network, then normalized channel and nick.
+    spoke: HashMap<(String, String, String), DateTime<Utc>>,
+}
+
+impl SmartFilter {
+    pub fn from_config(cfg: &Config) -> Self {
+        SmartFilter {
+            enabled: cfg.ignore.smart_filter,
+            window: TimeDelta::minutes(i64::from(cfg.ignore.smart_filter_mins)),
+            spoke: HashMap::new(),
+        }
+    }
+
+    pub fn spoke(&mut self, network: &str, channel: &str, nick: &str, at: DateTime<Utc>) {
+        if !self.enabled {
+            return;
+        }
+        let key = (network.to_owned(), normalize(channel), normalize(nick));
+        self.spoke.insert(key, at);
+
+        // Forget anyone who has gone quiet now and then.
+        if self.spoke.len().is_multiple_of(1024) {
+            self.spoke.retain(|_, last| at - *last < self.window);
+        }
+    }
+
+    /// Whether a join, part or quit of `nick` at `at` is hidden.
+    pub fn hides(&self, network: &str, channel: &str, nick: &str, at: DateTime<Utc>) -> bool {
+        if !self.enabled {
+            return false;
+        }
+        let key = (network.to_owned(), normalize(channel), normalize(nick));
+        self.spoke
+            .get(&key)
+            .is_none_or(|last| at - *last >= self.window)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rule(spec: &str) -> IgnoreRule {
+        IgnoreRule::parse(spec).unwrap()
+    }
+
+    fn list(name: &str, configured: &[&str], added: &[&str]) -> IgnoreList {
+        let path = std::env::temp_dir().join(format!("eesh-{name}-{}.toml", std::process::id()));
+        IgnoreList {
+            configured: configured.iter().map(|spec| rule(spec)).collect(),
+            added: added.iter().map(|spec| rule(spec)).collect(),
+            path,
+        }
+    }
+
+    fn smart_filter(enabled: bool) -> SmartFilter {
+        SmartFilter {
+            enabled,
+            window: TimeDelta::minutes(30),
+            spoke: HashMap::new(),
+        }
+    }
+
+    #[test]
+    fn stars_backtrack_to_find_a_match() {
+        assert!(wildcard("a*b*c", "axxbyybzc"));
+        assert!(wildcard("*!*@*.example.com", "nick!user@host.example.com"));
+        assert!(wildcard("*", ""));
+        assert!(wildcard("a**", "a"));
+        assert!(!wildcard("a*b*c", "axxbyy"));
+        assert!(!wildcard("*.example.com", "example.com"));
+    }
+
+    #[test]
+    fn question_marks_match_exactly_one_character() {
+        assert!(wildcard("n?ck", "nick"));
+        assert!(wildcard("n?ck", "nyck"));
+        assert!(!wildcard("n?ck", "nck"));
+        assert!(!wildcard("n?ck", "niick"));
+    }
+
+    #[test]
+    fn bare_masks_are_filled_in() {
+        assert_eq!(full_mask("nick"), "nick!*@*");
+        assert_eq!(full_mask("nick!user"), "nick!user@*");
+        assert_eq!(full_mask("user@host"), "*!user@host");
+        assert_eq!(full_mask("nick!user@host"), "nick!user@host");
+    }
+
+    #[test]
+    fn parses_rules_as_they_are_displayed() {
+        let spec = "spam* kinds=join,part channels=#rust,#eesh networks=libera";
+        let parsed = rule(spec);
+        assert_eq!(parsed.mask, "spam*");
+        assert_eq!(parsed.kinds, [MessageKind::Join, MessageKind::Part]);
+        assert_eq!(parsed.channels, ["#rust", "#eesh"]);
+        assert_eq!(parsed.networks, ["libera"]);
+        assert_eq!(parsed.to_string(), spec);
+
+        assert_eq!(rule("  nick  ").to_string(), "nick");
+        assert!(IgnoreRule::parse("").is_err());
+        assert!(IgnoreRule::parse("nick kinds=kick").is_err());
+        assert!(IgnoreRule::parse("nick #rust").is_err());
+    }
+
+    #[test]
+    fn rules_match_within_their_scope() {
+        let scoped = rule("spambot kinds=join channels=#Rust networks=libera");
+        let source = "SpamBot!bot@example.com";
+        assert!(scoped.matches("libera", "#rust", MessageKind::Join, source));
+        assert!(!scoped.matches("oftc", "#rust", MessageKind::Join, source));
+        assert!(!scoped.matches("libera", "#eesh", MessageKind::Join, source));
+        assert!(!scoped.matches("libera", "#rust", MessageKind::Message, source));
+        assert!(!scoped.matches(
+            "libera",
+            "#rust",
+            MessageKind::Join,
+            "other!bot@example.com"
+        ));
+    }
+
+    #[test]
+    fn removes_added_rules_by_number() {
+        let mut ignores = list("by-number", &["configured"], &["first", "second"]);
+        assert!(ignores.remove("1").is_err());
+        assert!(ignores.remove("4").is_err());
+        assert!(ignores.remove("0").is_err());
+
+        let removed = ignores.remove("2").unwrap();
+        assert_eq!(removed, [rule("first")]);
+        let left: Vec<_> = ignores.rules().map(|(n, r)| (n, r.mask.as_str())).collect();
+        assert_eq!(left, [(1, "configured"), (2, "second")]);
+        std::fs::remove_file(&ignores.path).unwrap();
+    }
+
+    #[test]
+    fn removes_added_rules_by_mask() {
+        let mut ignores = list(
+            "by-mask",
+            &["spam"],
+            &["spam kinds=join", "spam kinds=part", "eggs"],
+        );
+        assert_eq!(ignores.remove("spam").unwrap().len(), 2);
+        assert_eq!(ignores.rules().count(), 2);
+        assert!(ignores.remove("spam").is_err());
+        std::fs::remove_file(&ignores.path).unwrap();
+    }
+
+    #[test]
+    fn smart_filter_hides_only_the_quiet() {
+        let now = Utc::now();
+        let mut filter = smart_filter(true);
+        filter.spoke("libera", "#rust", "Chatty", now);
+
+        assert!(!filter.hides("libera", "#Rust", "chatty", now + TimeDelta::minutes(29)));
+        assert!(filter.hides("libera", "#rust", "chatty", now + TimeDelta::minutes(30)));
+        assert!(filter.hides("libera", "#eesh", "chatty", now));
+        assert!(filter.hides("oftc", "#rust", "chatty", now));
+        assert!(filter.hides("libera", "#rust", "lurker", now));
+    }
+
+    #[test]
+    fn disabled_smart_filter_hides_nothing() {
+        let mut filter = smart_filter(false);
+        filter.spoke("libera", "#rust", "chatty", Utc::now());
+        assert!(!filter.hides("libera", "#rust", "lurker", Utc::now()));
+    }
+}
@@ -1,6 +1,9 @@
 use color_eyre::eyre::Result;
 use irc::proto::Message;
 use ratatui::widgets::ScrollDirection;
+use std::path::Path;
+
+use crate::ignore::IgnoreRule;
 
 /// Software primitives for changing the
 /// UI state.
@@ -16,4 +19,65 @@ pub trait Api {
 
     /// Send a message to a given channel.
     fn send_message<M: Into<Message>>(&mut self, server: &str, channel: &str, message: M) -> Result<()>;
+
+    /// Send text typed into the input bar, including any
+    /// formatting codes, to the buffer currently in focus.
+    fn send_text(&mut self, text: &str) -> Result<()>;
+
+    /// Switch to a built-in theme, a theme file, or
+    /// back to the `[theme]` section of the config.
+    fn set_theme(&mut self, name: &str) -> Result<()>;
+
+    /// Show or hide diagnostics over the focused buffer.
+    fn toggle_debug_overlay(&mut self);
+
+    /// Switch the topic bar between a single line and the full topic.
+    fn toggle_topic(&mut self);
+
+    /// Scroll a topic too long for a single line sideways.
+    fn scroll_topic(&mut self, direction: ScrollDirection);
+
+    /// Show only the lines of the focused buffer matching a
+    /// filter such as `level=warn target=irc /re/`, or every
+    /// line again if `spec` is empty.
+    fn set_filter(&mut self, spec: &str) -> Result<()>;
+
+    /// Stop or resume moving the focused buffer down to new lines.
+    fn toggle_follow(&mut self);
+
+    /// Write the lines of the focused buffer
+    /// its filter lets through to a file.
+    fn export_buffer(&mut self, path: &Path) -> Result<()>;
+
+    /// Highlight `text` in the focused buffer and jump to its
+    /// last occurrence, or stop highlighting if it's empty.
+    fn find(&mut self, text: &str);
+
+    /// Jump to the next older (`Backward`) or
+    /// newer (`Forward`) occurrence.
+    fn find_next(&mut self, direction: ScrollDirection);
+
+    /// List every line containing `text`, in every buffer
+    /// and the chat log, in a buffer of results.
+    fn search(&mut self, text: &str);
+
+    /// Go to the buffer and line search result `n` came from.
+    fn jump(&mut self, n: usize) -> Result<()>;
+
+    /// Focus buffer `which`, by its number or name.
+    fn switch_buffer(&mut self, which: &str) -> Result<()>;
+
+    /// Hide messages matching `rule` from now on,
+    /// and save it for later sessions.
+    fn ignore(&mut self, rule: IgnoreRule) -> Result<()>;
+
+    /// List every ignore rule in effect, numbered.
+    fn list_ignores(&mut self);
+
+    /// Remove the ignore rule numbered `which`, or
+    /// those with `which` as their mask.
+    fn unignore(&mut self, which: &str) -> Result<()>;
+
+    /// Hand a leader command the application doesn't recognize to plugins.
+    fn plugin_command(&mut self, name: &str, args: &[String]) -> Result<()>;
 }
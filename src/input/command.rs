@@ -0,0 +1,154 @@
+use color_eyre::eyre::{bail, Result};
+use ratatui::widgets::ScrollDirection;
+use std::path::PathBuf;
+
+use super::Api;
+use crate::ignore::IgnoreRule;
+
+/// Commands typed after the leader key,
+/// e.g. `,q<enter>`.
+#[derive(Debug, PartialEq)]
+pub enum ClientCommand {
+    /// `q` or `quit`: exit the application.
+    Quit,
+
+    /// `theme <name>`: switch themes. `config` selects
+    /// the `[theme]` section of the config file.
+    Theme(String),
+
+    /// `debug`: toggle the diagnostics overlay.
+    Debug,
+
+    /// `topic`: show the whole topic and who set it, or
+    /// collapse it again. `topic left` and `topic right`
+    /// scroll a collapsed topic.
+    Topic(Option<ScrollDirection>),
+
+    /// `filter [spec]`: narrow the focused buffer, e.g.
+    /// `filter level=debug target=irc /join|part/`.
+    /// Without a spec, the filter is cleared.
+    Filter(String),
+
+    /// `follow`: stop or resume moving down to new lines.
+    Follow,
+
+    /// `export <path>`: write the filtered buffer to a file.
+    Export(PathBuf),
+
+    /// `/<text>`: highlight text in the focused buffer and
+    /// jump to its last occurrence. `/` alone stops.
+    Find(String),
+
+    /// `prev` and `next`: jump to the previous or next occurrence.
+    FindNext(ScrollDirection),
+
+    /// `search <text>`: list every line containing text, in
+    /// every buffer and the chat log, in a buffer of results.
+    Search(String),
+
+    /// `jump <n>`: go to where search result `n` came from.
+    Jump(usize),
+
+    /// `buffer <n|name>` or `b`: focus buffer `n`, counting
+    /// from 1, or the one named, e.g. `#rust`, `libera/#rust`,
+    /// `status`, `mentions` or `search`.
+    Buffer(String),
+
+    /// `ignore [rule]`: hide messages from whoever matches a
+    /// rule such as `spambot kinds=join,part channels=#rust`.
+    /// Without a rule, the rules in effect are listed.
+    Ignore(Option<IgnoreRule>),
+
+    /// `unignore <n|mask>`: remove an ignore rule added
+    /// with `ignore`, by its number or mask.
+    Unignore(String),
+
+    /// Anything else is offered to plugins, e.g.
+    /// `store <script>` for the Lua runtime.
+    Plugin { name: String, args: Vec<String> },
+}
+
+impl ClientCommand {
+    pub fn parse(line: &str) -> Result<ClientCommand> {
+        if let Some(text) = line.trim_start().strip_prefix('/') {
+            return Ok(ClientCommand::Find(text.trim().to_owned()));
+        }
+        // Text arguments are taken as typed, spaces and all.
+        let rest = line
+            .trim_start()
+            .split_once(char::is_whitespace)
+            .map_or("", |(_, rest)| rest.trim())
+            .to_owned();
+
+        let mut words = line.split_whitespace();
+        let Some(name) = words.next() else {
+            bail!("No command was given");
+        };
+
+        let cmd = match name.to_lowercase().as_str() {
+            "q" | "quit" => ClientCommand::Quit,
+            "theme" => match words.next() {
+                Some(name) => ClientCommand::Theme(name.to_owned()),
+                None => bail!("Usage: theme <name>"),
+            },
+            "debug" => ClientCommand::Debug,
+            "topic" => match words.next() {
+                None => ClientCommand::Topic(None),
+                Some("left") => ClientCommand::Topic(Some(ScrollDirection::Backward)),
+                Some("right") => ClientCommand::Topic(Some(ScrollDirection::Forward)),
+                Some(_) => bail!("Usage: topic [left|right]"),
+            },
+            "filter" => ClientCommand::Filter(rest),
+            "follow" => ClientCommand::Follow,
+            "export" if rest.is_empty() => bail!("Usage: export <path>"),
+            "export" => ClientCommand::Export(PathBuf::from(rest)),
+            "next" => ClientCommand::FindNext(ScrollDirection::Forward),
+            "prev" => ClientCommand::FindNext(ScrollDirection::Backward),
+            "search" if rest.is_empty() => bail!("Usage: search <text>"),
+            "search" => ClientCommand::Search(rest),
+            "ignore" if rest.is_empty() => ClientCommand::Ignore(None),
+            "ignore" => ClientCommand::Ignore(Some(IgnoreRule::parse(&rest)?)),
+            "unignore" => match words.next() {
+                Some(which) => ClientCommand::Unignore(which.to_owned()),
+                None => bail!("Usage: unignore <n|mask>"),
+            },
+            "jump" => match words.next().and_then(|n| n.parse().ok()) {
+                Some(n) => ClientCommand::Jump(n),
+                None => bail!("Usage: jump <result number>"),
+            },
+            "b" | "buffer" => match words.next() {
+                Some(which) => ClientCommand::Buffer(which.to_owned()),
+                None => bail!("Usage: buffer <n|name>"),
+            },
+            other => ClientCommand::Plugin {
+                name: other.to_owned(),
+                args: words.map(str::to_owned).collect(),
+            },
+        };
+
+        Ok(cmd)
+    }
+
+    pub fn execute(self, api: &mut impl Api) -> Result<()> {
+        match self {
+            ClientCommand::Quit => api.exit(),
+            ClientCommand::Theme(name) => api.set_theme(&name)?,
+            ClientCommand::Debug => api.toggle_debug_overlay(),
+            ClientCommand::Topic(None) => api.toggle_topic(),
+            ClientCommand::Topic(Some(direction)) => api.scroll_topic(direction),
+            ClientCommand::Filter(spec) => api.set_filter(&spec)?,
+            ClientCommand::Follow => api.toggle_follow(),
+            ClientCommand::Export(path) => api.export_buffer(&path)?,
+            ClientCommand::Find(text) => api.find(&text),
+            ClientCommand::FindNext(direction) => api.find_next(direction),
+            ClientCommand::Search(text) => api.search(&text),
+            ClientCommand::Jump(n) => api.jump(n)?,
+            ClientCommand::Buffer(which) => api.switch_buffer(&which)?,
+            ClientCommand::Ignore(Some(rule)) => api.ignore(rule)?,
+            ClientCommand::Ignore(None) => api.list_ignores(),
+            ClientCommand::Unignore(which) => api.unignore(&which)?,
+            ClientCommand::Plugin { name, args } => api.plugin_command(&name, &args)?,
+        }
+        Ok(())
+    }
+}
@@ -1,13 +1,19 @@
+use color_eyre::eyre::Result;
 use hashbrown::HashMap;
 use ratatui::crossterm::event::{KeyCode, KeyEvent, KeyModifiers};
 use std::fmt::{Display, Write};
+use tracing::warn;
+
+use crate::tui::mirc;
 
 mod api;
+mod command;
 mod lexer;
 pub use api::Api;
+pub use command::ClientCommand;
 use serde::Deserialize;
 
-#[derive(Clone, Debug, Deserialize)]
+#[derive(Clone, Debug, Default, Deserialize)]
 pub struct CommandAliases(HashMap<String, String>);
 
 impl CommandAliases {
@@ -28,13 +34,18 @@ impl CommandAliases {
 /// converting keypresses into application
 /// commands which in turn change the state
 /// of the application.
+#[derive(Default)]
 pub struct InputHandler {
     motion: Vec<KeyEvent>,
+    aliases: CommandAliases,
 }
 
 impl InputHandler {
-    pub fn new() -> Self {
-        InputHandler { motion: Vec::new() }
+    pub fn new(aliases: CommandAliases) -> Self {
+        InputHandler {
+            motion: Vec::new(),
+            aliases,
+        }
     }
 
     /// Resets the motion recording to EMPTY.
@@ -55,19 +66,60 @@ impl InputHandler {
     }
 
     /// Parse the current input buffer and execute any changes
-    /// to the app state it defines.
-    fn evaluate(&mut self, api: &mut impl api::Api) {
-        todo!()
+    /// to the app state it defines. Nothing happens until the
+    /// buffer has been submitted with the enter key.
+    pub fn evaluate(&mut self, api: &mut impl api::Api) -> Result<()> {
+        if self.motion.last().map(|ke| ke.code) != Some(KeyCode::Enter) {
+            return Ok(());
+        }
+        self.motion.pop();
+
+        let line = self.to_string();
+        self.clear();
+
+        let leader = self.aliases.get("leader").unwrap_or(",");
+        if let Some(cmd) = line.strip_prefix(leader) {
+            // A mistyped command shouldn't bring down the whole
+            // app, so failures are only reported to the log.
+            if let Err(e) = ClientCommand::parse(cmd).and_then(|cmd| cmd.execute(api)) {
+                warn!("{e}");
+            }
+        } else if !line.is_empty() {
+            if let Err(e) = api.send_text(&line) {
+                warn!("{e}");
+            }
+        }
+
+        Ok(())
+    }
+
+    /// The mIRC control code a key chord inserts, if any.
+    fn format_code(ke: &KeyEvent) -> Option<char> {
+        if !ke.modifiers.contains(KeyModifiers::CONTROL) {
+            return None;
+        }
+
+        match ke.code {
+            KeyCode::Char('b') => Some(mirc::BOLD),
+            KeyCode::Char('k') => Some(mirc::COLOR),
+            KeyCode::Char('u') => Some(mirc::UNDERLINE),
+            KeyCode::Char('i') => Some(mirc::ITALIC),
+            KeyCode::Char('o') => Some(mirc::RESET),
+            _ => None,
+        }
     }
 }
 
 impl Display for InputHandler {
     /// Display the user-line, the current input
     /// buffer as a string of text recognizable
-    /// to the user.
+    /// to the user. Formatting chords are written
+    /// as the raw mIRC control codes they insert.
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         for ke in &self.motion {
-            if let KeyCode::Char(c) = &ke.code {
+            if let Some(code) = Self::format_code(ke) {
+                f.write_char(code)?;
+            } else if let KeyCode::Char(c) = &ke.code {
                 if ke.modifiers.contains(KeyModifiers::CONTROL) {
                     f.write_char('^')?;
                 }
@@ -117,3 +169,95 @@ impl Motion {
     }
 }
 */
+
+#[cfg(test)]
+mod tests {
+    use super::InputHandler;
+    use crate::tui::mirc::{self, ColorDepth, Formatter};
+    use ratatui::{
+        crossterm::event::{KeyCode, KeyEvent, KeyModifiers},
+        style::{Color, Modifier, Style},
+        text::Span,
+    };
+
+    fn typed(keys: &[KeyEvent]) -> String {
+        let mut input = InputHandler::default();
+        for key in keys {
+            input.append(*key);
+        }
+        input.to_string()
+    }
+
+    fn key(c: char) -> KeyEvent {
+        KeyEvent::from(KeyCode::Char(c))
+    }
+
+    fn ctrl(c: char) -> KeyEvent {
+        KeyEvent::new(KeyCode::Char(c), KeyModifiers::CONTROL)
+    }
+
+    #[test]
+    fn chords_insert_format_codes() {
+        let codes = ['b', 'k', 'u', 'i', 'o', 'x'].map(|c| InputHandler::format_code(&ctrl(c)));
+        assert_eq!(
+            codes,
+            [
+                Some(mirc::BOLD),
+                Some(mirc::COLOR),
+                Some(mirc::UNDERLINE),
+                Some(mirc::ITALIC),
+                Some(mirc::RESET),
+                None,
+            ]
+        );
+        assert_eq!(InputHandler::format_code(&key('b')), None);
+
+        // Other chords are shown as typed.
+        assert_eq!(
+            typed(&[key('a'), ctrl('b'), key('b'), ctrl('x')]),
+            "a\x02b^x"
+        );
+        let backspace = KeyEvent::from(KeyCode::Backspace);
+        assert_eq!(typed(&[ctrl('b'), key('b'), backspace, backspace]), "");
+    }
+
+    #[test]
+    fn preview_shows_codes_beside_their_effect() {
+        let line = typed(&[
+            key('a'),
+            ctrl('b'),
+            key('b'),
+            ctrl('k'),
+            key('4'),
+            key('r'),
+            ctrl('o'),
+            key('x'),
+        ]);
+        let marker = Style::default().add_modifier(Modifier::REVERSED);
+        let bold = Style::default().add_modifier(Modifier::BOLD);
+        assert_eq!(
+            Formatter::new(ColorDepth::Ansi16, false)
+                .preview(&line)
+                .spans,
+            [
+                Span::raw("a"),
+                Span::styled("B", marker),
+                Span::styled("b", bold),
+                Span::styled("C4", marker),
+                Span::styled("r", bold.fg(Color::LightRed)),
+                Span::styled("O", marker),
+                Span::raw("x"),
+            ]
+        );
+        // Sent without the markers.
+        assert_eq!(
+            Formatter::new(ColorDepth::Ansi16, false).parse(&line).spans,
+            [
+                Span::raw("a"),
+                Span::styled("b", bold),
+                Span::styled("r", bold.fg(Color::LightRed)),
+                Span::raw("x"),
+            ]
+        );
+    }
+}
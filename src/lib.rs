@@ -0,0 +1,91 @@
+pub mod chatlog;
+pub mod client;
+pub mod highlight;
+pub mod ignore;
+pub mod input;
+pub mod logging;
+pub mod notify;
+mod paths;
+pub mod plugin;
+#[cfg(feature = "lua")]
+pub mod script;
+pub mod tui;
+
+mod app;
+mod config;
+
+pub use app::App;
+pub use config::Config;
+pub use input::Api;
+pub use tui::widget::{ContextualWidget, LogBuffer};
+
+// Generated by build script.
+pub mod built {
+    include!(concat!(env!("OUT_DIR"), "/built.rs"));
+
+    pub const SAMPLE_LOG: &str = r#"
+<Cthon98> hey, if you type in your pw, it will show as stars
+<Cthon98> ********* see!
+<AzureDiamond> hunter2
+<AzureDiamond> doesnt look like stars to me
+<Cthon98> <AzureDiamond> *******
+<Cthon98> thats what I see
+<AzureDiamond> oh, really?
+<Cthon98> Absolutely
+<AzureDiamond> you can go hunter2 my hunter2-ing hunter2
+<AzureDiamond> haha, does that look funny to you?
+<Cthon98> lol, yes. See, when YOU type hunter2, it shows to us as *******
+<AzureDiamond> thats neat, I didnt know IRC did that
+<Cthon98> yep, no matter how many times you type hunter2, it will show to us as *******
+<AzureDiamond> awesome!
+<AzureDiamond> wait, how do you know my pw?
+<Cthon98> er, I just copy pasted YOUR ******'s and it appears to YOU as hunter2 cause its your pw
+<AzureDiamond> oh, ok.
+<Donut[AFK]> HEY EURAKARTE
+<Donut[AFK]> INSULT
+<Eurakarte> RETORT
+<Donut[AFK]> COUNTER-RETORT
+<Eurakarte> QUESTIONING OF SEXUAL PREFERENCE
+<Donut[AFK]> SUGGESTION TO SHUT THE FUCK UP
+<Eurakarte> NOTATION THAT YOU CREATE A VACUUM
+<Donut[AFK]> RIPOSTE
+<Donut[AFK]> ADDON RIPOSTE
+<Eurakarte> COUNTER-RIPOSTE
+<Donut[AFK]> COUNTER-COUNTER RIPOSTE
+<Eurakarte> NONSENSICAL STATEMENT INVOLVING PLANKTON
+<Miles_Prower> RESPONSE TO RANDOM STATEMENT AND THREAT TO BAN OPPOSING SIDES
+<Eurakarte> WORDS OF PRAISE FOR FISHFOOD
+<Miles_Prower> ACKNOWLEDGEMENT AND ACCEPTENCE OF TERMS
+<t0rbad> so there i was in this hallway right
+<BlackAdder> i believe i speak for all of us when i say...
+<BlackAdder> WRONG BTICH
+<BlackAdder> IM SICK OF YOU
+<BlackAdder> AND YOUR LAME STORIES
+<BlackAdder> NOBODY  HERE THINKS YOURE FUNNY
+<BlackAdder> NOBODY HERE WANTS TO HEAR YOUR STORIES
+<BlackAdder> IN FACT
+<BlackAdder> IF YOU DIED RIGHT NOW
+<BlackAdder> I  DON"T THINK NOBODY WOULD CARE
+<BlackAdder> SO WHAT DO YOU SAY TO THAT FAG
+* t0rbad sets mode: +b BlackAdder*!*@*.*
+* BlackAdder has been kicked my t0rbad ( )
+<t0rbad> so there i was in this hallway right
+<CRCError> right
+<heartless> Right.
+<Zybl0re> get up
+<Zybl0re> get on up
+<Zybl0re> get up
+<Zybl0re> get on up
+<phxl|paper> and DANCE
+* nmp3bot dances :D-<
+* nmp3bot dances :D|-<
+* nmp3bot dances :D/-<
+<[SA]HatfulOfHollow> i'm going to become rich and famous after i invent a device that allows you to stab people in the face over the internet
+<Guo_Si> Hey, you know what sucks?
+<TheXPhial> vaccuums
+<Guo_Si> Hey, you know what sucks in a metaphorical sense?
+<TheXPhial> black holes
+<Guo_Si> Hey, you know what just isn't cool?
+<TheXPhial> lava?
+"#;
+}
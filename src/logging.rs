@@ -1,106 +1,327 @@
-use hashbrown::HashMap;
-use ratatui::prelude::Stylize;
-use std::sync::{Arc, Mutex};
-use tracing::Level;
-use tracing_subscriber::Layer;
+use ratatui::text::{Line, Span};
+use serde::Deserialize;
+use std::{
+    fs::OpenOptions,
+    io,
+    path::Path,
+    sync::{Arc, Mutex},
+};
+use tracing::{
+    field::{Field, Visit},
+    span, Level, Subscriber,
+};
+use tracing_subscriber::{fmt, layer::Context, registry::LookupSpan, EnvFilter, Layer};
 
-use crate::tui::widget::LogBuffer;
+use crate::tui::{widget::LogBuffer, Theme};
+
+/// Which events are logged where, as `EnvFilter` directives
+/// such as `info` or `eesh=debug,irc=warn`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct LoggingConfig {
+    /// Filter for the log file. `RUST_LOG` takes precedence.
+    pub file: String,
+
+    /// Filter for the debug log shown in the app.
+    pub buffer: String,
+}
+
+impl Default for LoggingConfig {
+    fn default() -> Self {
+        LoggingConfig {
+            file: String::from("debug"),
+            buffer: String::from("info"),
+        }
+    }
+}
+
+impl LoggingConfig {
+    pub fn file_filter(&self) -> Result<EnvFilter, tracing_subscriber::filter::ParseError> {
+        match std::env::var("RUST_LOG") {
+            Ok(directives) => EnvFilter::try_new(directives),
+            Err(_) => EnvFilter::try_new(&self.file),
+        }
+    }
+
+    pub fn buffer_filter(&self) -> Result<EnvFilter, tracing_subscriber::filter::ParseError> {
+        EnvFilter::try_new(&self.buffer)
+    }
+}
+
+/// Write events to `path` as plain text, after
+/// whatever earlier sessions left there.
+pub fn file_layer<S>(path: &Path, filter: EnvFilter) -> io::Result<impl Layer<S>>
+where
+    S: Subscriber + for<'a> LookupSpan<'a>,
+{
+    if let Some(parent) = path.parent() {
+        std::fs::create_dir_all(parent)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+    Ok(fmt::layer()
+        .with_ansi(false)
+        .with_writer(Mutex::new(file))
+        .with_filter(filter))
+}
 
 pub struct LogBufferLayer {
     lb: Arc<Mutex<LogBuffer>>,
+    theme: Arc<Mutex<Theme>>,
 }
 
 impl LogBufferLayer {
-    pub fn new(lb: Arc<Mutex<LogBuffer>>) -> Self {
-        Self { lb }
+    pub fn new(lb: Arc<Mutex<LogBuffer>>, theme: Arc<Mutex<Theme>>) -> Self {
+        Self { lb, theme }
     }
 }
 
 impl<S> Layer<S> for LogBufferLayer
 where
-    S: tracing::Subscriber,
+    S: Subscriber + for<'a> LookupSpan<'a>,
 {
-    fn on_event(
-        &self,
-        event: &tracing::Event<'_>,
-        _ctx: tracing_subscriber::layer::Context<'_, S>,
-    ) {
-        let now = chrono::Utc::now();
-        let level = match *event.metadata().level() {
-            Level::TRACE => "TRACE".cyan(),
-            Level::DEBUG => "DEBUG".light_magenta(),
-            Level::INFO => "INFO".light_green(),
-            Level::WARN => "WARN".light_yellow(),
-            Level::ERROR => "ERROR".light_red(),
+    fn on_new_span(&self, attrs: &span::Attributes<'_>, id: &span::Id, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
         };
+        let mut visitor = FieldVisitor::default();
+        attrs.record(&mut visitor);
+        span.extensions_mut().insert(visitor);
+    }
 
-        let mut visitor = HashMapVisitor::default();
-        event.record(&mut visitor);
-        let fields = visitor.unwrap();
+    fn on_record(&self, id: &span::Id, values: &span::Record<'_>, ctx: Context<'_, S>) {
+        let Some(span) = ctx.span(id) else {
+            return;
+        };
+        let mut extensions = span.extensions_mut();
+        if let Some(visitor) = extensions.get_mut::<FieldVisitor>() {
+            values.record(visitor);
+        }
+    }
 
-        let content = if fields.len() == 1 && fields.contains_key("message") {
-            fields.get("message").unwrap().clone()
-        } else {
-            use std::fmt::Write;
-            let mut buf = String::new();
+    fn on_event(&self, event: &tracing::Event<'_>, ctx: Context<'_, S>) {
+        let now = chrono::Utc::now();
+        let theme = self
+            .theme
+            .lock()
+            .expect("Theme mutex was poisoned!")
+            .clone();
 
-            for (key, value) in fields {
-                write!(buf, "  {key}={value}").expect("Infallible write failed!");
+        // The tag column shows where the event came from,
+        // coloured by its level.
+        let metadata = event.metadata();
+        let level_style = match *metadata.level() {
+            Level::TRACE => theme.trace,
+            Level::DEBUG => theme.debug,
+            Level::INFO => theme.info,
+            Level::WARN => theme.warn,
+            Level::ERROR => theme.error,
+        };
+        let tag = Line::from(Span::styled(metadata.target().to_owned(), level_style));
+
+        let mut content = Line::default();
+
+        // Spans the event happened in, outermost first,
+        // such as `client{net=libera}:join`.
+        if let Some(scope) = ctx.event_scope(event) {
+            let mut stack = String::new();
+            for span in scope.from_root() {
+                if !stack.is_empty() {
+                    stack.push(':');
+                }
+                stack.push_str(span.name());
+                if let Some(fields) = span.extensions().get::<FieldVisitor>() {
+                    if !fields.fields.is_empty() {
+                        let fields: Vec<_> = fields
+                            .fields
+                            .iter()
+                            .map(|(key, value)| format!("{key}={value}"))
+                            .collect();
+                        stack.push_str(&format!("{{{}}}", fields.join(" ")));
+                    }
+                }
+            }
+            if !stack.is_empty() {
+                content.push_span(Span::styled(format!("{stack} "), theme.span));
             }
+        }
+
+        let mut visitor = FieldVisitor::default();
+        event.record(&mut visitor);
 
-            buf.trim().to_owned()
+        let stack = content.spans.len();
+        if let Some(message) = visitor.message {
+            content.push_span(Span::raw(message));
+        }
+        for (key, value) in visitor.fields {
+            if content.spans.len() > stack {
+                content.push_span(Span::raw("  "));
+            }
+            content.push_span(Span::styled(format!("{key}="), theme.field_name));
+            content.push_span(Span::styled(value, theme.field_value));
         }
-        .into();
 
         self.lb
             .lock()
             .expect("Tracing LogBuffer was poisoned!")
-            .push_line(now, level.into(), content);
+            .push_event(now, *metadata.level(), tag, content);
     }
 }
 
+/// Collects the fields of an event or span in the
+/// order they were written, keeping the message apart.
 #[derive(Default)]
-struct HashMapVisitor {
-    buf: HashMap<String, String>,
+struct FieldVisitor {
+    message: Option<String>,
+    fields: Vec<(&'static str, String)>,
 }
 
-impl HashMapVisitor {
-    pub fn unwrap(self) -> HashMap<String, String> {
-        self.buf
+impl FieldVisitor {
+    fn push(&mut self, field: &Field, value: String) {
+        if field.name() == "message" {
+            self.message = Some(value);
+        } else if let Some(entry) = self
+            .fields
+            .iter_mut()
+            .find(|(name, _)| *name == field.name())
+        {
+            // Recorded again on a span, so the newest value wins.
+            entry.1 = value;
+        } else {
+            self.fields.push((field.name(), value));
+        }
     }
 }
 
-impl tracing::field::Visit for HashMapVisitor {
-    fn record_f64(&mut self, field: &tracing::field::Field, value: f64) {
-        self.buf.insert(field.name().to_string(), value.to_string());
+impl Visit for FieldVisitor {
+    fn record_f64(&mut self, field: &Field, value: f64) {
+        self.push(field, value.to_string());
+    }
+
+    fn record_i64(&mut self, field: &Field, value: i64) {
+        self.push(field, value.to_string());
+    }
+
+    fn record_u64(&mut self, field: &Field, value: u64) {
+        self.push(field, value.to_string());
+    }
+
+    fn record_bool(&mut self, field: &Field, value: bool) {
+        self.push(field, value.to_string());
     }
 
-    fn record_i64(&mut self, field: &tracing::field::Field, value: i64) {
-        self.buf.insert(field.name().to_string(), value.to_string());
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.push(field, value.to_string());
     }
 
-    fn record_u64(&mut self, field: &tracing::field::Field, value: u64) {
-        self.buf.insert(field.name().to_string(), value.to_string());
+    fn record_error(&mut self, field: &Field, value: &(dyn std::error::Error + 'static)) {
+        self.push(field, value.to_string());
     }
 
-    fn record_bool(&mut self, field: &tracing::field::Field, value: bool) {
-        self.buf.insert(field.name().to_string(), value.to_string());
+    fn record_debug(&mut self, field: &Field, value: &dyn std::fmt::Debug) {
+        self.push(field, format!("{value:?}"));
     }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{LogBufferLayer, LoggingConfig};
+    use crate::tui::{
+        widget::{plain, LogBuffer},
+        Theme,
+    };
+    use std::sync::{Arc, Mutex};
+    use tracing::{debug, error, info, info_span, trace, warn};
+    use tracing_subscriber::{layer::SubscriberExt, EnvFilter, Layer, Registry};
+
+    /// The content of every line `log` leaves in a
+    /// buffer behind a `LogBufferLayer`.
+    fn logged(filter: EnvFilter, log: impl FnOnce()) -> Vec<String> {
+        let lb = Arc::new(Mutex::new(LogBuffer::new(100, chrono_tz::UTC)));
+        let theme = Arc::new(Mutex::new(Theme::default()));
+        let layer = LogBufferLayer::new(Arc::clone(&lb), theme).with_filter(filter);
+        tracing::subscriber::with_default(Registry::default().with(layer), log);
 
-    fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
-        self.buf.insert(field.name().to_string(), value.to_string());
+        let lb = lb.lock().unwrap();
+        lb.lines().map(|(_, _, content)| plain(content)).collect()
     }
 
-    fn record_error(
-        &mut self,
-        field: &tracing::field::Field,
-        value: &(dyn std::error::Error + 'static),
-    ) {
-        self.buf.insert(field.name().to_string(), value.to_string());
+    fn everything() -> EnvFilter {
+        EnvFilter::new("trace")
     }
 
-    fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
-        self.buf
-            .insert(field.name().to_string(), format!("{value:?}"));
+    #[test]
+    fn shows_the_span_stack_and_fields_in_order() {
+        let lines = logged(everything(), || {
+            let _client = info_span!("client", net = "libera").entered();
+            let _join = info_span!("join", channel = "#rust", tries = 1).entered();
+            info!(b = 2, a = true, "joined");
+        });
+        assert_eq!(
+            lines,
+            ["client{net=libera}:join{channel=#rust tries=1} joined  b=2  a=true"]
+        );
+
+        assert_eq!(logged(everything(), || info!(n = 1)), ["n=1"]);
+    }
+
+    #[test]
+    fn recorded_span_fields_replace_earlier_values() {
+        let lines = logged(everything(), || {
+            let span = info_span!("conn", state = "connecting", lag = tracing::field::Empty);
+            span.record("state", "up");
+            span.record("lag", 3);
+            span.record("lag", 5);
+            span.in_scope(|| info!("ping"));
+        });
+        assert_eq!(lines, ["conn{state=up lag=5} ping"]);
+    }
+
+    #[test]
+    fn buffer_filter_decides_what_is_shown() {
+        let cfg = LoggingConfig::default();
+        let lines = logged(cfg.buffer_filter().unwrap(), || {
+            debug!("hidden");
+            info!("shown");
+        });
+        assert_eq!(lines, ["shown"]);
+
+        let cfg = LoggingConfig {
+            buffer: String::from("warn,irc=debug"),
+            ..LoggingConfig::default()
+        };
+        let lines = logged(cfg.buffer_filter().unwrap(), || {
+            info!("hidden");
+            warn!("shown");
+            debug!(target: "irc", "wire");
+            trace!(target: "irc", "hidden");
+        });
+        assert_eq!(lines, ["shown", "wire"]);
+
+        let cfg = LoggingConfig {
+            buffer: String::from("eesh=loud"),
+            ..LoggingConfig::default()
+        };
+        assert!(cfg.buffer_filter().is_err());
+    }
+
+    #[test]
+    fn rust_log_overrides_the_file_filter() {
+        // The only test reading RUST_LOG, so changing it is safe.
+        let cfg = LoggingConfig {
+            file: String::from("error"),
+            ..LoggingConfig::default()
+        };
+        let log = || {
+            trace!("trace");
+            error!("error");
+        };
+
+        std::env::remove_var("RUST_LOG");
+        assert_eq!(logged(cfg.file_filter().unwrap(), log), ["error"]);
+
+        std::env::set_var("RUST_LOG", "trace");
+        assert_eq!(logged(cfg.file_filter().unwrap(), log), ["trace", "error"]);
+        std::env::remove_var("RUST_LOG");
     }
 }
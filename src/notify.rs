@@ -0,0 +1,272 @@
+use chrono::{DateTime, NaiveTime, Utc};
+use chrono_tz::Tz;
+use serde::Deserialize;
+use std::{
+    io,
+    process::{Command, Stdio},
+    time::{Duration, Instant},
+};
+use tokio::sync::mpsc::UnboundedSender;
+
+use crate::Config;
+
+/// How we are told about mentions and private messages
+/// while looking elsewhere, as configured in `[notify]`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(default)]
+pub struct NotifyConfig {
+    /// Notify of messages that highlight.
+    pub mentions: bool,
+
+    /// Notify of private messages.
+    pub queries: bool,
+
+    /// Ring the terminal bell.
+    pub bell: bool,
+
+    /// Ask the terminal to show a desktop notification,
+    /// for terminals which support one of these.
+    pub osc: Option<Osc>,
+
+    /// Program to run, along with any leading arguments. The
+    /// buffer name and the message are passed after them,
+    /// e.g. `["notify-send", "--app-name=eesh"]`.
+    pub command: Vec<String>,
+
+    /// Fewest seconds between notifications. Any
+    /// more in the meantime are dropped.
+    pub interval_secs: u64,
+
+    /// Times of day nothing is notified of, in `ui.tz`.
+    pub quiet_hours: Option<QuietHours>,
+}
+
+impl Default for NotifyConfig {
+    fn default() -> Self {
+        NotifyConfig {
+            mentions: true,
+            queries: true,
+            bell: true,
+            osc: None,
+            command: Vec::new(),
+            interval_secs: 10,
+            quiet_hours: None,
+        }
+    }
+}
+
+/// Escape sequences terminals show desktop notifications for.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Osc {
+    /// `OSC 9`, as in iTerm2, Windows Terminal and kitty.
+    Osc9,
+    /// `OSC 777`, as in urxvt, foot and VTE-based terminals.
+    Osc777,
+}
+
+/// A span of the day, such as `from = "23:00"` and
+/// `to = "07:30"`, which may run past midnight.
+#[derive(Clone, Copy, Debug, Deserialize)]
+pub struct QuietHours {
+    pub from: NaiveTime,
+    pub to: NaiveTime,
+}
+
+impl QuietHours {
+    pub fn contains(&self, time: NaiveTime) -> bool {
+        if self.from <= self.to {
+            self.from <= time && time < self.to
+        } else {
+            time >= self.from || time < self.to
+        }
+    }
+}
+
+/// Something worth telling the user about.
+pub struct Notification<'a> {
+    /// Name of the buffer it happened in.
+    pub buffer: &'a str,
+    pub sender: &'a str,
+    pub text: &'a str,
+}
+
+/// Sends notifications the ways configured, at most
+/// one per interval and never during quiet hours.
+pub struct Notifier {
+    cfg: NotifyConfig,
+    tz: Tz,
+    last: Option<Instant>,
+
+    /// Where escape sequences are sent to be written to the
+    /// terminal. Without one, only the command is run.
+    terminal: Option<UnboundedSender<String>>,
+}
+
+impl Notifier {
+    pub fn from_config(cfg: &Config) -> Self {
+        Notifier {
+            cfg: cfg.notify.clone(),
+            tz: cfg.ui.tz,
+            last: None,
+            terminal: None,
+        }
+    }
+
+    pub fn config(&self) -> &NotifyConfig {
+        &self.cfg
+    }
+
+    /// Send escape sequences to `terminal` from now on.
+    pub fn attach(&mut self, terminal: UnboundedSender<String>) {
+        self.terminal = Some(terminal);
+    }
+
+    /// Send `notification`, unless it's too soon after the
+    /// last one or within quiet hours. Fails if the command
+    /// can't be run.
+    pub fn notify(&mut self, notification: &Notification, now: DateTime<Utc>) -> io::Result<()> {
+        let interval = Duration::from_secs(self.cfg.interval_secs);
+        if self.last.is_some_and(|last| last.elapsed() < interval) {
+            return Ok(());
+        }
+        if let Some(quiet) = self.cfg.quiet_hours {
+            if quiet.contains(now.with_timezone(&self.tz).time()) {
+                return Ok(());
+            }
+        }
+        self.last = Some(Instant::now());
+
+        let escapes = self.escapes(notification);
+        if let Some(terminal) = self.terminal.as_ref().filter(|_| !escapes.is_empty()) {
+            // The UI thread has stopped if this fails, so
+            // there is no terminal left to write to.
+            let _ = terminal.send(escapes);
+        }
+        self.run_command(notification)
+    }
+
+    /// The bell and OSC sequences to write to the terminal.
+    fn escapes(&self, notification: &Notification) -> String {
+        let title = format!("eesh: {}", sanitize(notification.buffer));
+        let body = format!(
+            "<{}> {}",
+            sanitize(notification.sender),
+            sanitize(notification.text)
+        );
+
+        let mut out = String::new();
+        match self.cfg.osc {
+            Some(Osc::Osc9) => out.push_str(&format!("\x1b]9;{title}: {body}\x07")),
+            Some(Osc::Osc777) => {
+                // Fields are separated by semicolons.
+                let (title, body) = (title.replace(';', ","), body.replace(';', ","));
+                out.push_str(&format!("\x1b]777;notify;{title};{body}\x07"));
+            }
+            None => (),
+        }
+        if self.cfg.bell {
+            out.push('\x07');
+        }
+        out
+    }
+
+    fn run_command(&self, notification: &Notification) -> io::Result<()> {
+        let Some((program, args)) = self.cfg.command.split_first() else {
+            return Ok(());
+        };
+        let mut child = Command::new(program)
+            .args(args)
+            .arg(notification.buffer)
+            .arg(format!("<{}> {}", notification.sender, notification.text))
+            .stdin(Stdio::null())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()?;
+
+        // Reaped in the background so it doesn't hold up chat.
+        std::thread::spawn(move || child.wait());
+        Ok(())
+    }
+}
+
+/// Drop control characters, which would end
+/// an escape sequence or start another.
+fn sanitize(text: &str) -> String {
+    text.chars().filter(|c| !c.is_control()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Notification, Notifier, NotifyConfig, QuietHours};
+    use chrono::{NaiveTime, TimeZone, Utc};
+    use tokio::sync::mpsc;
+
+    fn time(hour: u32, min: u32) -> NaiveTime {
+        NaiveTime::from_hms_opt(hour, min, 0).unwrap()
+    }
+
+    #[test]
+    fn quiet_hours_within_a_day() {
+        let quiet = QuietHours {
+            from: time(9, 0),
+            to: time(17, 0),
+        };
+        assert!(quiet.contains(time(9, 0)));
+        assert!(quiet.contains(time(12, 30)));
+        assert!(!quiet.contains(time(17, 0)));
+        assert!(!quiet.contains(time(8, 59)));
+    }
+
+    #[test]
+    fn quiet_hours_past_midnight() {
+        let quiet = QuietHours {
+            from: time(23, 0),
+            to: time(7, 30),
+        };
+        assert!(quiet.contains(time(23, 0)));
+        assert!(quiet.contains(time(0, 0)));
+        assert!(quiet.contains(time(7, 29)));
+        assert!(!quiet.contains(time(7, 30)));
+        assert!(!quiet.contains(time(12, 0)));
+        assert!(!quiet.contains(time(22, 59)));
+    }
+
+    #[test]
+    fn notifies_at_most_once_per_interval_outside_quiet_hours() {
+        let (tx, mut rx) = mpsc::unbounded_channel();
+        let mut notifier = Notifier {
+            cfg: NotifyConfig {
+                quiet_hours: Some(QuietHours {
+                    from: time(23, 0),
+                    to: time(7, 0),
+                }),
+                ..NotifyConfig::default()
+            },
+            tz: chrono_tz::UTC,
+            last: None,
+            terminal: Some(tx),
+        };
+        let notification = Notification {
+            buffer: "#eesh",
+            sender: "nick",
+            text: "hello",
+        };
+
+        let night = Utc.with_ymd_and_hms(2024, 1, 1, 3, 0, 0).unwrap();
+        notifier.notify(&notification, night).unwrap();
+        assert!(rx.try_recv().is_err());
+
+        let noon = Utc.with_ymd_and_hms(2024, 1, 1, 12, 0, 0).unwrap();
+        notifier.notify(&notification, noon).unwrap();
+        assert_eq!(rx.try_recv().unwrap(), "\x07");
+
+        // Too soon after the last one.
+        notifier.notify(&notification, noon).unwrap();
+        assert!(rx.try_recv().is_err());
+
+        notifier.cfg.interval_secs = 0;
+        notifier.notify(&notification, noon).unwrap();
+        assert_eq!(rx.try_recv().unwrap(), "\x07");
+    }
+}
@@ -0,0 +1,46 @@
+use std::{env, path::PathBuf};
+
+/// Root directory for anything eesh keeps between runs,
+/// such as script data. Honors `$XDG_DATA_HOME` and
+/// falls back to `~/.local/share/eesh`.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn data_dir() -> PathBuf {
+    env::var_os("XDG_DATA_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/share")))
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("eesh")
+}
+
+/// Root directory for anything eesh keeps between runs,
+/// such as script data. Lives under `%APPDATA%`.
+#[cfg(target_os = "windows")]
+pub fn data_dir() -> PathBuf {
+    env::var_os("APPDATA")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("eesh")
+}
+
+/// Directory for state worth keeping between runs but not
+/// backing up, such as the debug log and spilled scrollback.
+/// Honors `$XDG_STATE_HOME` and falls back to
+/// `~/.local/state/eesh`.
+#[cfg(any(target_os = "linux", target_os = "macos"))]
+pub fn state_dir() -> PathBuf {
+    env::var_os("XDG_STATE_HOME")
+        .map(PathBuf::from)
+        .or_else(|| env::var_os("HOME").map(|home| PathBuf::from(home).join(".local/state")))
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("eesh")
+}
+
+/// Directory for state worth keeping between runs, such as the
+/// debug log and spilled scrollback. Lives under `%LOCALAPPDATA%`.
+#[cfg(target_os = "windows")]
+pub fn state_dir() -> PathBuf {
+    env::var_os("LOCALAPPDATA")
+        .map(PathBuf::from)
+        .unwrap_or_else(|| PathBuf::from("."))
+        .join("eesh")
+}
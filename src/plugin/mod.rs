@@ -0,0 +1,549 @@
+use color_eyre::eyre::{bail, Result};
+use ratatui::widgets::ScrollDirection;
+use std::path::PathBuf;
+use tokio::sync::mpsc::{self, UnboundedReceiver, UnboundedSender};
+use tracing::warn;
+
+use crate::{ignore::IgnoreRule, input::Api, Config};
+
+/// Something that happened which plugins may want to react to.
+#[derive(Clone, Copy, Debug)]
+pub enum Event<'a> {
+    /// A PRIVMSG or NOTICE arrived from a server.
+    Message {
+        server: &'a str,
+        target: &'a str,
+        source: &'a str,
+        text: &'a str,
+    },
+    /// Someone, possibly us, joined a channel.
+    Join {
+        server: &'a str,
+        channel: &'a str,
+        nick: &'a str,
+    },
+    /// Someone, possibly us, left a channel.
+    Part {
+        server: &'a str,
+        channel: &'a str,
+        nick: &'a str,
+    },
+    /// Someone we share a channel with disconnected.
+    Quit { server: &'a str, nick: &'a str },
+    /// Someone, possibly us, changed their nick.
+    Nick {
+        server: &'a str,
+        old: &'a str,
+        new: &'a str,
+    },
+    /// The topic of a channel was set or changed.
+    Topic {
+        server: &'a str,
+        channel: &'a str,
+        topic: &'a str,
+    },
+}
+
+impl<'a> Event<'a> {
+    /// Name scripts use to subscribe to this event with `eesh.on`.
+    pub fn name(&self) -> &'static str {
+        match self {
+            Event::Message { .. } => "message",
+            Event::Join { .. } => "join",
+            Event::Part { .. } => "part",
+            Event::Quit { .. } => "quit",
+            Event::Nick { .. } => "nick",
+            Event::Topic { .. } => "topic",
+        }
+    }
+
+    /// The event a message received from `server` amounts to, if any.
+    pub fn of(server: &'a str, msg: &'a irc::proto::Message) -> Option<Event<'a>> {
+        use irc::proto::Command;
+
+        let nick = msg.source_nickname().unwrap_or(server);
+        Some(match &msg.command {
+            Command::PRIVMSG(target, text) | Command::NOTICE(target, text) => Event::Message {
+                server,
+                target,
+                source: nick,
+                text,
+            },
+            Command::JOIN(channel, ..) => Event::Join {
+                server,
+                channel,
+                nick,
+            },
+            Command::PART(channel, _) => Event::Part {
+                server,
+                channel,
+                nick,
+            },
+            Command::QUIT(_) => Event::Quit { server, nick },
+            Command::NICK(new) => Event::Nick {
+                server,
+                old: nick,
+                new,
+            },
+            Command::TOPIC(channel, Some(topic)) => Event::Topic {
+                server,
+                channel,
+                topic,
+            },
+            _ => return None,
+        })
+    }
+}
+
+/// Changes to the application state a plugin can ask for.
+/// Plugins never touch the application directly; each variant
+/// mirrors a method of `input::Api`, and queued requests are
+/// applied in order by the main loop.
+#[derive(Debug)]
+pub enum Request {
+    Exit,
+    Scroll(ScrollDirection),
+    ClearInputBuffer,
+    SendMessage {
+        server: String,
+        channel: String,
+        text: String,
+    },
+    SendText(String),
+    SetTheme(String),
+    ToggleDebugOverlay,
+    ToggleTopic,
+    ScrollTopic(ScrollDirection),
+    SetFilter(String),
+    ToggleFollow,
+    ExportBuffer(PathBuf),
+    Find(String),
+    FindNext(ScrollDirection),
+    Search(String),
+    Jump(usize),
+    SwitchBuffer(String),
+    Ignore(IgnoreRule),
+    ListIgnores,
+    Unignore(String),
+    /// Run a leader command of another plugin.
+    PluginCommand {
+        name: String,
+        args: Vec<String>,
+    },
+    /// Nothing but wake the main loop, e.g. when something
+    /// a plugin's background task waits on is ready.
+    Wake,
+}
+
+impl Request {
+    pub fn apply(self, api: &mut impl Api) -> Result<()> {
+        match self {
+            Request::Exit => api.exit(),
+            Request::Scroll(direction) => api.scroll(direction),
+            Request::ClearInputBuffer => api.clear_input_buffer(),
+            Request::SendMessage {
+                server,
+                channel,
+                text,
+            } => {
+                let msg = irc::proto::Command::PRIVMSG(channel.clone(), text);
+                api.send_message(&server, &channel, msg)?;
+            }
+            Request::SendText(text) => api.send_text(&text)?,
+            Request::SetTheme(name) => api.set_theme(&name)?,
+            Request::ToggleDebugOverlay => api.toggle_debug_overlay(),
+            Request::ToggleTopic => api.toggle_topic(),
+            Request::ScrollTopic(direction) => api.scroll_topic(direction),
+            Request::SetFilter(spec) => api.set_filter(&spec)?,
+            Request::ToggleFollow => api.toggle_follow(),
+            Request::ExportBuffer(path) => api.export_buffer(&path)?,
+            Request::Find(text) => api.find(&text),
+            Request::FindNext(direction) => api.find_next(direction),
+            Request::Search(text) => api.search(&text),
+            Request::Jump(n) => api.jump(n)?,
+            Request::SwitchBuffer(which) => api.switch_buffer(&which)?,
+            Request::Ignore(rule) => api.ignore(rule)?,
+            Request::ListIgnores => api.list_ignores(),
+            Request::Unignore(which) => api.unignore(&which)?,
+            Request::PluginCommand { name, args } => api.plugin_command(&name, &args)?,
+            Request::Wake => (),
+        }
+        Ok(())
+    }
+}
+
+/// Handle a plugin uses to queue requests for the main loop.
+pub type Requests = UnboundedSender<Request>;
+
+/// An extension compiled into eesh. Every hook
+/// but `name` and `load` is optional.
+pub trait Plugin {
+    /// Unique name of the plugin, used in log
+    /// messages and to route commands.
+    fn name(&self) -> &str;
+
+    /// Called once at startup with the loaded configuration
+    /// and the queue through which the plugin can act.
+    fn load(&mut self, cfg: &Config, requests: Requests) -> Result<()>;
+
+    /// Called for every event the application produces.
+    fn on_event(&mut self, _event: Event<'_>) {}
+
+    /// Called once per iteration of the main loop. Must
+    /// return quickly; long work belongs on a tokio task.
+    fn tick(&mut self) {}
+
+    /// Offered every leader command the application itself
+    /// doesn't recognize. Return `None` to let the next
+    /// plugin have a go at it.
+    fn command(&mut self, _name: &str, _args: &[String]) -> Option<Result<()>> {
+        None
+    }
+}
+
+/// Plugins built into this binary, selected by cargo features.
+/// An in-tree plugin is registered by adding it here behind a
+/// feature of its own; downstream crates building their own
+/// binary register theirs with `App::with_plugin` instead.
+fn builtin() -> Vec<Box<dyn Plugin>> {
+    vec![
+        #[cfg(feature = "lua")]
+        Box::new(crate::script::ScriptHost::new()),
+    ]
+}
+
+/// Owns every registered plugin and the queue
+/// of requests they have made.
+pub struct PluginHost {
+    plugins: Vec<Box<dyn Plugin>>,
+    requests: UnboundedReceiver<Request>,
+    sender: Requests,
+    /// A request taken off the queue by `requested`,
+    /// to be handed out first by `next_request`.
+    waiting: Option<Request>,
+}
+
+impl PluginHost {
+    pub fn new() -> Self {
+        let (sender, requests) = mpsc::unbounded_channel();
+        PluginHost {
+            plugins: builtin(),
+            requests,
+            sender,
+            waiting: None,
+        }
+    }
+
+    /// Add a plugin alongside the built-in ones.
+    /// Must be called before `load_all`.
+    pub fn register(&mut self, plugin: Box<dyn Plugin>) {
+        self.plugins.push(plugin);
+    }
+
+    /// Load every registered plugin. Plugins that fail to
+    /// load are reported and dropped rather than aborting
+    /// the whole application.
+    pub fn load_all(&mut self, cfg: &Config) {
+        let sender = &self.sender;
+        self.plugins
+            .retain_mut(|plugin| match plugin.load(cfg, sender.clone()) {
+                Ok(()) => true,
+                Err(e) => {
+                    warn!(
+                        plugin = plugin.name(),
+                        error = e.to_string(),
+                        "Failed to load plugin"
+                    );
+                    false
+                }
+            });
+    }
+
+    pub fn emit(&mut self, event: Event<'_>) {
+        for plugin in &mut self.plugins {
+            plugin.on_event(event);
+        }
+    }
+
+    pub fn tick(&mut self) {
+        for plugin in &mut self.plugins {
+            plugin.tick();
+        }
+    }
+
+    /// Offer a command to each plugin in turn until one handles it.
+    pub fn command(&mut self, name: &str, args: &[String]) -> Result<()> {
+        for plugin in &mut self.plugins {
+            if let Some(result) = plugin.command(name, args) {
+                return result;
+            }
+        }
+        bail!("Unknown command \"{name}\"")
+    }
+
+    /// Wait until a plugin has made a request, leaving it
+    /// for `next_request`. Safe to cancel, as in `select!`.
+    pub async fn requested(&mut self) {
+        if self.waiting.is_none() {
+            // The host holds a sender, so this never ends.
+            self.waiting = self.requests.recv().await;
+        }
+    }
+
+    /// Take the next pending request, if any, without blocking.
+    pub fn next_request(&mut self) -> Option<Request> {
+        self.waiting
+            .take()
+            .or_else(|| self.requests.try_recv().ok())
+    }
+}
+
+impl Default for PluginHost {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use irc::proto::Message;
+
+    /// Greets whoever joins and switches themes on command.
+    #[derive(Default)]
+    struct Greeter {
+        requests: Option<Requests>,
+        ticks: usize,
+    }
+
+    impl Plugin for Greeter {
+        fn name(&self) -> &str {
+            "greeter"
+        }
+
+        fn load(&mut self, _cfg: &Config, requests: Requests) -> Result<()> {
+            self.requests = Some(requests);
+            Ok(())
+        }
+
+        fn on_event(&mut self, event: Event<'_>) {
+            if let Event::Join { nick, .. } = event {
+                let request = Request::SendText(format!("hi {nick}"));
+                self.requests.as_ref().unwrap().send(request).unwrap();
+            }
+        }
+
+        fn tick(&mut self) {
+            self.ticks += 1;
+        }
+
+        fn command(&mut self, name: &str, args: &[String]) -> Option<Result<()>> {
+            if name != "greet-theme" {
+                return None;
+            }
+            let request = Request::SetTheme(args[0].clone());
+            self.requests.as_ref().unwrap().send(request).unwrap();
+            Some(Ok(()))
+        }
+    }
+
+    struct Broken;
+
+    impl Plugin for Broken {
+        fn name(&self) -> &str {
+            "broken"
+        }
+
+        fn load(&mut self, _cfg: &Config, _requests: Requests) -> Result<()> {
+            bail!("nope")
+        }
+    }
+
+    /// Records the `Api` calls requests turn into.
+    #[derive(Default)]
+    struct Calls(Vec<String>);
+
+    impl Api for Calls {
+        fn exit(&mut self) {
+            self.0.push("exit".into());
+        }
+        fn scroll(&mut self, direction: ScrollDirection) {
+            self.0.push(format!("scroll {direction:?}"));
+        }
+        fn clear_input_buffer(&mut self) {
+            self.0.push("clear_input_buffer".into());
+        }
+        fn send_message<M: Into<irc::proto::Message>>(
+            &mut self,
+            server: &str,
+            channel: &str,
+            message: M,
+        ) -> Result<()> {
+            let message = message.into().to_string();
+            self.0.push(format!(
+                "send_message {server} {channel} {}",
+                message.trim_end()
+            ));
+            Ok(())
+        }
+        fn send_text(&mut self, text: &str) -> Result<()> {
+            self.0.push(format!("send_text {text}"));
+            Ok(())
+        }
+        fn set_theme(&mut self, name: &str) -> Result<()> {
+            self.0.push(format!("set_theme {name}"));
+            Ok(())
+        }
+        fn toggle_debug_overlay(&mut self) {
+            self.0.push("toggle_debug_overlay".into());
+        }
+        fn toggle_topic(&mut self) {
+            self.0.push("toggle_topic".into());
+        }
+        fn scroll_topic(&mut self, direction: ScrollDirection) {
+            self.0.push(format!("scroll_topic {direction:?}"));
+        }
+        fn set_filter(&mut self, spec: &str) -> Result<()> {
+            self.0.push(format!("set_filter {spec}"));
+            Ok(())
+        }
+        fn toggle_follow(&mut self) {
+            self.0.push("toggle_follow".into());
+        }
+        fn export_buffer(&mut self, path: &std::path::Path) -> Result<()> {
+            self.0.push(format!("export_buffer {}", path.display()));
+            Ok(())
+        }
+        fn find(&mut self, text: &str) {
+            self.0.push(format!("find {text}"));
+        }
+        fn find_next(&mut self, direction: ScrollDirection) {
+            self.0.push(format!("find_next {direction:?}"));
+        }
+        fn search(&mut self, text: &str) {
+            self.0.push(format!("search {text}"));
+        }
+        fn jump(&mut self, n: usize) -> Result<()> {
+            self.0.push(format!("jump {n}"));
+            Ok(())
+        }
+        fn switch_buffer(&mut self, which: &str) -> Result<()> {
+            self.0.push(format!("switch_buffer {which}"));
+            Ok(())
+        }
+        fn ignore(&mut self, rule: IgnoreRule) -> Result<()> {
+            self.0.push(format!("ignore {}", rule.mask));
+            Ok(())
+        }
+        fn list_ignores(&mut self) {
+            self.0.push("list_ignores".into());
+        }
+        fn unignore(&mut self, which: &str) -> Result<()> {
+            self.0.push(format!("unignore {which}"));
+            Ok(())
+        }
+        fn plugin_command(&mut self, name: &str, args: &[String]) -> Result<()> {
+            self.0
+                .push(format!("plugin_command {name} {}", args.join(" ")));
+            Ok(())
+        }
+    }
+
+    fn config() -> Config {
+        Config::parse_str("[alias]\n[ui]\n[clients]\n").unwrap()
+    }
+
+    /// A host with only the given plugins, loaded.
+    fn host(plugins: Vec<Box<dyn Plugin>>) -> PluginHost {
+        let mut host = PluginHost::new();
+        host.plugins = plugins;
+        host.load_all(&config());
+        host
+    }
+
+    fn apply_all(host: &mut PluginHost) -> Vec<String> {
+        let mut calls = Calls::default();
+        while let Some(request) = host.next_request() {
+            request.apply(&mut calls).unwrap();
+        }
+        calls.0
+    }
+
+    #[test]
+    fn plugins_act_through_requests() {
+        let mut host = host(vec![Box::new(Greeter::default()), Box::new(Broken)]);
+        // The plugin that failed to load is dropped.
+        assert_eq!(host.plugins.len(), 1);
+
+        let join: Message = ":bob!b@host JOIN #rust\r\n".parse().unwrap();
+        host.emit(Event::of("libera", &join).unwrap());
+        host.command("greet-theme", &["light".into()]).unwrap();
+        host.tick();
+        assert_eq!(
+            apply_all(&mut host),
+            ["send_text hi bob", "set_theme light"]
+        );
+        assert!(host.next_request().is_none());
+
+        assert!(host.command("nope", &[]).is_err());
+    }
+
+    #[test]
+    fn requests_map_onto_the_api() {
+        let mut host = host(Vec::new());
+        let requests = [
+            Request::Scroll(ScrollDirection::Backward),
+            Request::SendMessage {
+                server: "libera".into(),
+                channel: "#rust".into(),
+                text: "hello".into(),
+            },
+            Request::SetFilter("level=warn".into()),
+            Request::ExportBuffer("out.txt".into()),
+            Request::Jump(2),
+            Request::Ignore(IgnoreRule::parse("spambot").unwrap()),
+            Request::Wake,
+            Request::PluginCommand {
+                name: "store".into(),
+                args: vec!["foo".into(), "clear".into()],
+            },
+        ];
+        for request in requests {
+            host.sender.send(request).unwrap();
+        }
+        assert_eq!(
+            apply_all(&mut host),
+            [
+                "scroll Backward",
+                "send_message libera #rust PRIVMSG #rust hello",
+                "set_filter level=warn",
+                "export_buffer out.txt",
+                "jump 2",
+                "ignore spambot",
+                "plugin_command store foo clear",
+            ]
+        );
+    }
+
+    #[test]
+    fn events_of_messages() {
+        let event = |raw: &str| {
+            let msg: Message = raw.parse().unwrap();
+            Event::of("libera", &msg).map(|e| format!("{} {e:?}", e.name()))
+        };
+        assert_eq!(
+            event(":a!u@h PRIVMSG #rust :hi\r\n").unwrap(),
+            r##"message Message { server: "libera", target: "#rust", source: "a", text: "hi" }"##
+        );
+        assert!(event(":a!u@h PART #rust\r\n").unwrap().starts_with("part "));
+        assert!(event(":a!u@h QUIT :bye\r\n").unwrap().starts_with("quit "));
+        assert_eq!(
+            event(":a!u@h NICK b\r\n").unwrap(),
+            r#"nick Nick { server: "libera", old: "a", new: "b" }"#
+        );
+        assert!(event(":a!u@h TOPIC #rust :news\r\n")
+            .unwrap()
+            .starts_with("topic "));
+        assert!(event("PING :x\r\n").is_none());
+    }
+}
@@ -0,0 +1,532 @@
+use color_eyre::eyre::{bail, Result};
+use hashbrown::HashMap;
+use mlua::{Function, Integer, IntoLuaMulti, Lua, Table};
+use serde::Deserialize;
+use std::{
+    path::PathBuf,
+    process::Stdio,
+    sync::{Arc, Mutex},
+};
+use tracing::{error, info, warn};
+
+use crate::{
+    plugin::{Event, Plugin, Request, Requests},
+    Config,
+};
+
+mod sandbox;
+mod store;
+mod tasks;
+pub use sandbox::{Capabilities, Limits};
+pub use store::Store;
+
+/// Name of the registry table holding every
+/// handler a script registered with `eesh.on`.
+const HANDLERS_KEY: &str = "eesh.handlers";
+
+#[derive(Clone, Debug, Deserialize)]
+pub struct ScriptConfig {
+    /// Path to the Lua file to run when the script is loaded.
+    pub path: PathBuf,
+
+    /// What the script is allowed to do outside of its own state.
+    #[serde(default)]
+    pub capabilities: Capabilities,
+
+    /// Instruction and memory limits for the script.
+    #[serde(default)]
+    pub limits: Limits,
+}
+
+/// A single loaded script and its sandboxed Lua state.
+pub struct Script {
+    name: String,
+    cfg: ScriptConfig,
+    lua: Lua,
+    budget: Arc<sandbox::Budget>,
+    /// Only scripts with the FILESYSTEM capability have one.
+    store: Option<Arc<Mutex<Store>>>,
+    enabled: bool,
+
+    /// Wakers of the script's background tasks, by task id.
+    wakers: HashMap<Integer, Arc<tasks::TaskWaker>>,
+    /// Where task wakers wake the main loop.
+    requests: Requests,
+}
+
+impl Script {
+    /// Create the script's sandbox, install the `eesh` API
+    /// according to its capabilities and run its entry point.
+    pub fn load(
+        name: &str,
+        cfg: ScriptConfig,
+        data_dir: PathBuf,
+        requests: Requests,
+    ) -> Result<Script> {
+        let (lua, budget) = sandbox::new_state(cfg.limits)?;
+        let store = if cfg.capabilities.contains(Capabilities::FILESYSTEM) {
+            let store = Store::open(data_dir.join("store.toml"), cfg.limits.store)?;
+            Some(Arc::new(Mutex::new(store)))
+        } else {
+            None
+        };
+
+        let mut script = Script {
+            name: name.to_owned(),
+            cfg,
+            lua,
+            budget,
+            store,
+            enabled: true,
+            wakers: HashMap::new(),
+            requests: requests.clone(),
+        };
+        script.install_api(data_dir, requests)?;
+
+        let source = std::fs::read_to_string(&script.cfg.path)?;
+        script.guarded(|lua| lua.load(&source).set_name(name).exec())?;
+
+        Ok(script)
+    }
+
+    pub fn name(&self) -> &str {
+        &self.name
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.enabled
+    }
+
+    pub fn store(&self) -> Option<&Arc<Mutex<Store>>> {
+        self.store.as_ref()
+    }
+
+    /// Write the script's store to disk, if it has changes
+    /// which have waited long enough.
+    pub fn save_store(&self) {
+        let Some(store) = &self.store else {
+            return;
+        };
+        let result = store
+            .lock()
+            .expect("Script store was poisoned!")
+            .save_if_due();
+        if let Err(e) = result {
+            error!(
+                script = self.name,
+                error = e.to_string(),
+                "Script store can't be saved"
+            );
+        }
+    }
+
+    /// Call every handler the script registered for `event`.
+    pub fn emit<A>(&mut self, event: &str, args: A)
+    where
+        A: for<'lua> IntoLuaMulti<'lua> + Clone,
+    {
+        if !self.enabled {
+            return;
+        }
+
+        let result = self.guarded(|lua| {
+            let handlers: Table = lua.named_registry_value(HANDLERS_KEY)?;
+            let Some(list) = handlers.get::<_, Option<Table>>(event)? else {
+                return Ok(());
+            };
+            for handler in list.sequence_values::<Function>() {
+                handler?.call::<_, ()>(args.clone())?;
+            }
+            Ok(())
+        });
+
+        if let Err(e) = result {
+            error!(
+                script = self.name,
+                event,
+                error = e.to_string(),
+                "Script handler failed"
+            );
+        }
+    }
+
+    /// Resume every background task the script has scheduled.
+    /// All tasks share a single instruction budget per frame,
+    /// so a busy task can't stall the main loop.
+    pub fn poll_tasks(&mut self) {
+        if !self.enabled || !self.cfg.capabilities.contains(Capabilities::TIMERS) {
+            return;
+        }
+
+        let limits = self.cfg.limits;
+        let budget = Arc::clone(&self.budget);
+        let mut wakers = std::mem::take(&mut self.wakers);
+        let requests = self.requests.clone();
+        let result = self.guarded(|lua| {
+            let result = tasks::pending(lua).and_then(|pending| {
+                // Forget the wakers of tasks which have finished.
+                wakers.retain(|id, _| pending.iter().any(|(task, _)| task == id));
+
+                pending.iter().try_for_each(|(id, thread)| {
+                    let waker = wakers
+                        .entry(*id)
+                        .or_insert_with(|| tasks::TaskWaker::new(requests.clone()));
+                    if !waker.take_woken() {
+                        return Ok(());
+                    }
+                    thread.set_hook(
+                        sandbox::TRIGGERS,
+                        sandbox::instruction_hook(limits, Arc::clone(&budget)),
+                    );
+                    tasks::poll(lua, *id, thread, Arc::clone(waker))
+                })
+            });
+
+            // Setting a thread hook moves the hook off of the main
+            // thread, so it has to be put back once we're done.
+            lua.set_hook(
+                sandbox::TRIGGERS,
+                sandbox::instruction_hook(limits, budget),
+            );
+            result
+        });
+
+        self.wakers = wakers;
+        if let Err(e) = result {
+            error!(
+                script = self.name,
+                error = e.to_string(),
+                "Script task failed"
+            );
+        }
+    }
+
+    /// Run `f` against the script's state with a fresh instruction
+    /// budget, disabling the script if it goes over its limits,
+    /// even if it caught the error.
+    fn guarded<T>(&mut self, f: impl FnOnce(&Lua) -> mlua::Result<T>) -> mlua::Result<T> {
+        self.budget.reset();
+        let mut result = f(&self.lua);
+
+        if let Err(e) = &result {
+            if sandbox::is_memory_error(e) {
+                self.budget.exceed("memory");
+            }
+        }
+        if let Some(limit) = self.budget.exceeded() {
+            self.enabled = false;
+            error!(
+                script = self.name,
+                limit, "Script exceeded its {limit} limit and has been disabled"
+            );
+            if result.is_ok() {
+                result = Err(mlua::Error::runtime(format!("{limit} limit exceeded")));
+            }
+        }
+
+        result
+    }
+
+    fn install_api(&self, data_dir: PathBuf, requests: Requests) -> mlua::Result<()> {
+        let lua = &self.lua;
+        let caps = self.cfg.capabilities;
+        let eesh = lua.create_table()?;
+
+        lua.set_named_registry_value(HANDLERS_KEY, lua.create_table()?)?;
+        eesh.set(
+            "on",
+            lua.create_function(|lua, (event, handler): (String, Function)| {
+                let handlers: Table = lua.named_registry_value(HANDLERS_KEY)?;
+                let list = match handlers.get::<_, Option<Table>>(event.as_str())? {
+                    Some(list) => list,
+                    None => {
+                        let list = lua.create_table()?;
+                        handlers.set(event, list.clone())?;
+                        list
+                    }
+                };
+                list.push(handler)
+            })?,
+        )?;
+
+        let name = self.name.clone();
+        eesh.set(
+            "log",
+            lua.create_function(move |_, msg: String| {
+                info!(script = name, "{msg}");
+                Ok(())
+            })?,
+        )?;
+
+        if let Some(store) = &self.store {
+            store::install(lua, &eesh, Arc::clone(store))?;
+        }
+
+        if caps.contains(Capabilities::NETWORK) {
+            eesh.set(
+                "send",
+                lua.create_function(
+                    move |_, (server, channel, text): (String, String, String)| {
+                        requests
+                            .send(Request::SendMessage {
+                                server,
+                                channel,
+                                text,
+                            })
+                            .map_err(mlua::Error::external)
+                    },
+                )?,
+            )?;
+        }
+
+        if caps.contains(Capabilities::FILESYSTEM) {
+            std::fs::create_dir_all(&data_dir).map_err(mlua::Error::external)?;
+            let fs = lua.create_table()?;
+
+            let root = data_dir.clone();
+            fs.set(
+                "read",
+                lua.create_function(move |_, path: String| {
+                    std::fs::read_to_string(sandbox::resolve_in(&root, &path)?)
+                        .map_err(mlua::Error::external)
+                })?,
+            )?;
+
+            let root = data_dir;
+            fs.set(
+                "write",
+                lua.create_function(move |_, (path, contents): (String, String)| {
+                    std::fs::write(sandbox::resolve_in(&root, &path)?, contents)
+                        .map_err(mlua::Error::external)
+                })?,
+            )?;
+
+            eesh.set("fs", fs)?;
+        }
+
+        if caps.contains(Capabilities::PROCESS) {
+            eesh.set(
+                "spawn",
+                lua.create_function(|_, (program, args): (String, Option<Vec<String>>)| {
+                    let runtime =
+                        tokio::runtime::Handle::try_current().map_err(mlua::Error::external)?;
+                    let _guard = runtime.enter();
+                    // Null stdio, so the process can't draw over the TUI.
+                    let mut child = tokio::process::Command::new(program)
+                        .args(args.unwrap_or_default())
+                        .stdin(Stdio::null())
+                        .stdout(Stdio::null())
+                        .stderr(Stdio::null())
+                        .spawn()
+                        .map_err(mlua::Error::external)?;
+                    let id = child.id();
+                    // Reap the process once it exits, so it isn't left a zombie.
+                    runtime.spawn(async move { child.wait().await });
+                    Ok(id)
+                })?,
+            )?;
+        }
+
+        if caps.contains(Capabilities::TIMERS) {
+            tasks::install(lua, &eesh)?;
+        }
+
+        lua.globals().set("eesh", eesh)
+    }
+}
+
+/// The Lua runtime, exposed to the rest of the
+/// application as a single plugin which hosts
+/// every configured script.
+#[derive(Default)]
+pub struct ScriptHost {
+    scripts: Vec<Script>,
+
+    /// Every configured script, loaded or not,
+    /// so that scripts can be reloaded by name.
+    configs: HashMap<String, ScriptConfig>,
+    requests: Option<Requests>,
+}
+
+impl ScriptHost {
+    pub fn new() -> Self {
+        ScriptHost::default()
+    }
+
+    pub fn scripts(&self) -> &[Script] {
+        &self.scripts
+    }
+
+    pub fn get(&self, name: &str) -> Option<&Script> {
+        self.scripts.iter().find(|script| script.name() == name)
+    }
+
+    /// Unload a script by name. Dropping its Lua state also
+    /// drops every timer and task it had scheduled.
+    pub fn unload(&mut self, name: &str) -> bool {
+        let before = self.scripts.len();
+        self.scripts.retain(|script| script.name() != name);
+        self.scripts.len() != before
+    }
+
+    /// Load, or load again, the script configured as `name`.
+    /// The script is run afresh from its file, keeping only
+    /// what it saved to its store.
+    pub fn reload(&mut self, name: &str) -> Result<()> {
+        let (Some(cfg), Some(requests)) = (self.configs.get(name), &self.requests) else {
+            bail!("No script named \"{name}\" is configured");
+        };
+        let data_dir = crate::paths::data_dir().join("scripts").join(name);
+        let script = Script::load(name, cfg.clone(), data_dir, requests.clone())?;
+
+        match self.scripts.iter_mut().find(|s| s.name() == name) {
+            Some(loaded) => *loaded = script,
+            None => self.scripts.push(script),
+        }
+        Ok(())
+    }
+
+    /// `script [list]`, `script reload <name>` or
+    /// `script unload <name>`.
+    fn script_command(&mut self, args: &[String]) -> Result<()> {
+        match args {
+            [] => self.list(),
+            [action] if action == "list" => self.list(),
+            [action, name] if action == "reload" => {
+                self.reload(name)?;
+                info!(script = name, "Reloaded script");
+            }
+            [action, name] if action == "unload" => {
+                if !self.unload(name) {
+                    bail!("No script named \"{name}\" is loaded");
+                }
+                info!(script = name, "Unloaded script");
+            }
+            _ => bail!("Usage: script [list|reload <name>|unload <name>]"),
+        }
+        Ok(())
+    }
+
+    fn list(&self) {
+        let mut names: Vec<_> = self.configs.keys().collect();
+        names.sort();
+        for name in names {
+            let state = match self.get(name) {
+                Some(script) if script.is_enabled() => "loaded",
+                Some(_) => "disabled",
+                None => "not loaded",
+            };
+            info!("  {name}: {state}");
+        }
+    }
+
+    /// Call every handler registered for `event` in every enabled script.
+    pub fn emit<A>(&mut self, event: &str, args: A)
+    where
+        A: for<'a> IntoLuaMulti<'a> + Clone,
+    {
+        for script in &mut self.scripts {
+            script.emit(event, args.clone());
+        }
+    }
+
+    /// `store <script> [clear]`: print or erase a script's storage.
+    fn store_command(&self, args: &[String]) -> Result<()> {
+        let (script, clear) = match args {
+            [script] => (script, false),
+            [script, action] if action == "clear" => (script, true),
+            _ => bail!("Usage: store <script> [clear]"),
+        };
+        let Some(script) = self.get(script) else {
+            bail!("No script named \"{script}\" is loaded");
+        };
+        let Some(store) = script.store() else {
+            bail!("Script \"{}\" has no store without the filesystem capability", script.name());
+        };
+
+        let mut store = store.lock().expect("Script store was poisoned!");
+        if clear {
+            store.clear()?;
+            info!(script = script.name(), "Cleared script store");
+        } else {
+            info!(
+                script = script.name(),
+                keys = store.values().len(),
+                "Script store"
+            );
+            for (key, value) in store.values() {
+                info!("  {key} = {value}");
+            }
+        }
+        Ok(())
+    }
+}
+
+impl Plugin for ScriptHost {
+    fn name(&self) -> &str {
+        "lua"
+    }
+
+    /// Load every configured script. Scripts that fail to
+    /// load are reported and skipped rather than failing
+    /// the whole plugin.
+    fn load(&mut self, cfg: &Config, requests: Requests) -> Result<()> {
+        self.configs = cfg.scripts.clone();
+        self.requests = Some(requests);
+        for name in cfg.scripts.keys() {
+            if let Err(e) = self.reload(name) {
+                warn!(
+                    script = name,
+                    error = e.to_string(),
+                    "Failed to load script"
+                );
+            }
+        }
+        Ok(())
+    }
+
+    fn on_event(&mut self, event: Event<'_>) {
+        match event {
+            Event::Message {
+                server,
+                target,
+                source,
+                text,
+            } => self.emit(event.name(), (server, target, source, text)),
+            Event::Join {
+                server,
+                channel,
+                nick,
+            }
+            | Event::Part {
+                server,
+                channel,
+                nick,
+            } => self.emit(event.name(), (server, channel, nick)),
+            Event::Quit { server, nick } => self.emit(event.name(), (server, nick)),
+            Event::Nick { server, old, new } => self.emit(event.name(), (server, old, new)),
+            Event::Topic {
+                server,
+                channel,
+                topic,
+            } => self.emit(event.name(), (server, channel, topic)),
+        }
+    }
+
+    /// Resume the background tasks of every loaded script,
+    /// and save the stores they have changed.
+    fn tick(&mut self) {
+        for script in &mut self.scripts {
+            script.poll_tasks();
+            script.save_store();
+        }
+    }
+
+    fn command(&mut self, name: &str, args: &[String]) -> Option<Result<()>> {
+        match name {
+            "store" => Some(self.store_command(args)),
+            "script" => Some(self.script_command(args)),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,337 @@
+use bitflags::bitflags;
+use mlua::{Debug, HookTriggers, Lua, LuaOptions, StdLib, Thread, Value};
+use serde::Deserialize;
+use std::{
+    io,
+    path::{Component, Path, PathBuf},
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, OnceLock,
+    },
+};
+
+/// How many VM instructions run between each
+/// check of the instruction budget.
+const INSTRUCTION_GRANULARITY: u32 = 1000;
+
+/// Hook triggers for the instruction budget. Hooks are
+/// per-thread, so coroutines need their own copy of the
+/// hook installed before they are resumed.
+pub const TRIGGERS: HookTriggers =
+    HookTriggers::new().every_nth_instruction(INSTRUCTION_GRANULARITY);
+
+bitflags! {
+    /// Everything a script may do outside of its own Lua state.
+    /// A script with no capabilities can still compute, log,
+    /// and react to events, but can't affect anything else.
+    #[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+    #[serde(try_from = "Vec<String>")]
+    pub struct Capabilities: u8 {
+        /// Send messages to IRC servers.
+        const NETWORK = 1 << 0;
+        /// Read and write files under the script's data
+        /// directory, including its persistent store.
+        const FILESYSTEM = 1 << 1;
+        /// Launch external processes.
+        const PROCESS = 1 << 2;
+        /// Schedule callbacks to run later.
+        const TIMERS = 1 << 3;
+    }
+}
+
+impl TryFrom<Vec<String>> for Capabilities {
+    type Error = String;
+
+    /// Capabilities are written in the config file as a
+    /// list of flag names, e.g. `["network", "timers"]`.
+    fn try_from(names: Vec<String>) -> Result<Self, Self::Error> {
+        names.iter().try_fold(Capabilities::empty(), |acc, name| {
+            Capabilities::from_name(&name.to_uppercase())
+                .map(|cap| acc | cap)
+                .ok_or_else(|| format!("Unknown script capability \"{name}\""))
+        })
+    }
+}
+
+/// Resource limits enforced on every call into a script.
+#[derive(Clone, Copy, Debug, Deserialize)]
+#[serde(default)]
+pub struct Limits {
+    /// Maximum number of VM instructions a single
+    /// call into the script may execute.
+    pub instructions: u64,
+
+    /// Maximum number of bytes the script's Lua
+    /// state may allocate in total.
+    pub memory: usize,
+
+    /// Maximum number of bytes the script's
+    /// persistent store may take up.
+    pub store: usize,
+}
+
+impl Default for Limits {
+    fn default() -> Self {
+        Limits {
+            instructions: 10_000_000,
+            memory: 16 * 1024 * 1024,
+            store: 1024 * 1024,
+        }
+    }
+}
+
+/// Replaces the error-catching functions of the base and
+/// coroutine libraries with ones that pass an error on once
+/// the script has gone over a limit, so that it can't catch
+/// the error and carry on regardless. Resuming a coroutine
+/// also moves the instruction hook onto it for the duration.
+const GUARDS: &str = r#"
+local tripped, move_hook = ...
+local pcall, xpcall = pcall, xpcall
+local create, resume, close = coroutine.create, coroutine.resume, coroutine.close
+
+local function rethrow(ok, ...)
+    if not ok and tripped((...)) then
+        error((...), 0)
+    end
+    return ok, ...
+end
+
+local function resumed(...)
+    move_hook()
+    return rethrow(...)
+end
+
+local function unwrap(ok, ...)
+    if not ok then
+        error((...), 0)
+    end
+    return ...
+end
+
+function _G.pcall(...) return rethrow(pcall(...)) end
+function _G.xpcall(...) return rethrow(xpcall(...)) end
+function coroutine.close(...) return rethrow(close(...)) end
+
+function coroutine.resume(co, ...)
+    move_hook(co)
+    return resumed(resume(co, ...))
+end
+
+function coroutine.wrap(f)
+    local co = create(f)
+    return function(...) return unwrap(coroutine.resume(co, ...)) end
+end
+"#;
+
+/// How much of its limits a script has used. Once a limit
+/// has been exceeded it stays exceeded, and the script has
+/// to be disabled.
+#[derive(Debug, Default)]
+pub struct Budget {
+    /// Instructions run since the budget was last reset.
+    executed: AtomicU64,
+    /// The first limit the script went over.
+    exceeded: OnceLock<&'static str>,
+}
+
+impl Budget {
+    /// Start counting instructions for a new call into the script.
+    pub fn reset(&self) {
+        self.executed.store(0, Ordering::Relaxed);
+    }
+
+    /// Which limit was exceeded, if any.
+    pub fn exceeded(&self) -> Option<&'static str> {
+        self.exceeded.get().copied()
+    }
+
+    /// Record that `limit` was exceeded, unless
+    /// another limit already was.
+    pub fn exceed(&self, limit: &'static str) {
+        let _ = self.exceeded.set(limit);
+    }
+}
+
+/// Create a Lua state with only the side-effect free parts of
+/// the standard library loaded and the given limits installed.
+/// The returned budget must be reset before each call into
+/// the script.
+pub fn new_state(limits: Limits) -> mlua::Result<(Lua, Arc<Budget>)> {
+    let lua = Lua::new_with(
+        StdLib::TABLE | StdLib::STRING | StdLib::MATH | StdLib::UTF8 | StdLib::COROUTINE,
+        LuaOptions::default(),
+    )?;
+
+    // The base library is always loaded, so strip the parts of
+    // it that touch the filesystem, write over the TUI, or
+    // compile code the script didn't ship with.
+    let globals = lua.globals();
+    for name in ["dofile", "loadfile", "load", "print"] {
+        globals.raw_remove(name)?;
+    }
+    drop(globals);
+
+    let budget = Arc::new(Budget::default());
+    let tripped = {
+        let budget = Arc::clone(&budget);
+        lua.create_function(move |_, err: Value| {
+            if caught_memory_error(&err) {
+                budget.exceed("memory");
+            }
+            Ok(budget.exceeded().is_some())
+        })?
+    };
+    // Moves the hook onto a coroutine about to be resumed, or
+    // back onto the running thread once it has yielded.
+    let move_hook = {
+        let budget = Arc::clone(&budget);
+        lua.create_function(move |lua, co: Option<Thread>| {
+            let thread = co.unwrap_or_else(|| lua.current_thread());
+            thread.set_hook(TRIGGERS, instruction_hook(limits, Arc::clone(&budget)));
+            Ok(())
+        })?
+    };
+    lua.load(GUARDS)
+        .set_name("eesh.guards")
+        .call::<_, ()>((tripped, move_hook))?;
+
+    lua.set_memory_limit(limits.memory)?;
+    lua.set_hook(TRIGGERS, instruction_hook(limits, Arc::clone(&budget)));
+
+    Ok((lua, budget))
+}
+
+/// Build the hook that aborts a call once it has run more
+/// instructions than `limits` allow, and aborts every call
+/// after that straight away.
+pub fn instruction_hook(
+    limits: Limits,
+    budget: Arc<Budget>,
+) -> impl Fn(&Lua, Debug) -> mlua::Result<()> + Send + 'static {
+    move |_lua, _debug| {
+        if let Some(limit) = budget.exceeded() {
+            return Err(mlua::Error::runtime(format!("{limit} limit exceeded")));
+        }
+
+        let total = budget
+            .executed
+            .fetch_add(INSTRUCTION_GRANULARITY.into(), Ordering::Relaxed);
+        if total >= limits.instructions {
+            budget.exceed("instruction");
+            return Err(mlua::Error::runtime("instruction limit exceeded"));
+        }
+        Ok(())
+    }
+}
+
+/// Whether an error, or whatever caused it,
+/// came from running out of memory.
+pub fn is_memory_error(mut err: &mlua::Error) -> bool {
+    loop {
+        match err {
+            mlua::Error::MemoryError(_) => return true,
+            mlua::Error::CallbackError { cause, .. } => err = cause,
+            _ => return false,
+        }
+    }
+}
+
+/// `is_memory_error` for an error value caught in Lua.
+fn caught_memory_error(err: &Value) -> bool {
+    match err {
+        Value::String(s) => s.as_bytes() == b"not enough memory",
+        Value::Error(err) => is_memory_error(err),
+        _ => false,
+    }
+}
+
+/// Resolve a path a script asked for against its data directory,
+/// refusing anything that would escape it. Symlinks are followed
+/// before checking, so one inside the directory can't lead out.
+pub fn resolve_in(root: &Path, requested: &str) -> mlua::Result<PathBuf> {
+    let outside = || {
+        mlua::Error::runtime(format!(
+            "path \"{requested}\" is outside of the script data directory"
+        ))
+    };
+    let escapes = Path::new(requested)
+        .components()
+        .any(|c| !matches!(c, Component::Normal(_) | Component::CurDir));
+    if escapes {
+        return Err(outside());
+    }
+
+    let root = root.canonicalize().map_err(mlua::Error::external)?;
+    let path = root.join(requested);
+    let resolved = match path.canonicalize() {
+        Ok(resolved) => resolved,
+        // A file yet to be written, as long as it isn't a
+        // dangling symlink, is checked by where its parent is.
+        Err(e) if e.kind() == io::ErrorKind::NotFound && path.symlink_metadata().is_err() => {
+            let (Some(parent), Some(name)) = (path.parent(), path.file_name()) else {
+                return Err(outside());
+            };
+            parent
+                .canonicalize()
+                .map_err(mlua::Error::external)?
+                .join(name)
+        }
+        Err(e) => return Err(mlua::Error::external(e)),
+    };
+
+    if !resolved.starts_with(&root) {
+        return Err(outside());
+    }
+    Ok(resolved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::resolve_in;
+
+    fn data_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("eesh-sandbox-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn resolves_paths_inside_the_data_dir() {
+        let root = data_dir("inside");
+        std::fs::create_dir_all(root.join("sub")).unwrap();
+        std::fs::write(root.join("sub/a.txt"), "a").unwrap();
+
+        let real = root.canonicalize().unwrap();
+        assert_eq!(
+            resolve_in(&root, "sub/a.txt").unwrap(),
+            real.join("sub/a.txt")
+        );
+        assert_eq!(
+            resolve_in(&root, "./new.txt").unwrap(),
+            real.join("new.txt")
+        );
+        assert!(resolve_in(&root, "../a.txt").is_err());
+        assert!(resolve_in(&root, "/etc/passwd").is_err());
+        // Nor can files be made in directories that don't exist.
+        assert!(resolve_in(&root, "missing/a.txt").is_err());
+
+        std::fs::remove_dir_all(root).unwrap();
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn symlinks_cannot_lead_out() {
+        let root = data_dir("symlink");
+        let outside = data_dir("outside");
+        std::os::unix::fs::symlink(&outside, root.join("out")).unwrap();
+        std::os::unix::fs::symlink(outside.join("gone"), root.join("dangling")).unwrap();
+
+        assert!(resolve_in(&root, "out").is_err());
+        assert!(resolve_in(&root, "out/new.txt").is_err());
+        assert!(resolve_in(&root, "dangling").is_err());
+
+        std::fs::remove_dir_all(root).unwrap();
+        std::fs::remove_dir_all(outside).unwrap();
+    }
+}
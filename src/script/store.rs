@@ -0,0 +1,207 @@
+use color_eyre::eyre::{bail, Result};
+use mlua::{Lua, LuaSerdeExt, Table, Value};
+use std::{
+    path::{Path, PathBuf},
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tracing::warn;
+
+/// How long changes are held before the store is written,
+/// so a script setting keys in a loop writes only once.
+const SAVE_DELAY: Duration = Duration::from_secs(2);
+
+/// Persistent key-value storage for a single script,
+/// kept in a TOML file in the script's data directory.
+pub struct Store {
+    path: PathBuf,
+    values: toml::Table,
+
+    /// Most bytes the keys and values may take up.
+    limit: usize,
+    /// Bytes the keys and values take up now.
+    size: usize,
+    /// When the oldest change not yet written was made.
+    changed: Option<Instant>,
+}
+
+impl Store {
+    /// Open the store at `path`, starting empty if the file
+    /// does not exist yet. The store can't grow past `limit`
+    /// bytes, though one already bigger is still read.
+    pub fn open(path: impl AsRef<Path>, limit: usize) -> Result<Store> {
+        let path = path.as_ref().to_owned();
+        let values: toml::Table = match std::fs::read_to_string(&path) {
+            Ok(raw) => toml::from_str(&raw)?,
+            Err(e) if e.kind() == std::io::ErrorKind::NotFound => toml::Table::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let size = values.iter().map(|(k, v)| entry_size(k, v)).sum();
+
+        Ok(Store {
+            path,
+            values,
+            limit,
+            size,
+            changed: None,
+        })
+    }
+
+    pub fn get(&self, key: &str) -> Option<&toml::Value> {
+        self.values.get(key)
+    }
+
+    pub fn values(&self) -> &toml::Table {
+        &self.values
+    }
+
+    /// Set or, if `value` is `None`, remove a key. Fails if
+    /// the store would grow past its limit. The change is
+    /// written to disk by `save_if_due`, or when dropped.
+    pub fn set(&mut self, key: &str, value: Option<toml::Value>) -> Result<()> {
+        let old = self.values.get(key).map_or(0, |v| entry_size(key, v));
+        let new = value.as_ref().map_or(0, |v| entry_size(key, v));
+        let size = self.size - old + new;
+        if new > old && size > self.limit {
+            bail!("Setting \"{key}\" would grow the store past {} bytes", self.limit);
+        }
+
+        match value {
+            Some(value) => self.values.insert(key.to_owned(), value),
+            None => self.values.remove(key),
+        };
+        self.size = size;
+        self.changed.get_or_insert_with(Instant::now);
+        Ok(())
+    }
+
+    /// Remove every key and write the now-empty store to disk.
+    pub fn clear(&mut self) -> Result<()> {
+        self.values.clear();
+        self.size = 0;
+        self.save()
+    }
+
+    /// Write the store to disk if it was changed at least
+    /// `SAVE_DELAY` ago.
+    pub fn save_if_due(&mut self) -> Result<()> {
+        match self.changed {
+            Some(changed) if changed.elapsed() >= SAVE_DELAY => self.save(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Write to a temporary file next to the store and then rename
+    /// it over the original, so a crash mid-write never leaves a
+    /// truncated store behind.
+    fn save(&mut self) -> Result<()> {
+        if let Some(parent) = self.path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let tmp = self.path.with_extension("toml.tmp");
+        std::fs::write(&tmp, toml::to_string(&self.values)?)?;
+        std::fs::rename(&tmp, &self.path)?;
+
+        self.changed = None;
+        Ok(())
+    }
+}
+
+impl Drop for Store {
+    /// Write any changes still held back.
+    fn drop(&mut self) {
+        if self.changed.is_none() {
+            return;
+        }
+        if let Err(e) = self.save() {
+            warn!(
+                path = %self.path.display(),
+                error = e.to_string(),
+                "Script store can't be saved"
+            );
+        }
+    }
+}
+
+/// Roughly how many bytes a key and its value take up.
+fn entry_size(key: &str, value: &toml::Value) -> usize {
+    key.len() + value.to_string().len()
+}
+
+/// Install `eesh.store.get`, `eesh.store.set` and
+/// `eesh.store.keys` into the given API table.
+pub fn install(lua: &Lua, eesh: &Table, store: Arc<Mutex<Store>>) -> mlua::Result<()> {
+    let api = lua.create_table()?;
+
+    let s = Arc::clone(&store);
+    api.set(
+        "get",
+        lua.create_function(move |lua, key: String| {
+            match s.lock().expect("Script store was poisoned!").get(&key) {
+                Some(value) => lua.to_value(value),
+                None => Ok(Value::Nil),
+            }
+        })?,
+    )?;
+
+    let s = Arc::clone(&store);
+    api.set(
+        "set",
+        lua.create_function(move |lua, (key, value): (String, Value)| {
+            let value = match value {
+                Value::Nil => None,
+                value => Some(lua.from_value(value)?),
+            };
+            s.lock()
+                .expect("Script store was poisoned!")
+                .set(&key, value)
+                .map_err(|e| mlua::Error::runtime(e.to_string()))
+        })?,
+    )?;
+
+    api.set(
+        "keys",
+        lua.create_function(move |_, ()| {
+            Ok(store
+                .lock()
+                .expect("Script store was poisoned!")
+                .values()
+                .keys()
+                .cloned()
+                .collect::<Vec<_>>())
+        })?,
+    )?;
+
+    eesh.set("store", api)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Store;
+
+    #[test]
+    fn keeps_to_its_limit_and_saves_when_dropped() {
+        let path = std::env::temp_dir().join(format!("eesh-store-{}.toml", std::process::id()));
+        let text = |len: usize| Some(toml::Value::String("x".repeat(len)));
+
+        let mut store = Store::open(&path, 64).unwrap();
+        store.set("a", text(40)).unwrap();
+        assert!(store.set("b", text(40)).is_err());
+        assert!(store.get("b").is_none());
+
+        // Shrinking or removing a key always works.
+        store.set("a", text(10)).unwrap();
+        store.set("b", text(40)).unwrap();
+        store.set("a", None).unwrap();
+        assert!(!path.exists());
+        drop(store);
+
+        let store = Store::open(&path, 64).unwrap();
+        assert!(store.get("a").is_none());
+        assert_eq!(store.get("b"), text(40).as_ref());
+
+        drop(store);
+        std::fs::remove_file(path).unwrap();
+    }
+}
@@ -0,0 +1,243 @@
+use mlua::{Function, Integer, Lua, Table, Thread, ThreadStatus};
+use std::{
+    future::Future,
+    pin::pin,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+    task::{Context, Poll, Wake, Waker},
+    time::Duration,
+};
+
+use crate::plugin::{Request, Requests};
+
+/// Name of the registry table mapping task ids to the
+/// coroutines running them.
+const TASKS_KEY: &str = "eesh.tasks";
+
+/// Name of the registry value holding the next task id.
+const NEXT_TASK_KEY: &str = "eesh.next_task";
+
+/// Timers are plain tasks that sleep before calling back,
+/// so they are written in Lua on top of `eesh.async`.
+const PRELUDE: &str = r#"
+local eesh = ...
+
+function eesh.timer(ms, fn)
+    return eesh.async(function()
+        eesh.sleep(ms)
+        fn()
+    end)
+end
+
+function eesh.every(ms, fn)
+    return eesh.async(function()
+        while true do
+            eesh.sleep(ms)
+            fn()
+        end
+    end)
+end
+"#;
+
+/// Install `eesh.async`, `eesh.sleep`, `eesh.cancel`,
+/// `eesh.timer` and `eesh.every` into the given API table.
+pub fn install(lua: &Lua, eesh: &Table) -> mlua::Result<()> {
+    lua.set_named_registry_value(TASKS_KEY, lua.create_table()?)?;
+    lua.set_named_registry_value(NEXT_TASK_KEY, 1)?;
+
+    eesh.set(
+        "async",
+        lua.create_function(|lua, f: Function| spawn(lua, f))?,
+    )?;
+    eesh.set(
+        "cancel",
+        lua.create_function(|lua, id: Integer| cancel(lua, id))?,
+    )?;
+    eesh.set(
+        "sleep",
+        lua.create_async_function(|_, ms: u64| async move {
+            tokio::time::sleep(Duration::from_millis(ms)).await;
+            Ok(())
+        })?,
+    )?;
+
+    lua.load(PRELUDE).set_name("eesh.tasks").call(eesh.clone())
+}
+
+/// Schedule `f` to run as a background task, returning
+/// an id that can be passed to `eesh.cancel`.
+fn spawn(lua: &Lua, f: Function) -> mlua::Result<Integer> {
+    let id: Integer = lua.named_registry_value(NEXT_TASK_KEY)?;
+    lua.set_named_registry_value(NEXT_TASK_KEY, id + 1)?;
+
+    let tasks: Table = lua.named_registry_value(TASKS_KEY)?;
+    tasks.raw_set(id, lua.create_thread(f)?)?;
+
+    Ok(id)
+}
+
+pub fn cancel(lua: &Lua, id: Integer) -> mlua::Result<()> {
+    let tasks: Table = lua.named_registry_value(TASKS_KEY)?;
+    tasks.raw_set(id, mlua::Nil)
+}
+
+/// Every task which has not yet finished or been cancelled.
+pub fn pending(lua: &Lua) -> mlua::Result<Vec<(Integer, Thread<'_>)>> {
+    let tasks: Table = lua.named_registry_value(TASKS_KEY)?;
+    tasks.pairs::<Integer, Thread>().collect()
+}
+
+/// Wakes a task by marking it to be resumed on the next
+/// tick, and waking the main loop to get there sooner.
+pub struct TaskWaker {
+    woken: AtomicBool,
+    requests: Requests,
+}
+
+impl TaskWaker {
+    /// A waker for a new task, which is due to be resumed.
+    pub fn new(requests: Requests) -> Arc<Self> {
+        Arc::new(TaskWaker {
+            woken: AtomicBool::new(true),
+            requests,
+        })
+    }
+
+    /// Whether the task has been woken since this was last called.
+    pub fn take_woken(&self) -> bool {
+        self.woken.swap(false, Ordering::Relaxed)
+    }
+}
+
+impl Wake for TaskWaker {
+    fn wake(self: Arc<Self>) {
+        self.wake_by_ref();
+    }
+
+    fn wake_by_ref(self: &Arc<Self>) {
+        self.woken.store(true, Ordering::Relaxed);
+        // The main loop has stopped if this fails.
+        let _ = self.requests.send(Request::Wake);
+    }
+}
+
+/// Resume a task until it next waits on something, removing
+/// it once it has run to completion or failed.
+///
+/// Whatever the task waits on lives in its thread, so the
+/// future driving the thread can be dropped between polls.
+/// It registers `waker`, which is woken once the task can
+/// make progress again.
+pub fn poll(lua: &Lua, id: Integer, thread: &Thread, waker: Arc<TaskWaker>) -> mlua::Result<()> {
+    let task_waker = Waker::from(Arc::clone(&waker));
+    let mut cx = Context::from_waker(&task_waker);
+    let future = pin!(thread.clone().into_async::<_, ()>(()));
+
+    let result = match future.poll(&mut cx) {
+        Poll::Pending => return Ok(()),
+        Poll::Ready(result) => result,
+    };
+
+    if result.is_err() || thread.status() != ThreadStatus::Resumable {
+        cancel(lua, id)?;
+    } else {
+        // It yielded without waiting on anything, so
+        // carries on next tick.
+        waker.wake_by_ref();
+    }
+
+    result
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{install, pending, poll, TaskWaker};
+    use crate::plugin::Requests;
+    use mlua::{Integer, Lua};
+    use std::time::Duration;
+
+    fn lua() -> Lua {
+        let lua = Lua::new();
+        let eesh = lua.create_table().unwrap();
+        install(&lua, &eesh).unwrap();
+        lua.globals().set("eesh", eesh).unwrap();
+        lua
+    }
+
+    /// Resume every pending task once, the way a tick does.
+    fn tick(lua: &Lua, requests: &Requests) -> mlua::Result<()> {
+        for (id, thread) in pending(lua)? {
+            poll(lua, id, &thread, TaskWaker::new(requests.clone()))?;
+        }
+        Ok(())
+    }
+
+    fn fired(lua: &Lua) -> Integer {
+        lua.globals().get("fired").unwrap()
+    }
+
+    async fn wait() {
+        tokio::time::sleep(Duration::from_millis(30)).await;
+    }
+
+    #[tokio::test]
+    async fn timers_fire_once_after_their_delay() {
+        let (requests, mut woken) = tokio::sync::mpsc::unbounded_channel();
+        let lua = lua();
+        lua.load("fired = 0; eesh.timer(5, function() fired = fired + 1 end)")
+            .exec()
+            .unwrap();
+
+        tick(&lua, &requests).unwrap();
+        assert_eq!(fired(&lua), 0);
+        assert_eq!(pending(&lua).unwrap().len(), 1);
+
+        wait().await;
+        // The sleep finishing wakes the main loop.
+        assert!(woken.try_recv().is_ok());
+        tick(&lua, &requests).unwrap();
+        assert_eq!(fired(&lua), 1);
+        assert!(pending(&lua).unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn cancelled_timers_stop_repeating() {
+        let (requests, _woken) = tokio::sync::mpsc::unbounded_channel();
+        let lua = lua();
+        lua.load("fired = 0; id = eesh.every(5, function() fired = fired + 1 end)")
+            .exec()
+            .unwrap();
+
+        tick(&lua, &requests).unwrap();
+        wait().await;
+        tick(&lua, &requests).unwrap();
+        assert_eq!(fired(&lua), 1);
+        // Still waiting for the next round.
+        assert_eq!(pending(&lua).unwrap().len(), 1);
+
+        lua.load("eesh.cancel(id)").exec().unwrap();
+        assert!(pending(&lua).unwrap().is_empty());
+        wait().await;
+        tick(&lua, &requests).unwrap();
+        assert_eq!(fired(&lua), 1);
+    }
+
+    #[tokio::test]
+    async fn tasks_get_their_own_ids_and_are_dropped_on_error() {
+        let (requests, _woken) = tokio::sync::mpsc::unbounded_channel();
+        let lua = lua();
+        let ids: (Integer, Integer) = lua
+            .load("return eesh.async(function() end), eesh.async(function() error('boom') end)")
+            .eval()
+            .unwrap();
+        assert_eq!(ids, (1, 2));
+
+        let error = tick(&lua, &requests).unwrap_err();
+        assert!(error.to_string().contains("boom"));
+        // The other task ran to completion on the same tick, or will now.
+        tick(&lua, &requests).unwrap();
+        assert!(pending(&lua).unwrap().is_empty());
+    }
+}
@@ -1,12 +1,23 @@
 use chrono_tz::Tz;
+use ratatui::style::Color;
 use serde::Deserialize;
 
+use super::{mirc::ColorDepth, widget::StatusSegment};
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(default)]
 pub struct UIConfig {
     /// How many Lines to keep in the scrollback buffer in-app.
     /// This is PER channel!
-    pub scrollbuffer: u16,
+    pub scrollbuffer: usize,
+
+    /// Write lines pushed out of the scrollback buffer to
+    /// a file, so they can still be scrolled back to.
+    pub spill_scrollback: bool,
+
+    /// Days spilled lines are kept for, so they can
+    /// be scrolled back to in later sessions.
+    pub spill_retention_days: u32,
 
     /// Width of the left pane containing
     /// usernames in the chat log, or log
@@ -16,14 +27,77 @@ pub struct UIConfig {
     /// Time zone to format timestamps for, expressed
     /// as a UTC offset.
     pub tz: Tz,
+
+    /// `chrono` format string for the timestamp column.
+    /// The column is sized to fit the rendered format.
+    pub timestamp_format: String,
+
+    /// Show the timestamp column at all.
+    pub show_timestamps: bool,
+
+    /// How far the lines a long message wraps
+    /// onto are indented, in cells.
+    pub wrap_indent: u16,
+
+    /// Most frames to draw per second. Frames are
+    /// only drawn when something has changed.
+    pub max_fps: u16,
+
+    /// Milliseconds after which a frame is drawn even if
+    /// nothing seems to have changed, e.g. to update lag.
+    pub idle_redraw_ms: u64,
+
+    /// Which segments the status line shows, in order.
+    pub status_line: Vec<StatusSegment>,
+
+    /// Remove mIRC formatting codes from incoming
+    /// text instead of rendering them.
+    pub strip_formatting: bool,
+
+    /// How many colours the terminal supports. Detected
+    /// from the environment when left unset.
+    pub color_depth: Option<ColorDepth>,
+
+    /// Colours nicks are assigned from. Each nick
+    /// always hashes to the same entry.
+    pub nick_palette: Vec<Color>,
+
+    /// Drop palette colours which would be hard to
+    /// read against the theme's background.
+    pub avoid_low_contrast: bool,
 }
 
 impl Default for UIConfig {
     fn default() -> Self {
         UIConfig {
             scrollbuffer: 1024,
+            spill_scrollback: false,
+            spill_retention_days: 7,
             lcol_width: 12,
             tz: chrono_tz::Tz::UTC,
+            timestamp_format: String::from("[%H:%M:%S]"),
+            show_timestamps: true,
+            wrap_indent: 2,
+            max_fps: 60,
+            idle_redraw_ms: 1000,
+            status_line: StatusSegment::defaults(),
+            strip_formatting: false,
+            color_depth: None,
+            nick_palette: vec![
+                Color::Red,
+                Color::Green,
+                Color::Yellow,
+                Color::Blue,
+                Color::Magenta,
+                Color::Cyan,
+                Color::LightRed,
+                Color::LightGreen,
+                Color::LightYellow,
+                Color::LightBlue,
+                Color::LightMagenta,
+                Color::LightCyan,
+            ],
+            avoid_low_contrast: true,
         }
     }
 }
@@ -0,0 +1,493 @@
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+use serde::Deserialize;
+
+use super::{nickcolor, UIConfig};
+
+pub const BOLD: char = '\x02';
+pub const COLOR: char = '\x03';
+pub const HEX_COLOR: char = '\x04';
+pub const RESET: char = '\x0F';
+pub const MONOSPACE: char = '\x11';
+pub const REVERSE: char = '\x16';
+pub const ITALIC: char = '\x1D';
+pub const STRIKETHROUGH: char = '\x1E';
+pub const UNDERLINE: char = '\x1F';
+
+/// Colours 16 through 98 of the extended mIRC palette
+/// as 24-bit RGB values.
+const EXTENDED_RGB: [u32; 83] = [
+    0x470000, 0x472100, 0x474700, 0x324700, 0x004700, 0x00472c, 0x004747, 0x002747, 0x000047,
+    0x2e0047, 0x470047, 0x47002a, 0x740000, 0x743a00, 0x747400, 0x517400, 0x007400, 0x007449,
+    0x007474, 0x004074, 0x000074, 0x4b0074, 0x740074, 0x740045, 0xb50000, 0xb56300, 0xb5b500,
+    0x7db500, 0x00b500, 0x00b571, 0x00b5b5, 0x0063b5, 0x0000b5, 0x7500b5, 0xb500b5, 0xb5006b,
+    0xff0000, 0xff8c00, 0xffff00, 0xb2ff00, 0x00ff00, 0x00ffa0, 0x00ffff, 0x008cff, 0x0000ff,
+    0xa500ff, 0xff00ff, 0xff0098, 0xff5959, 0xffb459, 0xffff71, 0xcfff60, 0x6fff6f, 0x65ffc9,
+    0x6dffff, 0x59b4ff, 0x5959ff, 0xc459ff, 0xff66ff, 0xff59bc, 0xff9c9c, 0xffd39c, 0xffff9c,
+    0xe2ff9c, 0x9cff9c, 0x9cffdb, 0x9cffff, 0x9cd3ff, 0x9c9cff, 0xdc9cff, 0xff9cff, 0xff94d3,
+    0x000000, 0x131313, 0x282828, 0x363636, 0x4d4d4d, 0x656565, 0x818181, 0x9f9f9f, 0xbcbcbc,
+    0xe2e2e2, 0xffffff,
+];
+
+/// Colours 16 through 98 of the extended mIRC palette
+/// as their closest xterm 256-colour equivalents.
+const EXTENDED_ANSI: [u8; 83] = [
+    52, 94, 100, 58, 22, 29, 23, 24, 17, 54, 53, 89, 88, 130, 142, 64, 28, 35, 30, 25, 18, 91, 90,
+    125, 124, 166, 184, 106, 34, 49, 37, 33, 19, 129, 127, 161, 196, 208, 226, 154, 46, 86, 51, 75,
+    21, 171, 201, 198, 203, 215, 227, 191, 83, 122, 87, 111, 63, 177, 207, 205, 217, 223, 229, 193,
+    157, 158, 159, 153, 147, 183, 219, 212, 16, 233, 235, 237, 239, 241, 244, 247, 250, 254, 231,
+];
+
+/// How many colours the terminal can display.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ColorDepth {
+    /// Only the 16 named ANSI colours. Extended mIRC
+    /// colours are dropped rather than approximated.
+    #[default]
+    Ansi16,
+    /// The xterm 256-colour palette.
+    Ansi256,
+    /// 24-bit RGB.
+    TrueColor,
+}
+
+impl ColorDepth {
+    /// Guess the terminal's colour support from `$COLORTERM` and `$TERM`.
+    pub fn detect() -> ColorDepth {
+        let colorterm = std::env::var("COLORTERM").unwrap_or_default();
+        let term = std::env::var("TERM").unwrap_or_default();
+
+        if colorterm == "truecolor" || colorterm == "24bit" {
+            ColorDepth::TrueColor
+        } else if term.contains("256color") {
+            ColorDepth::Ansi256
+        } else {
+            ColorDepth::Ansi16
+        }
+    }
+}
+
+/// Converts raw IRC text containing mIRC control
+/// codes into styled ratatui text.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct Formatter {
+    depth: ColorDepth,
+    strip: bool,
+}
+
+impl Formatter {
+    pub fn new(depth: ColorDepth, strip: bool) -> Self {
+        Formatter { depth, strip }
+    }
+
+    pub fn from_config(cfg: &UIConfig) -> Self {
+        Self::new(
+            cfg.color_depth.unwrap_or_else(ColorDepth::detect),
+            cfg.strip_formatting,
+        )
+    }
+
+    /// Parse `raw` into a line of styled spans. If the formatter
+    /// was configured to strip formatting, the control codes are
+    /// removed and a single unstyled span is produced instead.
+    pub fn parse(&self, raw: &str) -> Line<'static> {
+        self.render(raw, false)
+    }
+
+    /// Like `parse`, but also shows each control code as a
+    /// reversed letter so that text being composed in the
+    /// input bar can be edited.
+    pub fn preview(&self, raw: &str) -> Line<'static> {
+        self.render(raw, true)
+    }
+
+    fn render(&self, raw: &str, show_codes: bool) -> Line<'static> {
+        let mut line = Line::default();
+        let mut state = FormatState::default();
+        let mut buf = String::new();
+        let mut chars = raw.chars().peekable();
+
+        let style_of = |state: FormatState| {
+            if self.strip {
+                Style::default()
+            } else {
+                state.style()
+            }
+        };
+
+        while let Some(c) = chars.next() {
+            let before = state;
+            let params = chars.clone();
+            match c {
+                BOLD => state.bold = !state.bold,
+                ITALIC => state.italic = !state.italic,
+                UNDERLINE => state.underline = !state.underline,
+                STRIKETHROUGH => state.strikethrough = !state.strikethrough,
+                REVERSE => state.reverse = !state.reverse,
+                // Terminals are already monospaced.
+                MONOSPACE => {}
+                RESET => state = FormatState::default(),
+                // A background is only given after a foreground, so
+                // a comma straight after the code is part of the text.
+                COLOR => match take_digits(&mut chars) {
+                    None => (state.fg, state.bg) = (None, None),
+                    Some(fg) => {
+                        state.fg = self.palette(fg);
+                        if let Some(bg) = take_separated(&mut chars, take_digits) {
+                            state.bg = self.palette(bg);
+                        }
+                    }
+                },
+                HEX_COLOR => match take_hex(&mut chars) {
+                    None => (state.fg, state.bg) = (None, None),
+                    Some(fg) => {
+                        state.fg = self.rgb(fg);
+                        if let Some(bg) = take_separated(&mut chars, take_hex) {
+                            state.bg = self.rgb(bg);
+                        }
+                    }
+                },
+                c => {
+                    buf.push(c);
+                    continue;
+                }
+            }
+
+            if show_codes {
+                if !buf.is_empty() {
+                    line.push_span(Span::styled(std::mem::take(&mut buf), style_of(before)));
+                }
+
+                // Keep any colour parameters visible next to the
+                // marker, or they'd vanish as they are typed.
+                let consumed = params.clone().count() - chars.clone().count();
+                let mut marker = String::from(code_marker(c));
+                marker.extend(params.take(consumed));
+                line.push_span(Span::styled(
+                    marker,
+                    Style::default().add_modifier(Modifier::REVERSED),
+                ));
+            } else if state != before && !buf.is_empty() {
+                // Only start a new span once the style has actually changed.
+                line.push_span(Span::styled(std::mem::take(&mut buf), style_of(before)));
+            }
+        }
+
+        if !buf.is_empty() {
+            line.push_span(Span::styled(buf, style_of(state)));
+        }
+
+        line
+    }
+
+    /// Remove every control code from `raw`, leaving plain text.
+    pub fn strip(raw: &str) -> String {
+        Formatter::new(ColorDepth::Ansi16, true)
+            .parse(raw)
+            .spans
+            .into_iter()
+            .map(|span| span.content)
+            .collect()
+    }
+
+    /// Map an mIRC colour number onto a terminal colour.
+    fn palette(&self, code: u8) -> Option<Color> {
+        let color = match code {
+            0 => Color::White,
+            1 => Color::Black,
+            2 => Color::Blue,
+            3 => Color::Green,
+            4 => Color::LightRed,
+            5 => Color::Red,
+            6 => Color::Magenta,
+            7 => Color::Yellow,
+            8 => Color::LightYellow,
+            9 => Color::LightGreen,
+            10 => Color::Cyan,
+            11 => Color::LightCyan,
+            12 => Color::LightBlue,
+            13 => Color::LightMagenta,
+            14 => Color::DarkGray,
+            15 => Color::Gray,
+            16..=98 => {
+                let idx = code as usize - 16;
+                match self.depth {
+                    ColorDepth::Ansi16 => nearest_ansi(EXTENDED_RGB[idx]),
+                    ColorDepth::Ansi256 => Color::Indexed(EXTENDED_ANSI[idx]),
+                    ColorDepth::TrueColor => self.rgb(EXTENDED_RGB[idx])?,
+                }
+            }
+            // 99 means "the default colour", as does anything out of range.
+            _ => return None,
+        };
+        Some(color)
+    }
+
+    /// Map a 24-bit colour onto a terminal colour. Terminals without
+    /// truecolor support get the default colour instead.
+    fn rgb(&self, rgb: u32) -> Option<Color> {
+        match self.depth {
+            ColorDepth::TrueColor => Some(Color::from_u32(rgb)),
+            _ => None,
+        }
+    }
+}
+
+/// The one of the 16 named terminal colours closest to a
+/// 24-bit colour, for terminals with nothing in between.
+fn nearest_ansi(rgb: u32) -> Color {
+    const ANSI: [Color; 16] = [
+        Color::Black,
+        Color::Red,
+        Color::Green,
+        Color::Yellow,
+        Color::Blue,
+        Color::Magenta,
+        Color::Cyan,
+        Color::Gray,
+        Color::DarkGray,
+        Color::LightRed,
+        Color::LightGreen,
+        Color::LightYellow,
+        Color::LightBlue,
+        Color::LightMagenta,
+        Color::LightCyan,
+        Color::White,
+    ];
+
+    let [_, r, g, b] = rgb.to_be_bytes();
+    let distance = |color: &Color| {
+        let (cr, cg, cb) = nickcolor::rgb(*color);
+        [(r, cr), (g, cg), (b, cb)]
+            .into_iter()
+            .map(|(a, b)| (i32::from(a) - i32::from(b)).pow(2))
+            .sum::<i32>()
+    };
+    ANSI.into_iter()
+        .min_by_key(distance)
+        .expect("There are 16 colours to pick from")
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+struct FormatState {
+    bold: bool,
+    italic: bool,
+    underline: bool,
+    strikethrough: bool,
+    reverse: bool,
+    fg: Option<Color>,
+    bg: Option<Color>,
+}
+
+impl FormatState {
+    fn style(&self) -> Style {
+        let mut style = Style::default();
+        if let Some(fg) = self.fg {
+            style = style.fg(fg);
+        }
+        if let Some(bg) = self.bg {
+            style = style.bg(bg);
+        }
+
+        for (enabled, modifier) in [
+            (self.bold, Modifier::BOLD),
+            (self.italic, Modifier::ITALIC),
+            (self.underline, Modifier::UNDERLINED),
+            (self.strikethrough, Modifier::CROSSED_OUT),
+            (self.reverse, Modifier::REVERSED),
+        ] {
+            if enabled {
+                style = style.add_modifier(modifier);
+            }
+        }
+        style
+    }
+}
+
+/// Letter shown in place of a control code when previewing.
+fn code_marker(code: char) -> char {
+    match code {
+        BOLD => 'B',
+        COLOR => 'C',
+        HEX_COLOR => 'H',
+        RESET => 'O',
+        MONOSPACE => 'M',
+        REVERSE => 'R',
+        ITALIC => 'I',
+        STRIKETHROUGH => 'S',
+        UNDERLINE => 'U',
+        _ => '?',
+    }
+}
+
+type Chars<'a> = std::iter::Peekable<std::str::Chars<'a>>;
+
+/// Consume up to two decimal digits.
+fn take_digits(chars: &mut Chars) -> Option<u8> {
+    let mut value = None;
+    for _ in 0..2 {
+        match chars.peek().and_then(|c| c.to_digit(10)) {
+            Some(d) => {
+                value = Some(value.unwrap_or(0) * 10 + d as u8);
+                chars.next();
+            }
+            None => break,
+        }
+    }
+    value
+}
+
+/// Consume exactly six hex digits.
+fn take_hex(chars: &mut Chars) -> Option<u32> {
+    let lookahead: String = chars.clone().take(6).collect();
+    if lookahead.len() != 6 || !lookahead.chars().all(|c| c.is_ascii_hexdigit()) {
+        return None;
+    }
+    chars.nth(5);
+    u32::from_str_radix(&lookahead, 16).ok()
+}
+
+/// Consume a comma followed by whatever `take` accepts. The comma
+/// is left alone if it isn't followed by a valid value, since then
+/// it's part of the message rather than the colour code.
+fn take_separated<T>(chars: &mut Chars, take: fn(&mut Chars) -> Option<T>) -> Option<T> {
+    if chars.peek() != Some(&',') {
+        return None;
+    }
+    let mut lookahead = chars.clone();
+    lookahead.next();
+    let value = take(&mut lookahead)?;
+    *chars = lookahead;
+    Some(value)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ColorDepth, Formatter};
+    use ratatui::style::{Color, Modifier, Style};
+
+    /// The text and style of each span `raw` is parsed into.
+    fn spans(depth: ColorDepth, raw: &str) -> Vec<(String, Style)> {
+        Formatter::new(depth, false)
+            .parse(raw)
+            .spans
+            .into_iter()
+            .map(|span| (span.content.into_owned(), span.style))
+            .collect()
+    }
+
+    fn span(text: &str, style: Style) -> (String, Style) {
+        (text.to_owned(), style)
+    }
+
+    #[test]
+    fn colours_and_backgrounds() {
+        assert_eq!(
+            spans(ColorDepth::Ansi16, "\x034,12red\x03 plain"),
+            [
+                span("red", Style::default().fg(Color::LightRed).bg(Color::LightBlue)),
+                span(" plain", Style::default()),
+            ]
+        );
+    }
+
+    #[test]
+    fn commas_after_a_bare_colour_code_are_text() {
+        assert_eq!(
+            spans(ColorDepth::Ansi16, "\x034red\x03,12text"),
+            [
+                span("red", Style::default().fg(Color::LightRed)),
+                span(",12text", Style::default()),
+            ]
+        );
+        assert_eq!(
+            spans(ColorDepth::TrueColor, "\x04,ff0000text"),
+            [span(",ff0000text", Style::default())]
+        );
+    }
+
+    #[test]
+    fn reset_clears_everything() {
+        assert_eq!(
+            spans(ColorDepth::Ansi16, "\x02\x1d\x033bold\x0fplain"),
+            [
+                span(
+                    "bold",
+                    Style::default()
+                        .fg(Color::Green)
+                        .add_modifier(Modifier::BOLD | Modifier::ITALIC)
+                ),
+                span("plain", Style::default()),
+            ]
+        );
+    }
+
+    #[test]
+    fn reverse_toggles() {
+        assert_eq!(
+            spans(ColorDepth::Ansi16, "\x16on\x16off"),
+            [
+                span("on", Style::default().add_modifier(Modifier::REVERSED)),
+                span("off", Style::default()),
+            ]
+        );
+    }
+
+    #[test]
+    fn hex_colours_need_truecolor() {
+        assert_eq!(
+            spans(ColorDepth::TrueColor, "\x04FF8000,000080text"),
+            [span(
+                "text",
+                Style::default()
+                    .fg(Color::Rgb(0xff, 0x80, 0x00))
+                    .bg(Color::Rgb(0x00, 0x00, 0x80))
+            )]
+        );
+        assert_eq!(
+            spans(ColorDepth::Ansi256, "\x04FF8000text"),
+            [span("text", Style::default())]
+        );
+        // Too few digits to be a colour.
+        assert_eq!(
+            spans(ColorDepth::TrueColor, "\x04FF80text"),
+            [span("FF80text", Style::default())]
+        );
+    }
+
+    #[test]
+    fn extended_and_default_colours() {
+        assert_eq!(
+            spans(ColorDepth::Ansi256, "\x0352red"),
+            [span("red", Style::default().fg(Color::Indexed(196)))]
+        );
+        // Approximated by the closest of the 16 named colours.
+        assert_eq!(
+            spans(ColorDepth::Ansi16, "\x0352red"),
+            [span("red", Style::default().fg(Color::LightRed))]
+        );
+        assert_eq!(
+            spans(ColorDepth::Ansi16, "\x0328,88text"),
+            [span(
+                "text",
+                Style::default().fg(Color::Red).bg(Color::Black)
+            )]
+        );
+        assert_eq!(
+            spans(ColorDepth::Ansi16, "\x0397text"),
+            [span("text", Style::default().fg(Color::Gray))]
+        );
+        assert_eq!(
+            spans(ColorDepth::Ansi16, "\x0399,99text"),
+            [span("text", Style::default())]
+        );
+        // Only two digits are taken.
+        assert_eq!(
+            spans(ColorDepth::Ansi16, "\x031234"),
+            [span("34", Style::default().fg(Color::LightBlue))]
+        );
+    }
+}
@@ -1,12 +1,16 @@
 use ratatui::{
-    buffer::Buffer, layout::{Constraint, Layout, Rect}, widgets::Widget, Frame
+    buffer::Buffer, layout::{Constraint, Layout, Rect}, style::Style, widgets::Widget, Frame
 };
 
 mod config;
+pub mod mirc;
+pub mod nickcolor;
+pub mod theme;
 mod tuiwrapper;
 pub mod widget;
 
 pub use config::UIConfig;
+pub use theme::Theme;
 pub use tuiwrapper::Tui;
 pub use widget::{RenderContext, ContextualWidget};
 
@@ -30,6 +34,10 @@ impl<'a> StatelessView<'a> {
 
 impl<'a> Widget for &StatelessView<'a> {
     fn render(self, area: Rect, buf: &mut Buffer) {
+        if let Some(background) = self.ctx.theme.background {
+            buf.set_style(area, Style::default().bg(background));
+        }
+
         let layout = Layout::horizontal(vec![
             Constraint::Fill(1),
             Constraint::Percentage(75),
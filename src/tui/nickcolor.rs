@@ -0,0 +1,347 @@
+use ratatui::{
+    style::{Color, Style},
+    text::{Line, Span},
+};
+
+use super::{Theme, UIConfig};
+
+/// Minimum WCAG contrast ratio a nick colour must have
+/// against the background to be kept in the palette.
+const MIN_CONTRAST: f64 = 3.0;
+
+/// Assigns each nick a stable colour from a palette so that
+/// the same person is always drawn in the same colour, no
+/// matter where their nick appears.
+#[derive(Clone, Debug)]
+pub struct NickColors {
+    palette: Vec<Color>,
+}
+
+impl Default for NickColors {
+    fn default() -> Self {
+        NickColors::from_config(&UIConfig::default(), &Theme::default())
+    }
+}
+
+impl NickColors {
+    pub fn from_config(cfg: &UIConfig, theme: &Theme) -> Self {
+        let background = theme.background.unwrap_or(Color::Black);
+        let mut palette = cfg.nick_palette.clone();
+
+        if cfg.avoid_low_contrast {
+            let readable: Vec<Color> = palette
+                .iter()
+                .copied()
+                .filter(|c| contrast(*c, background) >= MIN_CONTRAST)
+                .collect();
+
+            // A palette that filters down to nothing is the user's
+            // problem to fix, but it shouldn't make nicks vanish.
+            if !readable.is_empty() {
+                palette = readable;
+            }
+        }
+
+        NickColors { palette }
+    }
+
+    /// The colour for `nick`. Channel mode prefixes are ignored and
+    /// nicks are compared case-insensitively, as IRC servers do.
+    pub fn color_for(&self, nick: &str) -> Color {
+        if self.palette.is_empty() {
+            return Color::Reset;
+        }
+
+        // FNV-1a, which unlike std's hasher is stable across runs.
+        let hash = normalize(nick).bytes().fold(0x811c9dc5u32, |hash, b| {
+            (hash ^ u32::from(b)).wrapping_mul(0x01000193)
+        });
+        self.palette[hash as usize % self.palette.len()]
+    }
+
+    pub fn style_for(&self, nick: &str) -> Style {
+        Style::default().fg(self.color_for(nick))
+    }
+
+    /// Colour a tag unless its producer already styled it.
+    pub fn color_tag(&self, tag: &Line<'static>) -> Line<'static> {
+        if tag.style.fg.is_some() || tag.spans.iter().any(|s| s.style.fg.is_some()) {
+            return tag.clone();
+        }
+
+        let nick: String = tag.spans.iter().map(|s| s.content.as_ref()).collect();
+        Line::from(Span::styled(nick.clone(), self.style_for(&nick)))
+    }
+
+    /// Colour every run of nick characters in `line` which
+    /// `is_nick` accepts once normalized. Spans that already
+    /// have a foreground colour are left alone.
+    pub fn color_mentions(
+        &self,
+        line: &Line<'static>,
+        is_nick: impl Fn(&str) -> bool,
+    ) -> Line<'static> {
+        let mut out = Line::default().style(line.style);
+        for span in &line.spans {
+            if span.style.fg.is_some() {
+                out.push_span(span.clone());
+                continue;
+            }
+
+            let mut plain = String::new();
+            for word in nick_runs(&span.content) {
+                if is_nick(&normalize(word)) {
+                    if !plain.is_empty() {
+                        out.push_span(Span::styled(std::mem::take(&mut plain), span.style));
+                    }
+                    out.push_span(Span::styled(
+                        word.to_owned(),
+                        span.style.patch(self.style_for(word)),
+                    ));
+                } else {
+                    plain.push_str(word);
+                }
+            }
+            if !plain.is_empty() {
+                out.push_span(Span::styled(plain, span.style));
+            }
+        }
+        out
+    }
+}
+
+/// Split `text` into alternating runs of characters that can
+/// and can't be part of a nick, so `foo|bar:` is `foo|bar`
+/// then `:`.
+fn nick_runs(text: &str) -> impl Iterator<Item = &str> {
+    let mut rest = text;
+    std::iter::from_fn(move || {
+        let first = rest.chars().next()?;
+        let end = rest
+            .find(|c| is_nick_char(c) != is_nick_char(first))
+            .unwrap_or(rest.len());
+        let (run, tail) = rest.split_at(end);
+        rest = tail;
+        Some(run)
+    })
+}
+
+fn is_nick_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || "-[]\\`_^{|}".contains(c)
+}
+
+/// Lowercase a nick the way RFC 1459 servers do and strip
+/// any channel mode prefix such as `@` or `+`.
+pub fn normalize(nick: &str) -> String {
+    fold_case(nick.trim_start_matches(['~', '&', '@', '%', '+']))
+}
+
+/// Whether `text` could be a nick: a letter or one of RFC
+/// 2812's special characters, then any of those, digits or `-`.
+pub fn is_nick(text: &str) -> bool {
+    let mut chars = text.chars();
+    chars
+        .next()
+        .is_some_and(|c| is_nick_char(c) && !c.is_ascii_digit() && c != '-')
+        && chars.all(is_nick_char)
+}
+
+/// Lowercase text the way RFC 1459 servers do, so
+/// `Nick[away]` and `nick{away}` compare equal.
+pub fn fold_case(text: &str) -> String {
+    text.chars()
+        .map(|c| match c {
+            '[' => '{',
+            ']' => '}',
+            '\\' => '|',
+            '~' => '^',
+            c => c.to_ascii_lowercase(),
+        })
+        .collect()
+}
+
+/// WCAG contrast ratio between two colours.
+fn contrast(a: Color, b: Color) -> f64 {
+    let (la, lb) = (luminance(a), luminance(b));
+    (la.max(lb) + 0.05) / (la.min(lb) + 0.05)
+}
+
+fn luminance(color: Color) -> f64 {
+    let (r, g, b) = rgb(color);
+    let channel = |c: u8| {
+        let c = f64::from(c) / 255.0;
+        if c <= 0.03928 {
+            c / 12.92
+        } else {
+            ((c + 0.055) / 1.055).powf(2.4)
+        }
+    };
+    0.2126 * channel(r) + 0.7152 * channel(g) + 0.0722 * channel(b)
+}
+
+/// Approximate RGB value of a terminal colour, using the
+/// xterm defaults for the named and indexed colours.
+pub(crate) fn rgb(color: Color) -> (u8, u8, u8) {
+    const ANSI: [(u8, u8, u8); 16] = [
+        (0, 0, 0),
+        (205, 0, 0),
+        (0, 205, 0),
+        (205, 205, 0),
+        (0, 0, 238),
+        (205, 0, 205),
+        (0, 205, 205),
+        (229, 229, 229),
+        (127, 127, 127),
+        (255, 0, 0),
+        (0, 255, 0),
+        (255, 255, 0),
+        (92, 92, 255),
+        (255, 0, 255),
+        (0, 255, 255),
+        (255, 255, 255),
+    ];
+
+    let index = match color {
+        Color::Rgb(r, g, b) => return (r, g, b),
+        Color::Reset | Color::Black => 0,
+        Color::Red => 1,
+        Color::Green => 2,
+        Color::Yellow => 3,
+        Color::Blue => 4,
+        Color::Magenta => 5,
+        Color::Cyan => 6,
+        Color::Gray => 7,
+        Color::DarkGray => 8,
+        Color::LightRed => 9,
+        Color::LightGreen => 10,
+        Color::LightYellow => 11,
+        Color::LightBlue => 12,
+        Color::LightMagenta => 13,
+        Color::LightCyan => 14,
+        Color::White => 15,
+        Color::Indexed(i) => i,
+    };
+
+    match index {
+        0..=15 => ANSI[index as usize],
+        // The 6x6x6 colour cube.
+        16..=231 => {
+            let i = index - 16;
+            let level = |v: u8| if v == 0 { 0 } else { 55 + v * 40 };
+            (level(i / 36), level((i / 6) % 6), level(i % 6))
+        }
+        // The greyscale ramp.
+        _ => {
+            let v = 8 + (index - 232) * 10;
+            (v, v, v)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{contrast, is_nick, NickColors};
+    use crate::tui::{Theme, UIConfig};
+    use ratatui::{
+        style::{Color, Style},
+        text::{Line, Span},
+    };
+
+    fn colors(palette: Vec<Color>, background: Option<Color>) -> NickColors {
+        let cfg = UIConfig {
+            nick_palette: palette,
+            ..UIConfig::default()
+        };
+        let theme = Theme {
+            background,
+            ..Theme::default()
+        };
+        NickColors::from_config(&cfg, &theme)
+    }
+
+    #[test]
+    fn nicks_keep_their_colour_however_written() {
+        let colors = NickColors::default();
+        let color = colors.color_for("Nick[away]");
+        assert_eq!(colors.color_for("nick{away}"), color);
+        assert_eq!(colors.color_for("@NICK[AWAY]"), color);
+        assert!(colors.palette.contains(&color));
+
+        // FNV-1a of "a" lands on the same entry every run.
+        let hash = (0x811c9dc5u32 ^ u32::from(b'a')).wrapping_mul(0x01000193);
+        assert_eq!(
+            colors.color_for("a"),
+            colors.palette[hash as usize % colors.palette.len()]
+        );
+    }
+
+    #[test]
+    fn drops_colours_too_close_to_the_background() {
+        let palette = vec![Color::Black, Color::Blue, Color::LightYellow, Color::White];
+        assert_eq!(
+            colors(palette.clone(), None).palette,
+            [Color::LightYellow, Color::White]
+        );
+        assert_eq!(
+            colors(palette, Some(Color::White)).palette,
+            [Color::Black, Color::Blue]
+        );
+        assert!(contrast(Color::Black, Color::White) > 20.0);
+    }
+
+    #[test]
+    fn keeps_the_palette_if_nothing_is_readable() {
+        let palette = vec![Color::Black, Color::Indexed(16)];
+        assert_eq!(colors(palette.clone(), None).palette, palette);
+        assert_eq!(colors(Vec::new(), None).color_for("nick"), Color::Reset);
+    }
+
+    #[test]
+    fn colours_only_known_nicks() {
+        let colors = NickColors::default();
+        let line = Line::from(vec![
+            Span::raw("hi Alice, bob"),
+            Span::styled(" alice", Style::default().fg(Color::Red)),
+        ]);
+        let coloured = colors.color_mentions(&line, |nick| nick == "alice");
+        assert_eq!(
+            coloured.spans,
+            [
+                Span::raw("hi "),
+                Span::styled("Alice", colors.style_for("alice")),
+                Span::raw(", bob"),
+                Span::styled(" alice", Style::default().fg(Color::Red)),
+            ]
+        );
+    }
+
+    #[test]
+    fn colours_nicks_with_special_characters_whole() {
+        let colors = NickColors::default();
+        let line = Line::from("^nick: ask foo|bar or nick[away]?");
+        // Nicks reach `is_nick` case folded, brackets included.
+        let known = ["^nick", "foo|bar", "nick{away}"];
+        let coloured = colors.color_mentions(&line, |nick| known.contains(&nick));
+        assert_eq!(
+            coloured.spans,
+            [
+                Span::styled("^nick", colors.style_for("^nick")),
+                Span::raw(": ask "),
+                Span::styled("foo|bar", colors.style_for("foo|bar")),
+                Span::raw(" or "),
+                Span::styled("nick[away]", colors.style_for("nick[away]")),
+                Span::raw("?"),
+            ]
+        );
+    }
+
+    #[test]
+    fn only_nicks_are_nicks() {
+        assert!(is_nick("alice"));
+        assert!(is_nick("[m]-2`"));
+        assert!(!is_nick("-->"));
+        assert!(!is_nick("*"));
+        assert!(!is_nick("2fast"));
+        assert!(!is_nick(""));
+    }
+}
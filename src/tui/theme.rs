@@ -0,0 +1,285 @@
+use color_eyre::eyre::{bail, Result};
+use ratatui::style::{Color, Modifier, Style};
+use serde::Deserialize;
+use std::path::Path;
+
+/// A style as written in a theme. Unlike ratatui's
+/// own `Style`, every field is optional.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct ThemeStyle {
+    pub fg: Option<Color>,
+    pub bg: Option<Color>,
+    pub bold: bool,
+    pub dim: bool,
+    pub italic: bool,
+    pub underline: bool,
+    pub reversed: bool,
+}
+
+impl ThemeStyle {
+    pub const fn fg(color: Color) -> Self {
+        ThemeStyle {
+            fg: Some(color),
+            bg: None,
+            bold: false,
+            dim: false,
+            italic: false,
+            underline: false,
+            reversed: false,
+        }
+    }
+
+    pub const fn bold(mut self) -> Self {
+        self.bold = true;
+        self
+    }
+
+    pub const fn dim(mut self) -> Self {
+        self.dim = true;
+        self
+    }
+
+    pub const fn reversed(mut self) -> Self {
+        self.reversed = true;
+        self
+    }
+}
+
+impl From<ThemeStyle> for Style {
+    fn from(t: ThemeStyle) -> Style {
+        let mut style = Style::default();
+        if let Some(fg) = t.fg {
+            style = style.fg(fg);
+        }
+        if let Some(bg) = t.bg {
+            style = style.bg(bg);
+        }
+
+        for (enabled, modifier) in [
+            (t.bold, Modifier::BOLD),
+            (t.dim, Modifier::DIM),
+            (t.italic, Modifier::ITALIC),
+            (t.underline, Modifier::UNDERLINED),
+            (t.reversed, Modifier::REVERSED),
+        ] {
+            if enabled {
+                style = style.add_modifier(modifier);
+            }
+        }
+        style
+    }
+}
+
+/// Named styles used by every widget. Themes are read from the
+/// `[theme]` section of the config file or from standalone theme
+/// files, and any style left out falls back to the default theme.
+#[derive(Clone, Debug, PartialEq, Eq, Deserialize)]
+#[serde(default)]
+pub struct Theme {
+    /// Background the screen is painted with, which nick
+    /// colours are kept readable against. When unset, the
+    /// terminal's own is used and assumed to be black.
+    pub background: Option<Color>,
+
+    pub timestamp: ThemeStyle,
+    pub tag: ThemeStyle,
+    pub own_message: ThemeStyle,
+    pub highlight: ThemeStyle,
+    pub join_part: ThemeStyle,
+    pub error: ThemeStyle,
+    pub border: ThemeStyle,
+    pub title: ThemeStyle,
+    pub input: ThemeStyle,
+    pub status: ThemeStyle,
+    pub topic: ThemeStyle,
+
+    /// Border of the pane showing the buffer in focus.
+    pub pane_selected: ThemeStyle,
+
+    /// Text matching a search.
+    pub search_match: ThemeStyle,
+
+    /// Lines replayed from the chat log, and
+    /// the marker after them.
+    pub backlog: ThemeStyle,
+
+    /// Tags of the debug log, one per tracing level.
+    /// ERROR events use `error`.
+    pub trace: ThemeStyle,
+    pub debug: ThemeStyle,
+    pub info: ThemeStyle,
+    pub warn: ThemeStyle,
+
+    /// Spans an event happened in, and the names and
+    /// values of its fields, in the debug log.
+    pub span: ThemeStyle,
+    pub field_name: ThemeStyle,
+    pub field_value: ThemeStyle,
+}
+
+impl Default for Theme {
+    fn default() -> Self {
+        Theme {
+            background: None,
+            timestamp: ThemeStyle::default(),
+            tag: ThemeStyle::default(),
+            own_message: ThemeStyle::default().bold(),
+            highlight: ThemeStyle::fg(Color::LightYellow).bold(),
+            join_part: ThemeStyle::fg(Color::DarkGray),
+            error: ThemeStyle::fg(Color::LightRed),
+            border: ThemeStyle::default(),
+            title: ThemeStyle::default(),
+            input: ThemeStyle::default(),
+            status: ThemeStyle::default().reversed(),
+            topic: ThemeStyle::default(),
+            pane_selected: ThemeStyle::default().bold(),
+            search_match: ThemeStyle::default().reversed(),
+            backlog: ThemeStyle::default().dim(),
+            trace: ThemeStyle::fg(Color::Cyan),
+            debug: ThemeStyle::fg(Color::LightMagenta),
+            info: ThemeStyle::fg(Color::LightGreen),
+            warn: ThemeStyle::fg(Color::LightYellow),
+            span: ThemeStyle::fg(Color::DarkGray),
+            field_name: ThemeStyle::default().dim(),
+            field_value: ThemeStyle::default(),
+        }
+    }
+}
+
+impl Theme {
+    /// Names of the themes compiled into eesh.
+    pub const BUILTIN: [&'static str; 3] = ["default", "light", "mono"];
+
+    pub fn builtin(name: &str) -> Option<Theme> {
+        let theme = match name {
+            "default" => Theme::default(),
+            "light" => Theme {
+                background: Some(Color::White),
+                timestamp: ThemeStyle::fg(Color::DarkGray),
+                own_message: ThemeStyle::fg(Color::Black).bold(),
+                highlight: ThemeStyle::fg(Color::Red).bold(),
+                join_part: ThemeStyle::fg(Color::Gray),
+                error: ThemeStyle::fg(Color::Red),
+                border: ThemeStyle::fg(Color::DarkGray),
+                pane_selected: ThemeStyle::fg(Color::Black).bold(),
+                title: ThemeStyle::fg(Color::Black).bold(),
+                input: ThemeStyle::fg(Color::Black),
+                trace: ThemeStyle::fg(Color::Blue),
+                debug: ThemeStyle::fg(Color::Magenta),
+                info: ThemeStyle::fg(Color::Green),
+                warn: ThemeStyle::fg(Color::Yellow),
+                span: ThemeStyle::fg(Color::Gray),
+                ..Theme::default()
+            },
+            "mono" => Theme {
+                highlight: ThemeStyle::default().bold(),
+                join_part: ThemeStyle::default(),
+                error: ThemeStyle::default().bold(),
+                trace: ThemeStyle::default(),
+                debug: ThemeStyle::default(),
+                info: ThemeStyle::default(),
+                warn: ThemeStyle::default().bold(),
+                span: ThemeStyle::default().dim(),
+                ..Theme::default()
+            },
+            _ => return None,
+        };
+        Some(theme)
+    }
+
+    /// Read a standalone theme file. Theme files use the same
+    /// keys as the `[theme]` section of the config file.
+    pub fn parse(path: impl AsRef<Path>) -> Result<Theme> {
+        Ok(toml::from_str(&std::fs::read_to_string(path.as_ref())?)?)
+    }
+
+    /// Find a theme by name: a built-in theme, or else
+    /// `<name>.toml` in the given directory.
+    pub fn load(name: &str, theme_dir: impl AsRef<Path>) -> Result<Theme> {
+        if let Some(theme) = Theme::builtin(name) {
+            return Ok(theme);
+        }
+
+        let path = theme_dir.as_ref().join(format!("{name}.toml"));
+        if !path.exists() {
+            bail!(
+                "No theme named \"{name}\". Built-in themes are {}, and others are read from {}",
+                Theme::BUILTIN.join(", "),
+                theme_dir.as_ref().display()
+            );
+        }
+        Theme::parse(path)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{Theme, ThemeStyle};
+    use ratatui::style::{Color, Modifier, Style};
+
+    fn theme_dir(name: &str) -> std::path::PathBuf {
+        let dir = std::env::temp_dir().join(format!("eesh-themes-{name}-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        dir
+    }
+
+    #[test]
+    fn every_builtin_theme_loads() {
+        for name in Theme::BUILTIN {
+            assert_eq!(Theme::load(name, "/nonexistent").ok(), Theme::builtin(name));
+        }
+        assert_eq!(Theme::builtin("default"), Some(Theme::default()));
+        assert_eq!(Theme::builtin("nope"), None);
+    }
+
+    #[test]
+    fn builtin_themes_differ_where_it_matters() {
+        let light = Theme::builtin("light").unwrap();
+        assert_eq!(light.background, Some(Color::White));
+        assert_eq!(light.input, ThemeStyle::fg(Color::Black));
+
+        let mono = Theme::builtin("mono").unwrap();
+        assert!([mono.highlight, mono.error, mono.join_part, mono.warn]
+            .iter()
+            .all(|style| style.fg.is_none() && style.bg.is_none()));
+        // Left out styles fall back to the default theme.
+        assert_eq!(mono.status, Theme::default().status);
+    }
+
+    #[test]
+    fn theme_files_override_the_default_theme() {
+        let dir = theme_dir("files");
+        std::fs::write(
+            dir.join("dusk.toml"),
+            "background = \"#202020\"\n\
+             [highlight]\nfg = \"magenta\"\nunderline = true\n",
+        )
+        .unwrap();
+
+        let dusk = Theme::load("dusk", &dir).unwrap();
+        assert_eq!(dusk.background, Some(Color::Rgb(0x20, 0x20, 0x20)));
+        assert_eq!(
+            Style::from(dusk.highlight),
+            Style::new()
+                .fg(Color::Magenta)
+                .add_modifier(Modifier::UNDERLINED)
+        );
+        assert_eq!(dusk.pane_selected, Theme::default().pane_selected);
+
+        // Built-in names win over theme files.
+        std::fs::write(dir.join("mono.toml"), "[error]\nfg = \"red\"\n").unwrap();
+        assert_eq!(
+            Theme::load("mono", &dir).unwrap(),
+            Theme::builtin("mono").unwrap()
+        );
+
+        let missing = Theme::load("nope", &dir).unwrap_err().to_string();
+        assert!(missing.contains("default, light, mono"));
+
+        std::fs::write(dir.join("broken.toml"), "highlight = 3\n").unwrap();
+        assert!(Theme::load("broken", &dir).is_err());
+
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -6,12 +6,13 @@ use lazy_static::lazy_static;
 use ratatui::{
     backend::CrosstermBackend,
     crossterm::{
+        event::{DisableFocusChange, EnableFocusChange},
         execute,
         terminal::{disable_raw_mode, enable_raw_mode, EnterAlternateScreen, LeaveAlternateScreen},
     },
 };
 use std::{
-    io::{stdout, Stdout},
+    io::{stdout, Stdout, Write},
     panic,
     sync::atomic::AtomicBool,
     sync::atomic::Ordering,
@@ -36,7 +37,9 @@ impl Tui {
         }
 
         Self::install_hooks()?;
-        execute!(stdout(), EnterAlternateScreen)?;
+        // Focus changes are reported so that notifications
+        // can be sent while the terminal is in the background.
+        execute!(stdout(), EnterAlternateScreen, EnableFocusChange)?;
         enable_raw_mode()?;
         let term = ratatui::Terminal::new(CrosstermBackend::new(stdout()))?;
 
@@ -61,19 +64,39 @@ impl Tui {
     }
 
     /// Performs the same tasks as `Tui::release()` without
-    /// modifying the resource lock state. This is marked
-    /// unsafe because in order to maintain proper state the
-    /// caller is also responsible for calling `Tui::set_acquired(false)`.
+    /// modifying the resource lock state.
+    ///
+    /// # Safety
+    ///
+    /// The caller must also call `Tui::set_acquired(false)`
+    /// once the terminal is restored, or no `Tui` can be
+    /// acquired again.
     pub unsafe fn restore() -> Result<()> {
-        execute!(stdout(), LeaveAlternateScreen)?;
+        execute!(stdout(), DisableFocusChange, LeaveAlternateScreen)?;
         disable_raw_mode()?;
         Ok(())
     }
 
+    /// Write escape sequences, such as a bell, straight
+    /// to the terminal without disturbing the frame.
+    pub fn write_raw(&mut self, escapes: &str) -> std::io::Result<()> {
+        let backend = self.term.backend_mut();
+        backend.write_all(escapes.as_bytes())?;
+        backend.flush()
+    }
+
     pub fn is_acquired() -> bool {
         TERMINAL_ACQUIRED.load(Ordering::SeqCst)
     }
 
+    /// Mark the terminal as acquired or released.
+    ///
+    /// # Safety
+    ///
+    /// The state must match the terminal's: setting it while
+    /// a `Tui` still holds the terminal lets a second one
+    /// acquire it, and the two fight over raw mode and the
+    /// alternate screen.
     pub unsafe fn set_acquired(state: bool) {
         TERMINAL_ACQUIRED.store(state, Ordering::SeqCst);
     }
@@ -0,0 +1,200 @@
+use color_eyre::eyre::{bail, Result};
+use ratatui::text::Line;
+use regex::Regex;
+use std::fmt;
+use tracing::Level;
+
+/// Narrows which lines of a `LogBuffer` are shown. Hidden
+/// lines are kept, and come back once the filter is cleared.
+#[derive(Clone, Debug, Default)]
+pub struct LineFilter {
+    /// Most verbose level shown, so `WARN` shows warnings and
+    /// errors. Lines without a level, such as chat, always pass.
+    pub level: Option<Level>,
+
+    /// Text the tag column must contain, such
+    /// as part of a tracing target.
+    pub target: Option<String>,
+
+    /// Text the message must contain, ignoring case.
+    pub text: Option<String>,
+
+    /// Pattern the message must match.
+    pub regex: Option<Regex>,
+}
+
+impl LineFilter {
+    /// Parse a filter such as `level=warn target=irc timed out`
+    /// or `/time[sd] out/`. Words other than `level=` and `target=`
+    /// are searched for together as text.
+    pub fn parse(spec: &str) -> Result<Self> {
+        let mut filter = LineFilter::default();
+
+        // A regex runs from the first word starting with a slash
+        // to the next slash not escaped with a backslash, spaces
+        // and all.
+        let start = if spec.starts_with('/') {
+            Some(0)
+        } else {
+            spec.find(" /").map(|i| i + 1)
+        };
+        let rest = match start {
+            Some(start) => {
+                let Some(end) = closing_slash(spec, start + 1) else {
+                    bail!(
+                        "The regex after {:?} is missing its closing slash",
+                        &spec[start..]
+                    );
+                };
+                filter.regex = Some(Regex::new(&spec[start + 1..end])?);
+                format!("{} {}", &spec[..start], &spec[end + 1..])
+            }
+            None => spec.to_owned(),
+        };
+
+        let mut text = Vec::new();
+        for word in rest.split_whitespace() {
+            if let Some(level) = word.strip_prefix("level=") {
+                let Ok(level) = level.parse() else {
+                    bail!("{level:?} is not a level, such as warn or debug");
+                };
+                filter.level = Some(level);
+            } else if let Some(target) = word.strip_prefix("target=") {
+                filter.target = Some(target.to_owned());
+            } else {
+                text.push(word.to_lowercase());
+            }
+        }
+        if !text.is_empty() {
+            filter.text = Some(text.join(" "));
+        }
+
+        Ok(filter)
+    }
+
+    pub fn matches(&self, level: Option<Level>, tag: &Line, content: &Line) -> bool {
+        if let (Some(max), Some(level)) = (self.level, level) {
+            if level > max {
+                return false;
+            }
+        }
+        if let Some(target) = &self.target {
+            if !plain(tag).contains(target.as_str()) {
+                return false;
+            }
+        }
+
+        if self.text.is_none() && self.regex.is_none() {
+            return true;
+        }
+        let content = plain(content);
+        self.text
+            .as_ref()
+            .is_none_or(|text| content.to_lowercase().contains(text.as_str()))
+            && self.regex.as_ref().is_none_or(|re| re.is_match(&content))
+    }
+}
+
+impl fmt::Display for LineFilter {
+    /// The filter as it would be typed.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut words = Vec::new();
+        if let Some(level) = self.level {
+            words.push(format!("level={}", level.as_str().to_lowercase()));
+        }
+        if let Some(target) = &self.target {
+            words.push(format!("target={target}"));
+        }
+        if let Some(text) = &self.text {
+            words.push(text.clone());
+        }
+        if let Some(regex) = &self.regex {
+            words.push(format!("/{regex}/"));
+        }
+        write!(f, "{}", words.join(" "))
+    }
+}
+
+/// Byte offset of the first slash in `spec` from `from`
+/// on which isn't escaped with a backslash.
+fn closing_slash(spec: &str, from: usize) -> Option<usize> {
+    let mut chars = spec[from..].char_indices();
+    while let Some((i, c)) = chars.next() {
+        match c {
+            '\\' => {
+                chars.next();
+            }
+            '/' => return Some(from + i),
+            _ => (),
+        }
+    }
+    None
+}
+
+/// The text of a line without its styling.
+pub fn plain(line: &Line) -> String {
+    line.spans.iter().map(|s| s.content.as_ref()).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::LineFilter;
+    use ratatui::text::Line;
+    use tracing::Level;
+
+    fn regex(filter: &LineFilter) -> Option<&str> {
+        filter.regex.as_ref().map(|re| re.as_str())
+    }
+
+    #[test]
+    fn parses_levels_targets_and_text() {
+        let filter = LineFilter::parse("level=warn target=irc Timed  Out").unwrap();
+        assert_eq!(filter.level, Some(Level::WARN));
+        assert_eq!(filter.target.as_deref(), Some("irc"));
+        assert_eq!(filter.text.as_deref(), Some("timed out"));
+        assert!(filter.regex.is_none());
+    }
+
+    #[test]
+    fn regexes_keep_their_spaces() {
+        let filter = LineFilter::parse("/time[sd] out/").unwrap();
+        assert_eq!(regex(&filter), Some("time[sd] out"));
+        assert!(filter.text.is_none());
+    }
+
+    #[test]
+    fn regexes_end_at_the_first_closing_slash() {
+        let filter = LineFilter::parse("/err/ target=a/b").unwrap();
+        assert_eq!(regex(&filter), Some("err"));
+        assert_eq!(filter.target.as_deref(), Some("a/b"));
+
+        let filter = LineFilter::parse("level=info /join|part/ eesh").unwrap();
+        assert_eq!(regex(&filter), Some("join|part"));
+        assert_eq!(filter.text.as_deref(), Some("eesh"));
+    }
+
+    #[test]
+    fn escaped_slashes_stay_in_the_regex() {
+        let filter = LineFilter::parse(r"/a\/b/ x").unwrap();
+        assert_eq!(regex(&filter), Some(r"a\/b"));
+        assert_eq!(filter.text.as_deref(), Some("x"));
+        assert!(filter.matches(None, &Line::default(), &Line::from("a/b x")));
+    }
+
+    #[test]
+    fn rejects_bad_specs() {
+        assert!(LineFilter::parse("/unclosed").is_err());
+        assert!(LineFilter::parse(r"/escaped\/").is_err());
+        assert!(LineFilter::parse("level=loud").is_err());
+        assert!(LineFilter::parse("/(/").is_err());
+    }
+
+    #[test]
+    fn levels_only_narrow_lines_which_have_one() {
+        let filter = LineFilter::parse("level=warn").unwrap();
+        let line = Line::from("text");
+        assert!(filter.matches(Some(Level::ERROR), &line, &line));
+        assert!(!filter.matches(Some(Level::INFO), &line, &line));
+        assert!(filter.matches(None, &line, &line));
+    }
+}
@@ -0,0 +1,58 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph, Widget},
+};
+
+use super::{ContextualWidget, LogBuffer, RenderContext};
+
+/// The filter narrowing the focused buffer, and whether
+/// it follows new lines, drawn above its log.
+pub struct FilterBar;
+
+impl FilterBar {
+    /// Rows the filter bar needs, including its top border. Buffers
+    /// showing every line as it arrives get no filter bar at all.
+    pub fn height(lb: &LogBuffer) -> u16 {
+        if lb.filter().is_some() || !lb.following() {
+            2
+        } else {
+            0
+        }
+    }
+}
+
+impl ContextualWidget for FilterBar {
+    fn render_ref(&self, ctx: &RenderContext, area: Rect, buf: &mut Buffer) {
+        let Some(tb) = ctx.text_buffer.as_ref().filter(|_| !area.is_empty()) else {
+            return;
+        };
+        let lb = tb.lock().expect("Screenbuffer mutex was poisoned!");
+
+        let mut line = Line::default();
+        if let Some(filter) = lb.filter() {
+            line.push_span(Span::styled("Filter: ", ctx.theme.title));
+            line.push_span(Span::raw(filter.to_string()));
+            line.push_span(Span::styled(
+                format!("  {} of {} lines", lb.shown_count(), lb.count()),
+                ctx.theme.timestamp,
+            ));
+        }
+        if !lb.following() {
+            if !line.spans.is_empty() {
+                line.push_span(Span::raw("  "));
+            }
+            line.push_span(Span::styled("Paused", ctx.theme.title));
+        }
+
+        Paragraph::new(line)
+            .style(ctx.theme.topic)
+            .block(
+                Block::new()
+                    .borders(Borders::ALL ^ Borders::BOTTOM)
+                    .border_style(ctx.theme.border),
+            )
+            .render(area, buf);
+    }
+}
@@ -1,151 +1,899 @@
-use chrono::{DateTime, TimeZone, Utc};
+use chrono::{
+    format::{Item, StrftimeItems},
+    DateTime, NaiveDate, TimeDelta, TimeZone, Utc,
+};
+use color_eyre::eyre::{bail, Result};
 use chrono_tz::Tz;
+use hashbrown::HashSet;
 use ratatui::{
     buffer::Buffer,
-    layout::{Alignment, Constraint, Rect},
-    text::{Line, Span, Text},
-    widgets::{Block, Borders, Row, StatefulWidgetRef, Table, TableState},
+    layout::{Constraint, Rect},
+    text::{Line, Text},
+    widgets::{
+        Block, Borders, Clear, Paragraph, Row, Table, Widget,
+    },
+};
+use std::{
+    cell::{Cell, Ref, RefCell},
+    collections::VecDeque,
+    io::{self, Write},
+    sync::atomic::{AtomicU16, AtomicUsize, Ordering},
 };
-use std::{collections::VecDeque, sync::atomic::AtomicU16};
-use unicode_segmentation::UnicodeSegmentation;
+use regex::Regex;
+use tracing::Level;
 use unicode_width::UnicodeWidthStr;
 
-use crate::RenderContext;
+use super::{
+    filter::{plain, LineFilter},
+    search,
+    spill::Spill,
+    wrap, ContextualWidget, RenderContext,
+};
+use crate::{
+    client::{ChannelState, Target},
+    tui::{nickcolor, UIConfig},
+};
+
+/// Text of the separator row inserted between
+/// lines that fall on different days.
+const DAY_CHANGED: &str = "Day changed to %A, %-d %B %Y";
+
+/// How timestamps are drawn in the left-most column.
+#[derive(Clone, Debug)]
+pub struct Timestamps {
+    format: String,
+    width: u16,
+}
+
+impl Timestamps {
+    /// Timestamps drawn with `format` in `tz`. Fails if
+    /// `format` has a specifier chrono doesn't know.
+    pub fn new(format: impl Into<String>, tz: Tz) -> Result<Self> {
+        let format = format.into();
+        if StrftimeItems::new(&format).any(|item| item == Item::Error) {
+            bail!("Invalid timestamp_format {format:?}");
+        }
+
+        // A Wednesday in September, so that formats naming the
+        // day or month are measured at their widest. Most other
+        // fields are fixed-width, bar `%Z`, which depends on `tz`.
+        let naive = NaiveDate::from_ymd_opt(2021, 9, 29)
+            .and_then(|day| day.and_hms_opt(23, 59, 59))
+            .unwrap();
+        let widest = tz
+            .from_local_datetime(&naive)
+            .earliest()
+            .unwrap_or_else(|| tz.from_utc_datetime(&naive));
+        let width = widest.format(&format).to_string().width();
+
+        Ok(Timestamps {
+            format,
+            width: width.try_into().unwrap_or(u16::MAX),
+        })
+    }
+
+    pub fn from_config(cfg: &UIConfig) -> Result<Option<Self>> {
+        cfg.show_timestamps
+            .then(|| Self::new(&cfg.timestamp_format, cfg.tz))
+            .transpose()
+    }
+
+    /// Width of the timestamp column in cells.
+    pub fn width(&self) -> u16 {
+        self.width
+    }
+}
+
+/// Layout of the last frame drawn, which scrolling is measured
+/// against between frames.
+#[derive(Default)]
+struct LastFrame {
+    view_height: AtomicU16,
+    content_width: AtomicU16,
+    wrap_indent: AtomicU16,
+    /// Screen lines the whole buffer took up, or `usize::MAX`
+    /// if the frame didn't need to lay out the whole buffer.
+    total_lines: AtomicUsize,
+}
+
+impl LastFrame {
+    fn store(&self, view_height: u16, content_width: u16, wrap_indent: u16) {
+        self.view_height.store(view_height, Ordering::Relaxed);
+        self.content_width.store(content_width, Ordering::Relaxed);
+        self.wrap_indent.store(wrap_indent, Ordering::Relaxed);
+    }
+}
+
+/// A line in the buffer, along with its layout from the last
+/// time it was drawn and whether the filter lets it through.
+struct Entry {
+    timestamp: DateTime<Utc>,
+    /// Level of a tracing event. Lines paged back
+    /// in from the spill file have lost theirs.
+    level: Option<Level>,
+    tag: Line<'static>,
+    content: Line<'static>,
+    wrapped: RefCell<Option<Wrapped>>,
+    /// Cleared whenever the filter changes.
+    shown: Cell<Option<bool>>,
+}
+
+/// `content` of an entry wrapped at a particular width.
+struct Wrapped {
+    width: u16,
+    indent: u16,
+    lines: Vec<Line<'static>>,
+}
+
+impl Entry {
+    fn new(timestamp: DateTime<Utc>, tag: Line<'static>, content: Line<'static>) -> Self {
+        Entry {
+            timestamp,
+            level: None,
+            tag,
+            content,
+            wrapped: RefCell::new(None),
+            shown: Cell::new(None),
+        }
+    }
+
+    /// The content wrapped at `width`, re-wrapped only
+    /// if the width or indent changed since last time.
+    fn wrapped(&self, width: u16, indent: u16) -> Ref<'_, [Line<'static>]> {
+        let cached = matches!(
+            &*self.wrapped.borrow(),
+            Some(w) if w.width == width && w.indent == indent
+        );
+        if !cached {
+            *self.wrapped.borrow_mut() = Some(Wrapped {
+                width,
+                indent,
+                lines: wrap::wrap(&self.content, width.into(), indent.into()),
+            });
+        }
 
-use super::ContextualWidget;
+        Ref::map(self.wrapped.borrow(), |w| {
+            w.as_ref().map_or(&[][..], |w| &w.lines[..])
+        })
+    }
+
+    fn height(&self, width: u16, indent: u16) -> usize {
+        self.wrapped(width, indent).len().max(1)
+    }
+}
+
+/// A row of the table drawn for a buffer.
+#[derive(Clone, Copy)]
+enum RowKind {
+    /// The line at this index.
+    Line(usize),
+    /// The day changed just before the line at this index.
+    DayChanged(usize),
+}
+
+/// Lines read back from the spill file at a time.
+const PAGE: usize = 512;
+
+/// Most nicks remembered as having spoken in a buffer,
+/// besides the members of its channel.
+const MAX_SPEAKERS: usize = 256;
 
 /// Two-column fixed-width paragraph display.
 pub struct LogBuffer {
-    buf_limit: u16,
+    buf_limit: usize,
     tz: Tz,
-    scroll: u16,
-    last_frame_height: AtomicU16,
-    raw: VecDeque<(DateTime<Utc>, Line<'static>, Line<'static>)>,
+    scroll: usize,
+    last_frame: LastFrame,
+    raw: VecDeque<Entry>,
+
+    /// Where lines evicted from `raw` go, if anywhere.
+    spill: Option<Spill>,
+    /// How many lines are older than the front of `raw`.
+    /// With a spill file, these are all in it.
+    first: usize,
+    /// Why the spill file was given up on, until reported.
+    spill_error: Option<io::Error>,
+
+    /// The last nicks to speak in this buffer, normalized,
+    /// so mentions of them can be coloured even once they
+    /// have left. Oldest first in `speaker_order`.
+    speakers: HashSet<String>,
+    speaker_order: VecDeque<String>,
+
+    /// Where messages typed into this buffer are sent.
+    /// Status and script buffers have no target.
+    target: Option<Target>,
+
+    /// Topic, modes and so on of the channel shown
+    /// in this buffer, if it shows a channel.
+    channel: ChannelState,
+
+    /// Bumped whenever anything that is drawn changes,
+    /// so the app knows when a new frame is needed.
+    revision: u64,
+
+    /// Only lines matching this are shown.
+    filter: Option<LineFilter>,
+    /// How many lines in `raw` the filter lets through.
+    shown: usize,
+
+    /// Whether the view moves down to new lines while
+    /// at the bottom, or stays where it is.
+    follow: bool,
+
+    /// Matches of this are highlighted.
+    search: Option<Regex>,
+    /// The match last jumped to, counted like `first`.
+    current_match: Option<usize>,
+
+    /// Lines mentioning us since the buffer was last in focus.
+    mentions: usize,
 }
 
 impl LogBuffer {
-    pub fn new(buf_limit: u16, tz: Tz) -> Self {
+    pub fn new(buf_limit: usize, tz: Tz) -> Self {
         Self {
             tz,
             buf_limit,
             scroll: 0,
-            last_frame_height: AtomicU16::new(0),
+            last_frame: LastFrame::default(),
             raw: VecDeque::new(),
+            spill: None,
+            first: 0,
+            spill_error: None,
+            speakers: HashSet::new(),
+            speaker_order: VecDeque::new(),
+            target: None,
+            channel: ChannelState::default(),
+            revision: 0,
+            filter: None,
+            shown: 0,
+            follow: true,
+            search: None,
+            current_match: None,
+            mentions: 0,
         }
     }
 
+    pub fn with_target(mut self, target: Target) -> Self {
+        self.target = Some(target);
+        self
+    }
+
+    pub fn target(&self) -> Option<&Target> {
+        self.target.as_ref()
+    }
+
+    /// Keep lines evicted from memory in a file at `path`, from
+    /// where they are paged back in when scrolled to. Lines spilled
+    /// there within `retention` by earlier sessions can be scrolled
+    /// back to as well. Without a file, evicted lines are gone for
+    /// good.
+    pub fn spill_to(
+        &mut self,
+        path: impl AsRef<std::path::Path>,
+        retention: TimeDelta,
+    ) -> std::io::Result<()> {
+        let spill = Spill::open(path, retention)?;
+        self.first = spill.len();
+        self.spill = Some(spill);
+        Ok(())
+    }
+
+    /// How many lines are older than those in memory, such
+    /// as ones earlier sessions left in the spill file.
+    pub fn older(&self) -> usize {
+        self.first
+    }
+
+    /// Why the spill file stopped being written to, if it has
+    /// since this was last called. Reported by the caller, since
+    /// the debug log is a `LogBuffer` too.
+    pub fn take_spill_error(&mut self) -> Option<io::Error> {
+        self.spill_error.take()
+    }
+
+    /// Time zone timestamps in this buffer are shown in.
+    pub fn tz(&self) -> Tz {
+        self.tz
+    }
+
+    pub fn channel(&self) -> &ChannelState {
+        &self.channel
+    }
+
+    pub fn channel_mut(&mut self) -> &mut ChannelState {
+        self.revision += 1;
+        &mut self.channel
+    }
+
+    /// Changes whenever the buffer needs to be drawn again.
+    pub fn revision(&self) -> u64 {
+        self.revision
+    }
+
     pub fn push_line(
         &mut self,
         timestamp: DateTime<Utc>,
         tag: Line<'static>,
         content: Line<'static>,
     ) {
-        self.raw.push_back((timestamp, tag, content));
+        // Tags styled by their producer, such as log levels,
+        // aren't nicks.
+        if tag.style.fg.is_none() && tag.spans.iter().all(|s| s.style.fg.is_none()) {
+            let nick: String = tag.spans.iter().map(|s| s.content.as_ref()).collect();
+            self.add_speaker(&nick);
+        }
+
+        self.push(Entry::new(timestamp, tag, content));
+    }
+
+    /// Remember `tag` as a nick that spoke here, if it is one,
+    /// forgetting the earliest speaker once there are too many.
+    fn add_speaker(&mut self, tag: &str) {
+        let nick = nickcolor::normalize(tag);
+        if !nickcolor::is_nick(&nick) || self.speakers.contains(&nick) {
+            return;
+        }
+        if self.speaker_order.len() == MAX_SPEAKERS {
+            if let Some(oldest) = self.speaker_order.pop_front() {
+                self.speakers.remove(&oldest);
+            }
+        }
+        self.speakers.insert(nick.clone());
+        self.speaker_order.push_back(nick);
+    }
+
+    /// Whether `nick`, normalized, is in the channel
+    /// or has spoken here lately.
+    fn knows_nick(&self, nick: &str) -> bool {
+        self.channel.members.contains(nick) || self.speakers.contains(nick)
+    }
+
+    /// Push a tracing event, whose level filters can narrow by.
+    pub fn push_event(
+        &mut self,
+        timestamp: DateTime<Utc>,
+        level: Level,
+        tag: Line<'static>,
+        content: Line<'static>,
+    ) {
+        let mut entry = Entry::new(timestamp, tag, content);
+        entry.level = Some(level);
+        self.push(entry);
+    }
+
+    fn push(&mut self, entry: Entry) {
+        self.revision += 1;
 
         // If scroll is zero, do not update scroll so as to 
         // auto-follow new messages.
-        // But if it's nonzero, we want to stay where the
-        // "camera" is and not disrupt the user's scroll.
-        if self.scroll() != 0 {
-            self.inc_scroll();
+        // But if it's nonzero, or following is off, we want
+        // to stay where the "camera" is and not disrupt the
+        // user's scroll.
+        if self.scroll() != 0 || !self.follow {
+            let added = self.added_height(&entry);
+            let _ = self.last_frame.total_lines.fetch_update(
+                Ordering::Relaxed,
+                Ordering::Relaxed,
+                |total| Some(total.saturating_add(added)),
+            );
+            self.set_scroll(self.scroll.saturating_add(added));
+        }
+
+        self.shown += usize::from(self.shows(&entry));
+        self.raw.push_back(entry);
+        self.trim();
+    }
+
+    /// Discard any older messages we need to in order to get to within the buffer limit.
+    fn trim(&mut self) {
+        // While scrolled back, lines paged in are given a page of
+        // slack, and the rows in view are kept unless a view left
+        // paused would otherwise hold back eviction for good.
+        let scrolled_back = self.spill.is_some() && self.scroll != 0;
+        let limit = if scrolled_back {
+            self.buf_limit.saturating_add(PAGE)
+        } else {
+            self.buf_limit
+        };
+        let mut excess = self.raw.len().saturating_sub(limit);
+        if excess == 0 {
+            return;
+        }
+        if scrolled_back && self.raw.len() <= limit.saturating_add(PAGE) {
+            excess = excess.min(self.first_in_view());
+        }
+
+        for entry in self.raw.drain(..excess) {
+            // Every entry has been matched by now.
+            if entry.shown.get() == Some(true) {
+                self.shown -= 1;
+            }
+            // Lines that were paged in are already in the file.
+            if let Some(spill) = &mut self.spill {
+                if self.first == spill.len() {
+                    if let Err(e) = spill.push(&entry.timestamp, &entry.tag, &entry.content) {
+                        self.spill = None;
+                        self.spill_error = Some(e);
+                    }
+                }
+            }
+            self.first += 1;
+        }
+
+        if scrolled_back {
+            // The layout of the buffer is no longer known.
+            self.last_frame.total_lines.store(usize::MAX, Ordering::Relaxed);
+        }
+    }
+
+    /// Index of the oldest line in `raw` at or below
+    /// the top of the view, as of the last frame.
+    fn first_in_view(&self) -> usize {
+        let width = self.last_frame.content_width.load(Ordering::Relaxed);
+        let indent = self.last_frame.wrap_indent.load(Ordering::Relaxed);
+        let view = usize::from(self.last_frame.view_height.load(Ordering::Relaxed));
+        let (rows, _) = self.tail(width, indent, self.scroll.saturating_add(view));
+        match rows.first() {
+            Some((RowKind::Line(i) | RowKind::DayChanged(i), _)) => *i,
+            None => self.raw.len(),
+        }
+    }
+
+    /// Read the page of spilled lines just before the
+    /// front of the buffer back into memory.
+    fn page_in(&mut self) -> bool {
+        let Some(spill) = &mut self.spill else {
+            return false;
+        };
+        if self.first == 0 {
+            return false;
         }
 
-        // Discard any older messages we need to in order to get to within the buffer limit.
-        while self.raw.len() >= self.buf_limit.into() {
-            self.raw.pop_front();
+        let start = self.first.saturating_sub(PAGE);
+        let lines = match spill.read(start..self.first) {
+            Ok(lines) => lines,
+            Err(e) => {
+                self.spill = None;
+                self.spill_error = Some(e);
+                return false;
+            }
+        };
+        for (timestamp, tag, content) in lines.into_iter().rev() {
+            let entry = Entry::new(timestamp, tag, content);
+            self.shown += usize::from(self.shows(&entry));
+            self.raw.push_front(entry);
         }
+        self.first = start;
+
+        // The layout of the buffer is no longer known.
+        self.last_frame.total_lines.store(usize::MAX, Ordering::Relaxed);
+        true
     }
 
-    pub fn scroll(&self) -> u16 {
+    pub fn scroll(&self) -> usize {
         self.scroll
     }
 
-    pub fn set_scroll(&mut self, val: u16) {
+    pub fn set_scroll(&mut self, val: usize) {
+        self.revision += 1;
         self.scroll = self.clamp_scroll(val);
+
+        // Scrolling past the top of what is in memory
+        // pages more in from the spill file.
+        if val > self.scroll && self.page_in() {
+            self.scroll = val;
+        }
+        if self.scroll == 0 {
+            self.trim();
+        }
     }
 
     pub fn inc_scroll(&mut self) {
-        self.scroll = self.clamp_scroll(self.scroll.saturating_add(1));
+        self.set_scroll(self.scroll.saturating_add(1));
     }
 
     pub fn dec_scroll(&mut self) {
-        self.scroll = self.clamp_scroll(self.scroll.saturating_sub(1));
+        self.set_scroll(self.scroll.saturating_sub(1));
     }
 
-    fn clamp_scroll(&self, value: u16) -> u16 {
-        value.clamp(
-            0,
-            self.count().try_into().unwrap_or(u16::MAX).saturating_add(2).saturating_sub(
-                self.last_frame_height
-                    .load(std::sync::atomic::Ordering::Relaxed),
-            ),
-        )
+    /// Scroll is measured in screen lines, and can go no
+    /// further back than the top of the buffer. If the last
+    /// frame didn't reach the top, it isn't clamped until
+    /// the next one does.
+    fn clamp_scroll(&self, value: usize) -> usize {
+        let total = self.last_frame.total_lines.load(Ordering::Relaxed);
+        let view = usize::from(self.last_frame.view_height.load(Ordering::Relaxed));
+        value.min(total.saturating_sub(view))
+    }
+
+    /// Screen lines a new line will add at the last frame's
+    /// width, including any day separator before it.
+    fn added_height(&self, entry: &Entry) -> usize {
+        if !self.shows(entry) {
+            return 0;
+        }
+        let separator = self
+            .raw
+            .iter()
+            .rev()
+            .find(|e| self.shows(e))
+            .is_some_and(|last| self.day(last) != self.day(entry));
+
+        entry.height(
+            self.last_frame.content_width.load(Ordering::Relaxed),
+            self.last_frame.wrap_indent.load(Ordering::Relaxed),
+        ) + usize::from(separator)
+    }
+
+    /// The local date an entry was logged on.
+    fn day(&self, entry: &Entry) -> NaiveDate {
+        self.tz
+            .from_utc_datetime(&entry.timestamp.naive_utc())
+            .date_naive()
     }
 
     pub fn count(&self) -> usize {
         self.raw.len()
     }
 
-    pub fn lines(&self) -> &VecDeque<(DateTime<Utc>, Line<'static>, Line<'static>)> {
-        &self.raw
-    }
-
-    pub fn line_height(line: &Line<'static>, max_width: u16) -> usize {
-        line.width()
-            .checked_div(max_width as usize)
-            .unwrap_or(1)
-            .saturating_add(1)
-    }
-
-    pub fn rows(&self, content_width: u16) -> impl Iterator<Item = Row<'static>> + '_ {
-        self.lines().iter().map(move |(timestamp, tag, content)| {
-            let row_height: u16;
-            Row::new::<_>([
-                Text::from(format!(
-                    "[{}]",
-                    self.tz
-                        .from_utc_datetime(&timestamp.naive_utc())
-                        .format("%H:%M:%S")
-                ))
-                .left_aligned(),
-                {
-                    let mut t = Text::default();
-                    t.push_line(tag.clone());
-                    t.right_aligned()
-                },
-                {
-                    let mut t = Text::default();
-                    row_height = Self::line_height(content, content_width)
-                        .clamp(0, u16::MAX as usize) as u16;
-
-                    let mut acc = Line::default();
-                    for span in &content.spans {
-                        let mut buf = String::new();
-                        for grapheme in span.content.graphemes(true) {
-                            // If adding the grapheme would overflow the current line...
-                            if acc.width() + buf.width() + grapheme.width() > content_width as usize
-                            {
-                                // Then push the line and clear the buffer for the next line!
-                                acc.push_span(Span::styled(std::mem::take(&mut buf), span.style));
-                                t.push_line(std::mem::take(&mut acc));
-                            }
-                            // In both cases, make sure to push the grapheme to the buffer!
-                            buf.push_str(grapheme);
-                        }
-
-                        // Cleanup in case there is any extra in the buffer.
-                        if !buf.is_empty() {
-                            // Then push the line and clear the buffer for the next line!
-                            acc.push_span(Span::styled(std::mem::take(&mut buf), span.style));
-                        }
-                    }
+    pub fn mentions(&self) -> usize {
+        self.mentions
+    }
 
-                    t.push_line(acc);
-                    t.left_aligned()
-                },
-            ])
-            .height(row_height)
-        })
+    pub fn add_mention(&mut self) {
+        self.mentions += 1;
+    }
+
+    /// Mark every mention as seen.
+    pub fn clear_mentions(&mut self) {
+        self.mentions = 0;
+    }
+
+    pub fn filter(&self) -> Option<&LineFilter> {
+        self.filter.as_ref()
+    }
+
+    /// Show only the lines matching `filter`, or every line
+    /// again if `None`. The view returns to the bottom.
+    pub fn set_filter(&mut self, filter: Option<LineFilter>) {
+        self.filter = filter;
+        for entry in &self.raw {
+            entry.shown.set(None);
+        }
+        self.shown = self.raw.iter().filter(|e| self.shows(e)).count();
+        self.last_frame.total_lines.store(usize::MAX, Ordering::Relaxed);
+        self.set_scroll(0);
+    }
+
+    pub fn following(&self) -> bool {
+        self.follow
+    }
+
+    /// Turning following back on jumps to the bottom.
+    pub fn set_follow(&mut self, follow: bool) {
+        self.follow = follow;
+        self.revision += 1;
+        if follow {
+            self.set_scroll(0);
+        }
+    }
+
+    /// Whether the filter, if any, lets `entry` through,
+    /// only matched against the filter once.
+    fn shows(&self, entry: &Entry) -> bool {
+        if let Some(shown) = entry.shown.get() {
+            return shown;
+        }
+        let shown = self
+            .filter
+            .as_ref()
+            .is_none_or(|f| f.matches(entry.level, &entry.tag, &entry.content));
+        entry.shown.set(Some(shown));
+        shown
+    }
+
+    /// How many lines the filter lets through.
+    pub fn shown_count(&self) -> usize {
+        self.shown
+    }
+
+    /// The lines the filter lets through, like `lines`.
+    pub fn shown_lines(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = (&DateTime<Utc>, &Line<'static>, &Line<'static>)> {
+        self.raw
+            .iter()
+            .filter(|e| self.shows(e))
+            .map(|e| (&e.timestamp, &e.tag, &e.content))
+    }
+
+    pub fn search(&self) -> Option<&Regex> {
+        self.search.as_ref()
+    }
+
+    /// Highlight matches of `search`, or stop highlighting.
+    /// Stepping through matches starts again from the bottom.
+    pub fn set_search(&mut self, search: Option<Regex>) {
+        if self.search.as_ref().map(Regex::as_str) == search.as_ref().map(Regex::as_str) {
+            return;
+        }
+        self.search = search;
+        self.current_match = None;
+        self.revision += 1;
+    }
+
+    /// Every line whose message matches `re`, oldest first, along
+    /// with an id for `jump_to` which stays put as lines are evicted.
+    pub fn find<'a>(
+        &'a self,
+        re: &'a Regex,
+    ) -> impl Iterator<Item = (usize, &'a DateTime<Utc>, &'a Line<'static>, &'a Line<'static>)>
+    {
+        self.raw
+            .iter()
+            .enumerate()
+            .filter(|(_, e)| re.is_match(&plain(&e.content)))
+            .map(|(i, e)| (self.first + i, &e.timestamp, &e.tag, &e.content))
+    }
+
+    /// When the oldest line still in memory was logged.
+    pub fn oldest(&self) -> Option<DateTime<Utc>> {
+        self.raw.front().map(|e| e.timestamp)
+    }
+
+    /// Scroll the line with id `id`, from `find`, to the middle
+    /// of the view, clearing a filter that hides it. Returns
+    /// false if the line is no longer in memory.
+    pub fn jump_to(&mut self, id: usize) -> bool {
+        let Some(i) = id.checked_sub(self.first).filter(|&i| i < self.raw.len()) else {
+            return false;
+        };
+        if !self.shows(&self.raw[i]) {
+            self.set_filter(None);
+        }
+
+        let width = self.last_frame.content_width.load(Ordering::Relaxed);
+        let indent = self.last_frame.wrap_indent.load(Ordering::Relaxed);
+        let view = usize::from(self.last_frame.view_height.load(Ordering::Relaxed));
+        let (rows, _) = self.tail(width, indent, usize::MAX);
+        let Some(pos) = rows
+            .iter()
+            .position(|(row, _)| matches!(row, RowKind::Line(j) if *j == i))
+        else {
+            return false;
+        };
+
+        let total = rows.iter().map(|(_, height)| height).sum();
+        let below: usize = rows[pos + 1..].iter().map(|(_, height)| height).sum();
+        let height = rows[pos].1;
+        self.last_frame.total_lines.store(total, Ordering::Relaxed);
+
+        self.current_match = Some(id);
+        self.set_scroll(below.saturating_sub(view.saturating_sub(height) / 2));
+        true
+    }
+
+    /// Scroll the first line logged at or after `timestamp`,
+    /// or else the newest line, to the middle of the view.
+    pub fn jump_near(&mut self, timestamp: DateTime<Utc>) -> bool {
+        let Some(newest) = self.raw.len().checked_sub(1) else {
+            return false;
+        };
+        let i = self.raw.partition_point(|e| e.timestamp < timestamp);
+        self.jump_to(self.first + i.min(newest))
+    }
+
+    /// Jump to the next older or newer match of the search,
+    /// starting from the bottom. Returns false if there are
+    /// no more matches that way.
+    pub fn step_search(&mut self, older: bool) -> bool {
+        let Some(re) = &self.search else {
+            return false;
+        };
+        let len = self.raw.len();
+        let current = self
+            .current_match
+            .and_then(|id| id.checked_sub(self.first))
+            .filter(|&i| i < len);
+        let is_match =
+            |i: &usize| self.shows(&self.raw[*i]) && re.is_match(&plain(&self.raw[*i].content));
+
+        let found = if older {
+            (0..current.unwrap_or(len)).rev().find(is_match)
+        } else {
+            (current.map_or(len, |i| i + 1)..len).find(is_match)
+        };
+        found.is_some_and(|i| self.jump_to(self.first + i))
+    }
+
+    /// Write the lines the filter lets through as plain text,
+    /// one per line, and return how many there were.
+    pub fn export(&self, mut out: impl Write) -> io::Result<usize> {
+        let mut count = 0;
+        for (timestamp, tag, content) in self.shown_lines() {
+            let local = self.tz.from_utc_datetime(&timestamp.naive_utc());
+            writeln!(
+                out,
+                "{} {}\t{}",
+                local.format("%Y-%m-%d %H:%M:%S"),
+                plain(tag),
+                plain(content)
+            )?;
+            count += 1;
+        }
+        out.flush()?;
+        Ok(count)
+    }
+
+    /// Every line in the buffer as its timestamp,
+    /// tag and content, oldest first.
+    pub fn lines(
+        &self,
+    ) -> impl DoubleEndedIterator<Item = (&DateTime<Utc>, &Line<'static>, &Line<'static>)> {
+        self.raw.iter().map(|e| (&e.timestamp, &e.tag, &e.content))
+    }
+
+    /// How many screen lines `line` takes up once wrapped.
+    pub fn line_height(line: &Line<'static>, max_width: u16, indent: u16) -> usize {
+        wrap::height(line, max_width.into(), indent.into())
+    }
+
+    /// The rows at the bottom of the buffer and their heights,
+    /// top to bottom: enough to fill `needed` screen lines, or
+    /// every row if the buffer is shorter than that. Only these
+    /// rows are wrapped, so scrollback far out of view costs
+    /// nothing to draw. Also returns whether the top was reached.
+    fn tail(&self, width: u16, indent: u16, needed: usize) -> (Vec<(RowKind, usize)>, bool) {
+        let mut rows = Vec::new();
+        let mut lines = 0;
+
+        let mut shown = self
+            .raw
+            .iter()
+            .enumerate()
+            .rev()
+            .filter(|(_, e)| self.shows(e))
+            .peekable();
+        while let Some((i, entry)) = shown.next() {
+            let height = entry.height(width, indent);
+            rows.push((RowKind::Line(i), height));
+            lines += height;
+
+            // Mark the first line of each new day, but not the
+            // very first line shown.
+            let above = shown.peek();
+            if above.is_some_and(|(_, above)| self.day(above) != self.day(entry)) {
+                rows.push((RowKind::DayChanged(i), 1));
+                lines += 1;
+            }
+
+            if lines >= needed && above.is_some() {
+                rows.reverse();
+                return (rows, false);
+            }
+        }
+
+        rows.reverse();
+        (rows, true)
+    }
+
+    /// The cells of a row, with `skip` lines cut from the
+    /// top and at most `keep` lines left.
+    fn cells(
+        &self,
+        row: RowKind,
+        width: u16,
+        ctx: &RenderContext,
+        skip: usize,
+        keep: usize,
+    ) -> Vec<Text<'static>> {
+        let entry = match row {
+            RowKind::Line(i) => &self.raw[i],
+            // Separators are one line tall, so are never cut.
+            RowKind::DayChanged(i) => {
+                let local = self.tz.from_utc_datetime(&self.raw[i].timestamp.naive_utc());
+                return Self::day_separator(local.format(DAY_CHANGED).to_string(), ctx);
+            }
+        };
+        let local = self.tz.from_utc_datetime(&entry.timestamp.naive_utc());
+
+        // The timestamp and tag sit beside the first line of the
+        // message, so they scroll out of view along with it.
+        let mut cells = Vec::with_capacity(3);
+        if let Some(timestamps) = &ctx.timestamps {
+            cells.push(if skip > 0 {
+                Text::default()
+            } else {
+                Text::from(local.format(&timestamps.format).to_string())
+                    .style(ctx.theme.timestamp)
+                    .left_aligned()
+            });
+        }
+        cells.push(if skip > 0 {
+            Text::default()
+        } else {
+            let mut t = Text::default().style(ctx.theme.tag);
+            t.push_line(ctx.nick_colors.color_tag(&entry.tag));
+            t.right_aligned()
+        });
+
+        // Only the lines in view are coloured, since
+        // colouring doesn't change how lines wrap.
+        let content: Vec<Line<'static>> = entry
+            .wrapped(width, ctx.wrap_indent)
+            .iter()
+            .skip(skip)
+            .take(keep)
+            .map(|line| ctx.nick_colors.color_mentions(line, |nick| self.knows_nick(nick)))
+            .map(|line| match &self.search {
+                Some(re) => search::highlight(&line, re, ctx.theme.search_match.into()),
+                None => line,
+            })
+            .collect();
+        cells.push(Text::from(content).left_aligned());
+        cells
+    }
+
+    /// A row announcing that the lines below it are from a new day.
+    fn day_separator(text: String, ctx: &RenderContext) -> Vec<Text<'static>> {
+        let mut cells = vec![Text::default(); ctx.timestamps.is_some() as usize + 1];
+        cells.push(Text::from(text).style(ctx.theme.timestamp).left_aligned());
+        cells
+    }
+
+    /// Diagnostics about the last frame, drawn in the
+    /// top right corner of the buffer.
+    fn render_overlay(
+        &self,
+        ctx: &RenderContext,
+        area: Rect,
+        content_width: u16,
+        heights: &[usize],
+        buf: &mut Buffer,
+    ) {
+        let last_line_height = self
+            .raw
+            .back()
+            .map_or(0, |e| e.height(content_width, ctx.wrap_indent));
+        let text = Text::from(vec![
+            Line::from(format!("Rect: {:?}", (area.width, area.height))),
+            Line::from(format!("Scroll: {}", self.scroll)),
+            Line::from(format!(
+                "Lines: {} ({} rows, {} screen lines laid out)",
+                self.count(),
+                heights.len(),
+                heights.iter().sum::<usize>(),
+            )),
+            Line::from(format!("Last line height: {last_line_height}")),
+        ]);
+
+        let width = (text.width() as u16 + 2).min(area.width);
+        let height = (text.height() as u16 + 2).min(area.height);
+        let overlay = Rect::new(area.right().saturating_sub(width), area.y, width, height);
+
+        Clear.render(overlay, buf);
+        Paragraph::new(text)
+            .block(
+                Block::bordered()
+                    .border_style(ctx.theme.border)
+                    .title_style(ctx.theme.title)
+                    .title("Debug"),
+            )
+            .render(overlay, buf);
+    }
+
+    /// Widths of the table's columns: timestamp
+    /// (if shown), tag, then the message itself.
+    fn columns(ctx: &RenderContext) -> Vec<Constraint> {
+        let mut columns = Vec::with_capacity(3);
+        if let Some(timestamps) = &ctx.timestamps {
+            columns.push(Constraint::Length(timestamps.width()));
+        }
+        columns.push(Constraint::Length(ctx.lcol_width));
+        columns.push(Constraint::Fill(1));
+        columns
     }
 }
 
@@ -153,45 +901,195 @@ impl ContextualWidget for LogBuffer {
     fn render_ref(&self, ctx: &RenderContext, area: Rect, buf: &mut Buffer) {
         // Determine how many characters wide the content buffer is, in order to properly
         // apply line wrap.
-        // content_width = area.width - TIMESTAMP_WIDTH - CUMULATIVE_BORDER_WIDTH - LCOL_WIDTH;
-        let content_width = area.width.saturating_sub(14).saturating_sub(ctx.lcol_width);
+        // content_width = area.width - CUMULATIVE_BORDER_WIDTH - fixed columns - column gaps;
+        let columns = Self::columns(ctx);
+        let fixed_width: u16 = columns
+            .iter()
+            .map(|c| match c {
+                Constraint::Length(width) => *width,
+                _ => 0,
+            })
+            .sum();
+        let gaps = columns.len().saturating_sub(1) as u16;
+        let content_width = area
+            .width
+            .saturating_sub(2)
+            .saturating_sub(fixed_width)
+            .saturating_sub(gaps);
 
-        let content = self.rows(content_width);
+        // Everything below the top border.
+        let view_height = area.height.saturating_sub(1);
+        let scroll = self.scroll();
 
-        self.last_frame_height.store(area.height, std::sync::atomic::Ordering::Relaxed);
+        // Day separators mean there can be more rows than lines.
+        let (rows, complete) =
+            self.tail(content_width, ctx.wrap_indent, scroll + usize::from(view_height));
+        let heights: Vec<usize> = rows.iter().map(|(_, height)| *height).collect();
 
-        let t = Table::new(
-            content,
-            vec![
-                Constraint::Length(10),
-                Constraint::Length(ctx.lcol_width),
-                Constraint::Fill(1),
-            ],
-        )
+        self.last_frame.store(view_height, content_width, ctx.wrap_indent);
+        self.last_frame.total_lines.store(
+            if complete { heights.iter().sum() } else { usize::MAX },
+            Ordering::Relaxed,
+        );
+
+        // Rows only partly in view are cut down to the lines that
+        // are, so that scrolling moves one screen line at a time.
+        let (first, skip, end) = visible(&heights, view_height.into(), scroll);
+        let mut remaining = usize::from(view_height);
+        let content: Vec<Row> = rows[first..end]
+            .iter()
+            .enumerate()
+            .map(|(i, (row, height))| {
+                let skip = if i == 0 { skip } else { 0 };
+                let keep = height.saturating_sub(skip).min(remaining);
+                remaining -= keep;
+
+                let cells = self.cells(*row, content_width, ctx, skip, keep);
+                Row::new(cells).height(keep.try_into().unwrap_or(u16::MAX))
+            })
+            .collect();
+
+        let t = Table::new(content, columns)
         .block(
             Block::new()
                 .borders(Borders::ALL ^ Borders::BOTTOM)
-                .title_alignment(Alignment::Center)
-                .title(format!(
-                    "Rect: {:?} | Scroll: {} | LC: {} | LLH: {:?}",
-                    (area.width, area.height),
-                    self.scroll,
-                    self.count(),
-                    Self::line_height(
-                        self.lines()
-                            .get(self.count().saturating_sub(1))
-                            .map(|i| &i.2)
-                            .unwrap_or(&Line::default()),
-                        content_width
-                    ),
-                )),
+                .border_style(ctx.theme.pane_selected),
         );
+        Widget::render(t, area, buf);
 
-        let mut t_state = TableState::default().with_offset(
-            self.count()
-                .saturating_sub(area.height.saturating_sub(2) as usize)
-                .saturating_sub(self.scroll() as usize),
-        );
-        StatefulWidgetRef::render_ref(&t, area, buf, &mut t_state)
+        if ctx.debug_overlay {
+            self.render_overlay(ctx, area, content_width, &heights, buf);
+        }
+    }
+}
+
+/// Which rows to draw so that the view ends `scroll` screen lines
+/// above the bottom of the buffer. Returns the first row in view,
+/// how many of its lines are above the top of the view, and the
+/// row after the last one in view.
+fn visible(heights: &[usize], view_height: usize, scroll: usize) -> (usize, usize, usize) {
+    let total: usize = heights.iter().sum();
+    let scroll = scroll.min(total.saturating_sub(view_height));
+    let bottom = total - scroll;
+    let top = bottom.saturating_sub(view_height);
+
+    let (mut first, mut skip, mut end) = (heights.len(), 0, heights.len());
+    let mut start = 0;
+    for (i, height) in heights.iter().enumerate() {
+        if first == heights.len() && start + height > top {
+            first = i;
+            skip = top - start;
+        }
+        start += height;
+        if start >= bottom {
+            end = i + 1;
+            break;
+        }
+    }
+    (first.min(end), skip, end)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{visible, LogBuffer, Timestamps, MAX_SPEAKERS, PAGE};
+    use chrono::{TimeDelta, Utc};
+    use ratatui::text::Line;
+
+    #[test]
+    fn follows_the_bottom_when_not_scrolled() {
+        assert_eq!(visible(&[1, 1, 1, 1], 2, 0), (2, 0, 4));
+    }
+
+    #[test]
+    fn scrolls_by_screen_lines_through_tall_rows() {
+        // The second row wraps onto three lines.
+        let heights = [1, 3, 1];
+        assert_eq!(visible(&heights, 2, 0), (1, 2, 3));
+        assert_eq!(visible(&heights, 2, 1), (1, 1, 2));
+        assert_eq!(visible(&heights, 2, 2), (1, 0, 2));
+        assert_eq!(visible(&heights, 2, 3), (0, 0, 2));
+    }
+
+    #[test]
+    fn cannot_scroll_past_the_top() {
+        assert_eq!(visible(&[1, 3, 1], 2, 99), (0, 0, 2));
+    }
+
+    #[test]
+    fn short_buffers_show_everything() {
+        assert_eq!(visible(&[1, 2], 10, 0), (0, 0, 2));
+        assert_eq!(visible(&[1, 2], 10, 5), (0, 0, 2));
+    }
+
+    #[test]
+    fn empty_buffers_show_nothing() {
+        assert_eq!(visible(&[], 10, 0), (0, 0, 0));
+    }
+
+    #[test]
+    fn rejects_unknown_timestamp_specifiers() {
+        assert!(Timestamps::new("%Q", chrono_tz::UTC).is_err());
+        assert!(Timestamps::new("[%H:%M", chrono_tz::UTC).is_ok());
+    }
+
+    #[test]
+    fn measures_timestamps_in_their_time_zone() {
+        let utc = Timestamps::new("%H:%M %Z", chrono_tz::UTC).unwrap();
+        let nz = Timestamps::new("%H:%M %Z", chrono_tz::Pacific::Auckland).unwrap();
+        assert_eq!(utc.width(), "23:59 UTC".len() as u16);
+        assert_eq!(nz.width(), "23:59 NZDT".len() as u16);
+    }
+
+    #[test]
+    fn paused_buffers_still_spill() {
+        let path = std::env::temp_dir().join(format!("eesh-paused-{}.spill", std::process::id()));
+        let mut lb = LogBuffer::new(10, chrono_tz::UTC);
+        lb.spill_to(&path, TimeDelta::days(1)).unwrap();
+        lb.set_follow(false);
+
+        for i in 0..4 * PAGE {
+            lb.push_line(Utc::now(), Line::from("nick"), Line::from(i.to_string()));
+        }
+        assert!(lb.scroll() > 0);
+        assert!(lb.count() <= 10 + 2 * PAGE);
+
+        drop(lb);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn reopened_spills_count_as_older_lines() {
+        let path = std::env::temp_dir().join(format!("eesh-reopen-{}.spill", std::process::id()));
+        let mut lb = LogBuffer::new(2, chrono_tz::UTC);
+        lb.spill_to(&path, TimeDelta::days(1)).unwrap();
+        assert_eq!(lb.older(), 0);
+        for i in 0..5 {
+            lb.push_line(Utc::now(), Line::from("nick"), Line::from(i.to_string()));
+        }
+        drop(lb);
+
+        let mut lb = LogBuffer::new(2, chrono_tz::UTC);
+        lb.spill_to(&path, TimeDelta::days(1)).unwrap();
+        assert_eq!(lb.older(), 3);
+
+        drop(lb);
+        std::fs::remove_file(path).unwrap();
+    }
+
+    #[test]
+    fn remembers_only_the_latest_speakers() {
+        let mut lb = LogBuffer::new(10, chrono_tz::UTC);
+        lb.push_line(Utc::now(), Line::from("-->"), Line::from("joined"));
+        for i in 0..=MAX_SPEAKERS {
+            lb.push_line(Utc::now(), Line::from(format!("Nick{i}")), Line::from("hi"));
+        }
+
+        assert_eq!(lb.speakers.len(), MAX_SPEAKERS);
+        assert!(!lb.knows_nick("-->"));
+        assert!(!lb.knows_nick("nick0"));
+        assert!(lb.knows_nick(&format!("nick{MAX_SPEAKERS}")));
+
+        lb.channel_mut().members.insert(String::from("nick0"));
+        assert!(lb.knows_nick("nick0"));
     }
 }
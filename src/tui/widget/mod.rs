@@ -1,16 +1,48 @@
 use std::sync::{Arc, Mutex};
 
 use ratatui::{buffer::Buffer, layout::Rect, widgets::Widget};
+
+use super::{mirc::Formatter, nickcolor::NickColors, Theme};
+use crate::client::NetworkStatus;
 pub use terminal::Terminal;
-pub use logbuffer::LogBuffer;
+pub use filter::{plain, LineFilter};
+pub use filterbar::FilterBar;
+pub use logbuffer::{LogBuffer, Timestamps};
+pub use statusline::{StatusLine, StatusSegment};
+pub use topicbar::{TopicBar, TopicView};
 
+mod filter;
+mod filterbar;
 mod logbuffer;
+pub mod search;
+mod spill;
+mod statusline;
 mod terminal;
+mod topicbar;
+pub mod wrap;
 
 #[derive(Default)]
 pub struct RenderContext {
     pub user_line: String,
     pub lcol_width: u16,
+    /// `None` when timestamps are hidden.
+    pub timestamps: Option<Timestamps>,
+    /// Indent of the lines a long message wraps onto.
+    pub wrap_indent: u16,
+    pub formatter: Formatter,
+    pub nick_colors: NickColors,
+    pub theme: Theme,
+
+    /// Segments of the status line, in order.
+    pub status_line: Vec<StatusSegment>,
+    /// Our own state on the focused buffer's network.
+    pub network: Option<NetworkStatus>,
+    /// Draw diagnostics over the focused buffer.
+    pub debug_overlay: bool,
+    pub topic_view: TopicView,
+    /// Names of other buffers with unseen mentions,
+    /// and how many there are.
+    pub mentions: Vec<(String, usize)>,
 
     pub text_buffer: Option<Arc<Mutex<LogBuffer>>>,
 }
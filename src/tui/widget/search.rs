@@ -0,0 +1,57 @@
+use ratatui::{
+    style::Style,
+    text::{Line, Span},
+};
+use regex::{Regex, RegexBuilder};
+
+use super::filter::plain;
+
+/// A regex finding `text` anywhere, ignoring case,
+/// or `None` if there is nothing to find.
+pub fn pattern(text: &str) -> Option<Regex> {
+    if text.is_empty() {
+        return None;
+    }
+    RegexBuilder::new(&regex::escape(text))
+        .case_insensitive(true)
+        .build()
+        .ok()
+}
+
+/// `line` with every match of `re` restyled with `style`.
+/// Spans are split where a match starts or ends inside them.
+pub fn highlight(line: &Line<'static>, re: &Regex, style: Style) -> Line<'static> {
+    let text = plain(line);
+    let matches: Vec<_> = re
+        .find_iter(&text)
+        .map(|m| m.range())
+        .filter(|m| !m.is_empty())
+        .collect();
+    if matches.is_empty() {
+        return line.clone();
+    }
+
+    let mut out = Line::default().style(line.style);
+    out.alignment = line.alignment;
+    let mut offset = 0;
+    for span in &line.spans {
+        let end = offset + span.content.len();
+        let mut pos = offset;
+        for m in matches.iter().filter(|m| m.start < end && m.end > offset) {
+            let (start, stop) = (m.start.max(offset), m.end.min(end));
+            if start > pos {
+                out.push_span(Span::styled(text[pos..start].to_owned(), span.style));
+            }
+            out.push_span(Span::styled(
+                text[start..stop].to_owned(),
+                span.style.patch(style),
+            ));
+            pos = stop;
+        }
+        if pos < end {
+            out.push_span(Span::styled(text[pos..end].to_owned(), span.style));
+        }
+        offset = end;
+    }
+    out
+}
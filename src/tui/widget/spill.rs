@@ -0,0 +1,253 @@
+use chrono::{DateTime, TimeDelta, Utc};
+use ratatui::{
+    style::{Color, Modifier, Style},
+    text::{Line, Span},
+};
+use std::{
+    fs::{File, OpenOptions},
+    io::{self, BufRead, BufReader, BufWriter, Seek, SeekFrom, Write},
+    ops::Range,
+    path::Path,
+};
+
+/// Separates the fields of a style from the text of a span,
+/// and the spans of a line from each other.
+const FIELD: char = '\x1e';
+const SPAN: char = '\x1f';
+
+/// Lines evicted from a `LogBuffer`, oldest first, kept in an
+/// append-only file so that they can be paged back in. The file
+/// is kept between sessions, and locked while in use.
+pub struct Spill {
+    writer: BufWriter<File>,
+    reader: BufReader<File>,
+
+    /// Byte offset of every record in the file.
+    offsets: Vec<u64>,
+    end: u64,
+}
+
+impl Spill {
+    /// Open the spill file at `path`, keeping the lines an earlier
+    /// session spilled there within the last `retention`. Fails if
+    /// another session has the file open.
+    pub fn open(path: impl AsRef<Path>, retention: TimeDelta) -> io::Result<Self> {
+        let path = path.as_ref();
+        if let Some(parent) = path.parent() {
+            std::fs::create_dir_all(parent)?;
+        }
+
+        let mut file = OpenOptions::new()
+            .create(true)
+            .truncate(false)
+            .read(true)
+            .write(true)
+            .open(path)?;
+        file.try_lock()?;
+
+        // Drop records that have expired, or are cut short.
+        let cutoff = (Utc::now() - retention).timestamp_millis();
+        let mut kept = String::new();
+        let mut offsets = Vec::new();
+        let mut expired = false;
+        for record in BufReader::new(&file).lines() {
+            let record = record?;
+            let timestamp = record.split_once('\t').map(|(ms, _)| ms.parse::<i64>());
+            match timestamp {
+                Some(Ok(ms)) if ms >= cutoff => {
+                    offsets.push(kept.len() as u64);
+                    kept.push_str(&record);
+                    kept.push('\n');
+                }
+                _ => expired = true,
+            }
+        }
+        if expired {
+            file.set_len(0)?;
+            file.seek(SeekFrom::Start(0))?;
+            file.write_all(kept.as_bytes())?;
+        }
+        file.seek(SeekFrom::Start(kept.len() as u64))?;
+
+        let reader = File::open(path)?;
+        Ok(Spill {
+            writer: BufWriter::new(file),
+            reader: BufReader::new(reader),
+            offsets,
+            end: kept.len() as u64,
+        })
+    }
+
+    /// How many lines have been spilled.
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn push(
+        &mut self,
+        timestamp: &DateTime<Utc>,
+        tag: &Line<'static>,
+        content: &Line<'static>,
+    ) -> io::Result<()> {
+        let mut record = timestamp.timestamp_millis().to_string();
+        record.push('\t');
+        encode_line(tag, &mut record);
+        record.push('\t');
+        encode_line(content, &mut record);
+        record.push('\n');
+
+        self.writer.write_all(record.as_bytes())?;
+        self.offsets.push(self.end);
+        self.end += record.len() as u64;
+        Ok(())
+    }
+
+    /// Read back the lines in `range`, oldest first.
+    pub fn read(
+        &mut self,
+        range: Range<usize>,
+    ) -> io::Result<Vec<(DateTime<Utc>, Line<'static>, Line<'static>)>> {
+        let Some(&start) = self.offsets.get(range.start) else {
+            return Ok(Vec::new());
+        };
+        self.writer.flush()?;
+        self.reader.seek(SeekFrom::Start(start))?;
+
+        let mut lines = Vec::with_capacity(range.len());
+        let mut record = String::new();
+        for _ in range {
+            record.clear();
+            if self.reader.read_line(&mut record)? == 0 {
+                break;
+            }
+            lines.push(decode_record(record.trim_end_matches('\n')).ok_or_else(|| {
+                io::Error::new(io::ErrorKind::InvalidData, "Corrupt scrollback record")
+            })?);
+        }
+        Ok(lines)
+    }
+}
+
+fn encode_line(line: &Line<'static>, out: &mut String) {
+    encode_style(line.style, out);
+    for span in &line.spans {
+        out.push(SPAN);
+        encode_style(span.style, out);
+        out.push(FIELD);
+        escape(&span.content, out);
+    }
+}
+
+fn encode_style(style: Style, out: &mut String) {
+    let color = |c: Option<Color>| c.map(|c| c.to_string()).unwrap_or_default();
+    out.push_str(&format!(
+        "{};{};{};{}",
+        color(style.fg),
+        color(style.bg),
+        style.add_modifier.bits(),
+        style.sub_modifier.bits()
+    ));
+}
+
+fn decode_record(record: &str) -> Option<(DateTime<Utc>, Line<'static>, Line<'static>)> {
+    let mut fields = record.splitn(3, '\t');
+    let timestamp = DateTime::from_timestamp_millis(fields.next()?.parse().ok()?)?;
+    let tag = decode_line(fields.next()?)?;
+    let content = decode_line(fields.next()?)?;
+    Some((timestamp, tag, content))
+}
+
+fn decode_line(raw: &str) -> Option<Line<'static>> {
+    let mut spans = raw.split(SPAN);
+    let mut line = Line::default().style(decode_style(spans.next()?)?);
+    for span in spans {
+        let (style, text) = span.split_once(FIELD)?;
+        line.push_span(Span::styled(unescape(text), decode_style(style)?));
+    }
+    Some(line)
+}
+
+fn decode_style(raw: &str) -> Option<Style> {
+    let mut fields = raw.split(';');
+    let color = |c: &str| -> Option<Option<Color>> {
+        if c.is_empty() {
+            Some(None)
+        } else {
+            c.parse().ok().map(Some)
+        }
+    };
+
+    Some(Style {
+        fg: color(fields.next()?)?,
+        bg: color(fields.next()?)?,
+        add_modifier: Modifier::from_bits_truncate(fields.next()?.parse().ok()?),
+        sub_modifier: Modifier::from_bits_truncate(fields.next()?.parse().ok()?),
+        ..Style::default()
+    })
+}
+
+/// Escape the characters the record format uses as delimiters.
+fn escape(text: &str, out: &mut String) {
+    for c in text.chars() {
+        match c {
+            '\\' => out.push_str("\\\\"),
+            '\t' => out.push_str("\\t"),
+            '\n' => out.push_str("\\n"),
+            '\r' => out.push_str("\\r"),
+            FIELD => out.push_str("\\f"),
+            SPAN => out.push_str("\\s"),
+            c => out.push(c),
+        }
+    }
+}
+
+fn unescape(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        if c != '\\' {
+            out.push(c);
+            continue;
+        }
+        match chars.next() {
+            Some('t') => out.push('\t'),
+            Some('n') => out.push('\n'),
+            Some('r') => out.push('\r'),
+            Some('f') => out.push(FIELD),
+            Some('s') => out.push(SPAN),
+            Some(c) => out.push(c),
+            None => {}
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::Spill;
+    use chrono::{TimeDelta, Utc};
+    use ratatui::text::Line;
+
+    #[test]
+    fn keeps_lines_within_retention_between_sessions() {
+        let path = std::env::temp_dir().join(format!("eesh-spill-{}.spill", std::process::id()));
+        let line = |text: &str| Line::from(text.to_owned());
+
+        let mut spill = Spill::open(&path, TimeDelta::days(7)).unwrap();
+        let old = Utc::now() - TimeDelta::days(8);
+        spill.push(&old, &line("a"), &line("old")).unwrap();
+        spill.push(&Utc::now(), &line("b"), &line("new")).unwrap();
+
+        // The file is locked while a session has it open.
+        assert!(Spill::open(&path, TimeDelta::days(7)).is_err());
+        drop(spill);
+
+        let mut spill = Spill::open(&path, TimeDelta::days(7)).unwrap();
+        assert_eq!(spill.len(), 1);
+        let lines = spill.read(0..1).unwrap();
+        assert_eq!(lines[0].2, line("new"));
+
+        drop(spill);
+        std::fs::remove_file(path).unwrap();
+    }
+}
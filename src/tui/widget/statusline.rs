@@ -0,0 +1,215 @@
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    text::{Line, Span},
+    widgets::{Block, Borders, Paragraph, Widget},
+};
+use serde::Deserialize;
+
+use super::{ContextualWidget, LogBuffer, RenderContext};
+
+/// A piece of information shown in the status line.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum StatusSegment {
+    /// Name of the buffer in focus.
+    Buffer,
+    Topic,
+    ChannelModes,
+    /// How many users are in the channel.
+    Users,
+    /// Our nick and user modes on the buffer's network.
+    Nick,
+    Lag,
+    /// Other buffers with unseen mentions, and how many.
+    Mentions,
+    /// "-- MORE (n) --" while scrolled back.
+    More,
+}
+
+impl StatusSegment {
+    /// The topic is left out, since the topic bar shows it.
+    pub fn defaults() -> Vec<StatusSegment> {
+        vec![
+            StatusSegment::Buffer,
+            StatusSegment::Nick,
+            StatusSegment::ChannelModes,
+            StatusSegment::Users,
+            StatusSegment::Lag,
+            StatusSegment::Mentions,
+            StatusSegment::More,
+        ]
+    }
+}
+
+/// A single row describing the buffer in focus. Segments
+/// with nothing to show are left out entirely.
+pub struct StatusLine;
+
+impl StatusLine {
+    fn segment(
+        segment: StatusSegment,
+        lb: &LogBuffer,
+        ctx: &RenderContext,
+    ) -> Option<Line<'static>> {
+        let channel = lb.channel();
+
+        let text = match segment {
+            StatusSegment::Buffer => match lb.target() {
+                Some(target) => format!("{}/{}", target.network, target.name),
+                None => String::from("status"),
+            },
+            StatusSegment::Topic => {
+                return channel.topic.as_deref().map(|t| ctx.formatter.parse(t))
+            }
+            StatusSegment::ChannelModes if !channel.modes.is_empty() => channel.modes.clone(),
+            StatusSegment::Users => format!("{} users", channel.users?),
+            StatusSegment::Nick => {
+                let network = ctx.network.as_ref()?;
+                if network.modes.is_empty() {
+                    network.nick.clone()
+                } else {
+                    format!("{}({})", network.nick, network.modes)
+                }
+            }
+            StatusSegment::Lag => {
+                format!("Lag: {:.2}s", ctx.network.as_ref()?.lag?.as_secs_f32())
+            }
+            StatusSegment::Mentions if !ctx.mentions.is_empty() => {
+                let buffers: Vec<_> = ctx
+                    .mentions
+                    .iter()
+                    .map(|(name, count)| format!("{name}({count})"))
+                    .collect();
+                return Some(Line::styled(
+                    format!("Mentions: {}", buffers.join(" ")),
+                    ctx.theme.highlight,
+                ));
+            }
+            StatusSegment::More if lb.scroll() > 0 => format!("-- MORE ({}) --", lb.scroll()),
+            _ => return None,
+        };
+        Some(Line::from(text))
+    }
+}
+
+impl ContextualWidget for StatusLine {
+    fn render_ref(&self, ctx: &RenderContext, area: Rect, buf: &mut Buffer) {
+        let mut line = Line::default();
+        if let Some(tb) = &ctx.text_buffer {
+            let lb = tb.lock().expect("Screenbuffer mutex was poisoned!");
+            for segment in &ctx.status_line {
+                let Some(text) = Self::segment(*segment, &lb, ctx) else {
+                    continue;
+                };
+
+                if !line.spans.is_empty() {
+                    line.push_span(" ");
+                }
+                line.push_span(Span::raw("["));
+                line.spans.extend(text.spans);
+                line.push_span(Span::raw("]"));
+            }
+        }
+
+        Paragraph::new(line)
+            .style(ctx.theme.status)
+            .block(
+                Block::new()
+                    .borders(Borders::LEFT | Borders::RIGHT)
+                    .border_style(ctx.theme.border),
+            )
+            .render(area, buf);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{StatusLine, StatusSegment};
+    use crate::{
+        client::{NetworkStatus, Target},
+        tui::{
+            widget::{plain, LogBuffer},
+            RenderContext,
+        },
+    };
+    use chrono::Utc;
+    use ratatui::text::Line;
+    use std::time::Duration;
+
+    fn channel() -> LogBuffer {
+        LogBuffer::new(100, chrono_tz::UTC).with_target(Target {
+            network: String::from("libera"),
+            name: String::from("#rust"),
+        })
+    }
+
+    fn segment(segment: StatusSegment, lb: &LogBuffer, ctx: &RenderContext) -> Option<String> {
+        StatusLine::segment(segment, lb, ctx).map(|line| plain(&line))
+    }
+
+    #[test]
+    fn empty_segments_are_left_out() {
+        let (lb, ctx) = (channel(), RenderContext::default());
+        for empty in [
+            StatusSegment::Topic,
+            StatusSegment::ChannelModes,
+            StatusSegment::Users,
+            StatusSegment::Nick,
+            StatusSegment::Lag,
+            StatusSegment::Mentions,
+            StatusSegment::More,
+        ] {
+            assert_eq!(segment(empty, &lb, &ctx), None, "{empty:?}");
+        }
+
+        let status = LogBuffer::new(100, chrono_tz::UTC);
+        assert_eq!(
+            segment(StatusSegment::Buffer, &status, &ctx).as_deref(),
+            Some("status")
+        );
+    }
+
+    #[test]
+    fn segments_describe_the_buffer_and_network() {
+        let mut lb = channel();
+        let state = lb.channel_mut();
+        state.topic = Some(String::from("\x02Rust\x02 things"));
+        state.modes = String::from("+nt");
+        state.users = Some(42);
+        let ctx = RenderContext {
+            network: Some(NetworkStatus {
+                nick: String::from("eesh"),
+                modes: String::from("+iw"),
+                lag: Some(Duration::from_millis(1250)),
+                ..NetworkStatus::default()
+            }),
+            mentions: vec![(String::from("#a"), 2), (String::from("bob"), 1)],
+            ..RenderContext::default()
+        };
+
+        let shown = |s| segment(s, &lb, &ctx).unwrap();
+        assert_eq!(shown(StatusSegment::Buffer), "libera/#rust");
+        // Formatting codes are rendered, not shown.
+        assert_eq!(shown(StatusSegment::Topic), "Rust things");
+        assert_eq!(shown(StatusSegment::ChannelModes), "+nt");
+        assert_eq!(shown(StatusSegment::Users), "42 users");
+        assert_eq!(shown(StatusSegment::Nick), "eesh(+iw)");
+        assert_eq!(shown(StatusSegment::Lag), "Lag: 1.25s");
+        assert_eq!(shown(StatusSegment::Mentions), "Mentions: #a(2) bob(1)");
+    }
+
+    #[test]
+    fn more_shows_how_far_back_the_buffer_is_scrolled() {
+        let mut lb = channel();
+        lb.set_follow(false);
+        // Lines arriving while not following push the view up.
+        for i in 0..3 {
+            lb.push_line(Utc::now(), Line::from("nick"), Line::from(i.to_string()));
+        }
+        assert_eq!(
+            segment(StatusSegment::More, &lb, &RenderContext::default()).as_deref(),
+            Some("-- MORE (3) --")
+        );
+    }
+}
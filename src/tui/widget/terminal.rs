@@ -1,12 +1,15 @@
 use ratatui::{
     buffer::Buffer,
     layout::{Constraint, Layout, Rect},
-    text::{Line, Text},
+    text::Text,
     widgets::{Block, Borders, Paragraph, Widget},
 };
 
 use super::ContextualWidget;
+use super::FilterBar;
 use super::RenderContext;
+use super::StatusLine;
+use super::TopicBar;
 
 pub struct Terminal;
 
@@ -16,23 +19,53 @@ impl Terminal {
     }
 }
 
+impl Default for Terminal {
+    fn default() -> Self {
+        Terminal::new()
+    }
+}
+
 impl ContextualWidget for Terminal {
     fn render_ref(&self, ctx: &RenderContext, area: Rect, buf: &mut Buffer)
     where
         Self: Sized,
     {
-        let layout = Layout::vertical(vec![Constraint::Fill(1), Constraint::Length(2)]).split(area);
+        let (topic_height, filter_height) = ctx.text_buffer.as_ref().map_or((0, 0), |tb| {
+            let lb = tb.lock().expect("Screenbuffer mutex was poisoned!");
+            // Leave room for at least a few lines of the log.
+            let topic = TopicBar::height(&lb, ctx, area.width).min(area.height / 3);
+            (topic, FilterBar::height(&lb))
+        });
+
+        let layout = Layout::vertical(vec![
+            Constraint::Length(topic_height),
+            Constraint::Length(filter_height),
+            Constraint::Fill(1),
+            Constraint::Length(1),
+            Constraint::Length(2),
+        ])
+        .split(area);
+
+        TopicBar.with_context(ctx).render(layout[0], buf);
+        FilterBar.with_context(ctx).render(layout[1], buf);
 
         if let Some(tb) = &ctx.text_buffer {
             tb.lock()
                 .expect("Screenbuffer mutex was poisoned!")
                 .with_context(ctx)
-                .render(layout[0], buf);
+                .render(layout[2], buf);
         }
 
-        Paragraph::new(Text::from(vec![Line::from(ctx.user_line.as_str())]))
+        StatusLine.with_context(ctx).render(layout[3], buf);
+
+        Paragraph::new(Text::from(vec![ctx.formatter.preview(&ctx.user_line)]))
+            .style(ctx.theme.input)
             .left_aligned()
-            .block(Block::new().borders(Borders::ALL ^ Borders::TOP))
-            .render(layout[1], buf);
+            .block(
+                Block::new()
+                    .borders(Borders::ALL ^ Borders::TOP)
+                    .border_style(ctx.theme.border),
+            )
+            .render(layout[4], buf);
     }
 }
@@ -0,0 +1,180 @@
+use chrono::TimeZone;
+use ratatui::{
+    buffer::Buffer,
+    layout::Rect,
+    text::{Line, Text},
+    widgets::{Block, Borders, Paragraph, Widget},
+};
+
+use super::{wrap, ContextualWidget, LogBuffer, RenderContext};
+
+/// How the topic bar is currently shown.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct TopicView {
+    /// Show the whole topic, wrapped, along with who set it.
+    pub expanded: bool,
+
+    /// Columns scrolled past while collapsed to a single line.
+    pub scroll: u16,
+}
+
+/// The topic of the channel in focus, drawn above its log.
+pub struct TopicBar;
+
+impl TopicBar {
+    /// Rows the topic bar needs, including its top border.
+    /// Buffers without a topic get no topic bar at all.
+    pub fn height(lb: &LogBuffer, ctx: &RenderContext, width: u16) -> u16 {
+        let Some(topic) = &lb.channel().topic else {
+            return 0;
+        };
+        if !ctx.topic_view.expanded {
+            return 2;
+        }
+
+        let rows = Self::expanded(lb, topic, ctx, width).len();
+        (rows + 1).try_into().unwrap_or(u16::MAX)
+    }
+
+    /// The whole topic and who set it, wrapped to fit
+    /// between the borders of a bar `width` wide.
+    fn expanded(
+        lb: &LogBuffer,
+        topic: &str,
+        ctx: &RenderContext,
+        width: u16,
+    ) -> Vec<Line<'static>> {
+        let inner_width = usize::from(width.saturating_sub(2)).max(1);
+        let mut lines = wrap::wrap(&ctx.formatter.parse(topic), inner_width, 0);
+        if let Some(set_by) = Self::set_by(lb) {
+            let set_by = Line::styled(set_by, ctx.theme.timestamp);
+            lines.extend(wrap::wrap(&set_by, inner_width, 0));
+        }
+        lines
+    }
+
+    /// "Set by nick on date", if the server told us.
+    fn set_by(lb: &LogBuffer) -> Option<String> {
+        let channel = lb.channel();
+        let by = channel.topic_set_by.as_ref()?;
+        Some(match channel.topic_set_at {
+            Some(at) => format!(
+                "Set by {by} on {}",
+                lb.tz()
+                    .from_utc_datetime(&at.naive_utc())
+                    .format("%Y-%m-%d %H:%M")
+            ),
+            None => format!("Set by {by}"),
+        })
+    }
+}
+
+impl ContextualWidget for TopicBar {
+    fn render_ref(&self, ctx: &RenderContext, area: Rect, buf: &mut Buffer) {
+        let Some(tb) = &ctx.text_buffer else {
+            return;
+        };
+        let lb = tb.lock().expect("Screenbuffer mutex was poisoned!");
+        let Some(topic) = &lb.channel().topic else {
+            return;
+        };
+
+        let block = Block::new()
+            .borders(Borders::ALL ^ Borders::BOTTOM)
+            .border_style(ctx.theme.border);
+
+        if ctx.topic_view.expanded {
+            // Wrapped the same way `height` measured it.
+            Paragraph::new(Text::from(Self::expanded(&lb, topic, ctx, area.width)))
+                .style(ctx.theme.topic)
+                .block(block)
+                .render(area, buf);
+        } else {
+            let topic = ctx.formatter.parse(topic);
+            // Don't let the topic scroll out of view entirely.
+            let overflow = topic
+                .width()
+                .saturating_sub(usize::from(area.width.saturating_sub(2)));
+            let scroll = ctx
+                .topic_view
+                .scroll
+                .min(overflow.try_into().unwrap_or(u16::MAX));
+
+            Paragraph::new(topic)
+                .style(ctx.theme.topic)
+                .scroll((0, scroll))
+                .block(block)
+                .render(area, buf);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{TopicBar, TopicView};
+    use crate::tui::{widget::LogBuffer, ContextualWidget, RenderContext};
+    use ratatui::{buffer::Buffer, layout::Rect, widgets::Widget};
+    use std::sync::{Arc, Mutex};
+
+    fn context(topic: Option<&str>, set_by: Option<&str>, expanded: bool) -> RenderContext {
+        let mut lb = LogBuffer::new(10, chrono_tz::UTC);
+        let channel = lb.channel_mut();
+        channel.topic = topic.map(str::to_owned);
+        channel.topic_set_by = set_by.map(str::to_owned);
+        RenderContext {
+            topic_view: TopicView {
+                expanded,
+                scroll: 0,
+            },
+            text_buffer: Some(Arc::new(Mutex::new(lb))),
+            ..RenderContext::default()
+        }
+    }
+
+    fn height(ctx: &RenderContext, width: u16) -> u16 {
+        let lb = ctx.text_buffer.as_ref().unwrap().lock().unwrap();
+        TopicBar::height(&lb, ctx, width)
+    }
+
+    /// Each row of the bar as drawn in an area exactly `height` tall.
+    fn rows(ctx: &RenderContext, width: u16) -> Vec<String> {
+        let area = Rect::new(0, 0, width, height(ctx, width));
+        let mut buf = Buffer::empty(area);
+        TopicBar.with_context(ctx).render(area, &mut buf);
+        (0..area.height)
+            .map(|y| (0..width).map(|x| buf.get(x, y).symbol()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn only_buffers_with_a_topic_get_a_bar() {
+        assert_eq!(height(&context(None, None, true), 20), 0);
+        assert_eq!(height(&context(Some("a long topic"), None, false), 5), 2);
+    }
+
+    #[test]
+    fn expanded_height_fits_the_wrapped_topic() {
+        let ctx = context(Some("one two three"), Some("bob"), true);
+        assert_eq!(
+            rows(&ctx, 9),
+            [
+                "┌───────┐",
+                "│one two│",
+                "│three  │",
+                "│Set by │",
+                "│bob    │",
+            ]
+        );
+
+        // Wide enough for everything on one line each.
+        assert_eq!(height(&ctx, 20), 3);
+    }
+
+    #[test]
+    fn long_words_are_broken_to_fit() {
+        let ctx = context(Some("abcdefghij"), None, true);
+        let rows = rows(&ctx, 6);
+        assert_eq!(rows.len(), 4);
+        assert_eq!(rows[3], "│ij  │");
+    }
+}
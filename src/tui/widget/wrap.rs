@@ -0,0 +1,223 @@
+use ratatui::{
+    style::Style,
+    text::{Line, Span},
+};
+use unicode_segmentation::UnicodeSegmentation;
+use unicode_width::UnicodeWidthStr;
+
+/// Break `line` into lines no wider than `width` cells, at word
+/// boundaries where possible. Every line after the first is
+/// indented by `indent` cells. Words too long for a line of their
+/// own are broken between graphemes, and a grapheme is never split;
+/// one wider than the whole line gets a line to itself.
+pub fn wrap(line: &Line<'static>, width: usize, indent: usize) -> Vec<Line<'static>> {
+    if width == 0 {
+        return vec![line.clone()];
+    }
+    // An indent that leaves no room for text is no indent at all.
+    let indent = if indent < width { indent } else { 0 };
+
+    let mut wrapper = Wrapper {
+        lines: Vec::new(),
+        current: Line::default().style(line.style),
+        used: 0,
+        width,
+        indent,
+        style: line.style,
+    };
+
+    for span in &line.spans {
+        for word in span.content.split_word_bounds() {
+            wrapper.push_word(word, span.style);
+        }
+    }
+
+    wrapper.finish()
+}
+
+/// How many lines `wrap` would break `line` into.
+pub fn height(line: &Line<'static>, width: usize, indent: usize) -> usize {
+    wrap(line, width, indent).len()
+}
+
+struct Wrapper {
+    lines: Vec<Line<'static>>,
+    current: Line<'static>,
+    /// Cells of `current` already filled.
+    used: usize,
+    width: usize,
+    indent: usize,
+    style: Style,
+}
+
+impl Wrapper {
+    /// Cells a line can hold, given whether it's the first.
+    fn capacity(&self, first: bool) -> usize {
+        if first {
+            self.width
+        } else {
+            self.width - self.indent
+        }
+    }
+
+    fn is_first(&self) -> bool {
+        self.lines.is_empty()
+    }
+
+    fn room(&self) -> usize {
+        self.capacity(self.is_first()).saturating_sub(self.used)
+    }
+
+    fn push_word(&mut self, word: &str, style: Style) {
+        let word_width = word.width();
+        if word_width <= self.room() {
+            self.push(word, word_width, style);
+            return;
+        }
+
+        // Whitespace at a break is swallowed rather than
+        // starting the next line with a gap.
+        if word.trim().is_empty() {
+            if self.used > 0 {
+                self.break_line();
+            }
+            return;
+        }
+
+        // Move the word to a line of its own if it fits there.
+        if self.used > 0 && word_width <= self.capacity(false) {
+            self.break_line();
+            self.push(word, word_width, style);
+            return;
+        }
+
+        for grapheme in word.graphemes(true) {
+            let grapheme_width = grapheme.width();
+            if grapheme_width > self.room() && self.used > 0 {
+                self.break_line();
+            }
+            self.push(grapheme, grapheme_width, style);
+        }
+    }
+
+    fn push(&mut self, text: &str, text_width: usize, style: Style) {
+        match self.current.spans.last_mut() {
+            Some(last) if last.style == style && self.used > 0 => {
+                last.content.to_mut().push_str(text);
+            }
+            _ => self.current.push_span(Span::styled(text.to_owned(), style)),
+        }
+        self.used += text_width;
+    }
+
+    fn break_line(&mut self) {
+        let next = Line::default().style(self.style);
+        self.lines.push(std::mem::replace(&mut self.current, next));
+        self.used = 0;
+
+        if self.indent > 0 {
+            self.current.push_span(Span::raw(" ".repeat(self.indent)));
+        }
+    }
+
+    fn finish(mut self) -> Vec<Line<'static>> {
+        self.lines.push(self.current);
+        self.lines
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use ratatui::style::Stylize;
+
+    fn text(lines: &[Line<'static>]) -> Vec<String> {
+        lines
+            .iter()
+            .map(|l| l.spans.iter().map(|s| s.content.as_ref()).collect())
+            .collect()
+    }
+
+    #[test]
+    fn short_lines_are_left_alone() {
+        let lines = wrap(&Line::from("hello"), 10, 2);
+        assert_eq!(text(&lines), ["hello"]);
+    }
+
+    #[test]
+    fn empty_lines_take_one_row() {
+        assert_eq!(height(&Line::default(), 10, 2), 1);
+    }
+
+    #[test]
+    fn exact_multiples_of_the_width_are_not_overcounted() {
+        assert_eq!(height(&Line::from("abcdefghij"), 10, 0), 1);
+        assert_eq!(height(&Line::from("abcdefghijabcdefghij"), 10, 0), 2);
+    }
+
+    #[test]
+    fn wraps_at_word_boundaries() {
+        let lines = wrap(&Line::from("the quick brown fox jumps"), 10, 0);
+        assert_eq!(text(&lines), ["the quick ", "brown fox ", "jumps"]);
+    }
+
+    #[test]
+    fn continuation_lines_get_a_hanging_indent() {
+        let lines = wrap(&Line::from("the quick brown fox jumps"), 10, 2);
+        assert_eq!(
+            text(&lines),
+            ["the quick ", "  brown ", "  fox ", "  jumps"]
+        );
+        assert!(lines.iter().all(|l| l.width() <= 10));
+    }
+
+    #[test]
+    fn long_words_are_broken_between_graphemes() {
+        let lines = wrap(&Line::from("see https://example.com/abc"), 10, 0);
+        assert_eq!(text(&lines), ["see https:", "//example.", "com/abc"]);
+    }
+
+    #[test]
+    fn wide_cjk_characters_are_never_split() {
+        // Each character is two cells wide, so only two fit in five cells.
+        let lines = wrap(&Line::from("日本語のテキスト"), 5, 0);
+        assert_eq!(text(&lines), ["日本", "語の", "テキ", "スト"]);
+        assert!(lines.iter().all(|l| l.width() <= 5));
+    }
+
+    #[test]
+    fn cjk_text_fills_even_widths_exactly() {
+        assert_eq!(height(&Line::from("日本語のテキスト"), 4, 0), 4);
+        assert_eq!(height(&Line::from("日本語のテキスト"), 16, 0), 1);
+    }
+
+    #[test]
+    fn emoji_sequences_stay_whole() {
+        // A family emoji joined by ZWJs is a single grapheme.
+        let family = "👨\u{200d}👩\u{200d}👧";
+        let raw = format!("{family}{family}{family}");
+        let lines = wrap(&Line::from(raw), 3, 0);
+        assert_eq!(lines.len(), 3);
+        assert!(text(&lines).iter().all(|l| l == family));
+    }
+
+    #[test]
+    fn graphemes_wider_than_the_line_get_their_own() {
+        let lines = wrap(&Line::from("a日b"), 1, 0);
+        assert_eq!(text(&lines), ["a", "日", "b"]);
+    }
+
+    #[test]
+    fn styles_carry_across_breaks() {
+        let line = Line::from(vec!["plain ".into(), "bold words here".bold()]);
+        let lines = wrap(&line, 10, 0);
+        assert_eq!(text(&lines), ["plain bold", "words here"]);
+        assert_eq!(lines[0].spans[1].style, Style::new().bold());
+        assert_eq!(lines[1].spans[0].style, Style::new().bold());
+    }
+
+    #[test]
+    fn zero_width_leaves_the_line_alone() {
+        assert_eq!(height(&Line::from("anything"), 0, 0), 1);
+    }
+}
@@ -0,0 +1,82 @@
+//! Drives the app without a terminal, the way
+//! integration tests and plugin crates can.
+
+use color_eyre::Result;
+use eesh::{
+    plugin::{Plugin, Requests},
+    App, Config,
+};
+use ratatui::crossterm::event::{KeyCode, KeyEvent};
+use std::sync::{Arc, Mutex};
+
+const CONFIG: &str = r#"
+[alias]
+[ui]
+[clients]
+"#;
+
+type Commands = Arc<Mutex<Vec<(String, Vec<String>)>>>;
+
+/// Records every `record` command offered to it.
+struct Recorder {
+    commands: Commands,
+}
+
+impl Plugin for Recorder {
+    fn name(&self) -> &str {
+        "recorder"
+    }
+
+    fn load(&mut self, _cfg: &Config, _requests: Requests) -> Result<()> {
+        Ok(())
+    }
+
+    fn command(&mut self, name: &str, args: &[String]) -> Option<Result<()>> {
+        if name != "record" {
+            return None;
+        }
+        self.commands
+            .lock()
+            .unwrap()
+            .push((name.to_owned(), args.to_vec()));
+        Some(Ok(()))
+    }
+}
+
+fn type_keys(app: &mut App, text: &str) {
+    for c in text.chars() {
+        app.push_key(KeyEvent::from(KeyCode::Char(c)));
+    }
+}
+
+#[test]
+fn typed_commands_reach_plugins_and_quit() {
+    let commands = Commands::default();
+    let mut app = App::new(Config::parse_str(CONFIG).unwrap())
+        .unwrap()
+        .with_plugin(Recorder {
+            commands: Arc::clone(&commands),
+        });
+    app.load_plugins();
+
+    // Nothing happens until the line is submitted.
+    type_keys(&mut app, ",record a b");
+    app.update().unwrap();
+    assert!(commands.lock().unwrap().is_empty());
+
+    app.push_key(KeyEvent::from(KeyCode::Enter));
+    app.update().unwrap();
+    assert_eq!(
+        *commands.lock().unwrap(),
+        [(
+            String::from("record"),
+            vec![String::from("a"), String::from("b")]
+        )]
+    );
+    assert!(!app.is_exiting());
+
+    type_keys(&mut app, ",q");
+    app.push_key(KeyEvent::from(KeyCode::Enter));
+    app.update().unwrap();
+    assert!(app.is_exiting());
+}